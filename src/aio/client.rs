@@ -0,0 +1,554 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use bb8::{Pool, PooledConnection};
+
+use super::connection::ConnectionManager;
+use crate::client::{
+    caller_key, caller_keys, decode_update, default_hash_function, server_index, update_backoff, Stats, Update,
+};
+use crate::error::{ClientError, CommandError, MemcacheError};
+use crate::ketama::Ketama;
+use crate::key_policy::KeyPolicy;
+use crate::protocol::{Item, MetaGetOptions, MetaValue, StoreOptions};
+use crate::value::{Borrowed, FromMemcacheValueExt, ToMemcacheValue};
+
+/// Asynchronous counterpart of [`memcache::Client`](crate::Client), routing keys over one pool per server the
+/// same way.
+#[derive(Clone)]
+pub struct Client {
+    pools: Vec<Pool<ConnectionManager>>,
+    ketama: Option<Arc<Ketama>>,
+    pub hash_function: fn(&[u8]) -> u64,
+    namespace: Arc<str>,
+    key_policy: Arc<KeyPolicy>,
+}
+
+impl Client {
+    pub fn with_pool(pool: Pool<ConnectionManager>) -> Self {
+        Self {
+            pools: vec![pool],
+            ketama: None,
+            hash_function: default_hash_function,
+            namespace: Arc::from(""),
+            key_policy: Arc::new(KeyPolicy::Strict),
+        }
+    }
+
+    /// Create a client over several memcached servers, one pool per server. Keys are routed to a server with
+    /// `hash_function`, which can be replaced after construction.
+    pub fn with_pools(pools: Vec<Pool<ConnectionManager>>) -> Result<Self, MemcacheError> {
+        if pools.is_empty() {
+            Err(ClientError::Error("at least one pool is required".into()))?
+        }
+        Ok(Self {
+            pools,
+            ketama: None,
+            hash_function: default_hash_function,
+            namespace: Arc::from(""),
+            key_policy: Arc::new(KeyPolicy::Strict),
+        })
+    }
+
+    /// Create a client over several memcached servers, routing keys with a ketama consistent hashing ring.
+    /// The pools must be in the same order as the servers the ring was built from.
+    pub fn with_ketama(pools: Vec<Pool<ConnectionManager>>, ring: Ketama) -> Result<Self, MemcacheError> {
+        if pools.len() != ring.len() {
+            Err(ClientError::Error(
+                "ketama ring and pools have different server counts".into(),
+            ))?
+        }
+        let mut client = Self::with_pools(pools)?;
+        client.ketama = Some(Arc::new(ring));
+        Ok(client)
+    }
+
+    /// Create a client with a default connection pool for each of the given server urls.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let client = memcache::aio::Client::connect(&["memcache://localhost:12345"]).await.unwrap();
+    /// client.version().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn connect<S: AsRef<str>>(urls: &[S]) -> Result<Self, MemcacheError> {
+        let mut pools = Vec::with_capacity(urls.len());
+        for url in urls {
+            pools.push(Pool::builder().build(ConnectionManager::new(url)?).await?);
+        }
+        Self::with_pools(pools)
+    }
+
+    /// Get pool connection for the server which owns the key
+    pub async fn get_connection(
+        &self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<PooledConnection<'_, ConnectionManager>, MemcacheError> {
+        Ok(self.pools[self.server_index(key.as_ref())].get().await?)
+    }
+
+    /// Get ConnectionManager pool for the server which owns the key
+    pub fn get_pool(&self, key: impl AsRef<[u8]>) -> Pool<ConnectionManager> {
+        self.pools[self.server_index(key.as_ref())].clone()
+    }
+
+    /// Get ConnectionManager pools of all servers
+    pub fn get_pools(&self) -> &[Pool<ConnectionManager>] {
+        &self.pools
+    }
+
+    /// Get a handle on the same servers which prefixes every key with `namespace`, like
+    /// [`memcache::Client::with_namespace`](crate::Client::with_namespace).
+    pub fn with_namespace(&self, namespace: &str) -> Self {
+        Self {
+            namespace: Arc::from(format!("{}{}", self.namespace, namespace)),
+            ..self.clone()
+        }
+    }
+
+    /// The prefix added to every key, empty by default.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Get a handle on the same servers which applies `policy` to the keys they can't take as is, like
+    /// [`memcache::Client::with_key_policy`](crate::Client::with_key_policy).
+    pub fn with_key_policy(&self, policy: KeyPolicy) -> Self {
+        Self {
+            key_policy: Arc::new(policy),
+            ..self.clone()
+        }
+    }
+
+    /// The policy applied to the keys the servers can't take as is.
+    pub fn key_policy(&self) -> &KeyPolicy {
+        &self.key_policy
+    }
+
+    fn key<'a>(&self, key: &'a [u8]) -> Result<Cow<'a, [u8]>, MemcacheError> {
+        self.key_policy.apply(&self.namespace, key)
+    }
+
+    fn keys<'a, K: AsRef<[u8]>>(&self, keys: &'a [K]) -> Result<Vec<Cow<'a, [u8]>>, MemcacheError> {
+        keys.iter().map(|key| self.key(key.as_ref())).collect()
+    }
+
+    fn server_index(&self, key: &[u8]) -> usize {
+        server_index(self.pools.len(), self.ketama.as_deref(), self.hash_function, key)
+    }
+
+    /// Get a connection to the server which owns a key of any bytes.
+    async fn connection(&self, key: &[u8]) -> Result<PooledConnection<'_, ConnectionManager>, MemcacheError> {
+        self.get_connection(key).await
+    }
+
+    fn distribute_keys<'a, K: AsRef<[u8]>>(&self, keys: &'a [K]) -> HashMap<usize, Vec<&'a [u8]>> {
+        let mut distribution: HashMap<usize, Vec<&'a [u8]>> = HashMap::new();
+        for key in keys {
+            let key = key.as_ref();
+            distribution.entry(self.server_index(key)).or_default().push(key);
+        }
+        distribution
+    }
+
+    /// Get the memcached server version of every server, paired with the server url.
+    pub async fn version(&self) -> Result<Vec<(String, String)>, MemcacheError> {
+        let mut result = Vec::with_capacity(self.pools.len());
+        for pool in &self.pools {
+            let mut connection = pool.get().await?;
+            let url = connection.url.to_string();
+            result.push((url, connection.version().await?));
+        }
+        Ok(result)
+    }
+
+    /// Flush all cache on memcached servers immediately.
+    pub async fn flush(&self) -> Result<(), MemcacheError> {
+        for pool in &self.pools {
+            pool.get().await?.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Flush all cache on memcached servers with a delay seconds.
+    pub async fn flush_with_delay(&self, delay: u32) -> Result<(), MemcacheError> {
+        for pool in &self.pools {
+            pool.get().await?.flush_with_delay(delay).await?;
+        }
+        Ok(())
+    }
+
+    /// Get a key from memcached server.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let client = memcache::aio::Client::connect(&["memcache://localhost:12345"]).await.unwrap();
+    /// let _: Option<String> = client.get("foo").await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get<V: FromMemcacheValueExt>(&self, key: impl AsRef<[u8]>) -> Result<Option<V>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.get(&key).await
+    }
+
+    /// Get multiple keys from memcached servers, with one request per server.
+    pub async fn gets<V, K>(&self, keys: &[K]) -> Result<HashMap<K, V>, MemcacheError>
+    where
+        V: FromMemcacheValueExt,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let sent = self.keys(keys)?;
+        let callers = caller_keys(keys, &sent);
+        let mut result = HashMap::with_capacity(sent.len());
+        for (index, keys) in self.distribute_keys(&sent) {
+            for (key, value) in self.pools[index].get().await?.gets(&keys).await? {
+                result.insert(caller_key(&callers, &key)?, value);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get a key along with the metadata of its item, like [`memcache::Client::get_item`](crate::Client::get_item).
+    pub async fn get_item<V: FromMemcacheValueExt>(
+        &self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<Item<V>>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        match self
+            .connection(&key)
+            .await?
+            .get_items(&[&key])
+            .await?
+            .remove(key.as_ref())
+        {
+            Some(item) => Ok(Some(item.decode()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get multiple keys along with the metadata of their items, with one request per server.
+    pub async fn get_items<V, K>(&self, keys: &[K]) -> Result<HashMap<K, Item<V>>, MemcacheError>
+    where
+        V: FromMemcacheValueExt,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let sent = self.keys(keys)?;
+        let callers = caller_keys(keys, &sent);
+        let mut result = HashMap::with_capacity(sent.len());
+        for (index, keys) in self.distribute_keys(&sent) {
+            for (key, item) in self.pools[index].get().await?.get_items(&keys).await? {
+                result.insert(caller_key(&callers, &key)?, item.decode()?);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get a key and update its expiration in a single round trip. The value is decoded with its cas token.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let client = memcache::aio::Client::connect(&["memcache://localhost:12345"]).await.unwrap();
+    /// let _: Option<String> = client.get_and_touch("foo", 600).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get_and_touch<V: FromMemcacheValueExt>(
+        &self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.get_and_touch(&key, expiration).await
+    }
+
+    /// Get multiple keys and update their expiration, with one request per server.
+    pub async fn get_and_touch_multi<V, K>(&self, keys: &[K], expiration: u32) -> Result<HashMap<K, V>, MemcacheError>
+    where
+        V: FromMemcacheValueExt,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let sent = self.keys(keys)?;
+        let callers = caller_keys(keys, &sent);
+        let mut result = HashMap::with_capacity(sent.len());
+        for (index, keys) in self.distribute_keys(&sent) {
+            let values = self.pools[index]
+                .get()
+                .await?
+                .get_and_touch_multi(&keys, expiration)
+                .await?;
+            for (key, value) in values {
+                result.insert(caller_key(&callers, &key)?, value);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get a key along with the item metadata selected by `options`. Only supported by the meta protocol.
+    pub async fn meta_get<V: FromMemcacheValueExt>(
+        &self,
+        key: impl AsRef<[u8]>,
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.meta_get(&key, options).await
+    }
+
+    /// Get the internal metadata memcached holds for a key, without fetching or bumping it. Only supported by
+    /// the meta protocol.
+    pub async fn meta_debug(&self, key: impl AsRef<[u8]>) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.meta_debug(&key).await
+    }
+
+    /// Set a key with associate value into memcached server with expiration seconds.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let client = memcache::aio::Client::connect(&["memcache://localhost:12345"]).await.unwrap();
+    /// client.set("foo", "bar", 10).await.unwrap();
+    /// # client.flush().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn set<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.set(&key, value, expiration).await
+    }
+
+    /// Compare and swap a key with the associate value into memcached server with expiration seconds.
+    /// `cas_id` should be obtained from a previous `gets` call.
+    pub async fn cas<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+        cas_id: u64,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.cas(&key, value, expiration, cas_id).await
+    }
+
+    /// Read-modify-write a key with optimistic locking, retrying on conflicts, like
+    /// [`memcache::Client::update`](crate::Client::update).
+    pub async fn update<V, F>(
+        &self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+        max_retries: u32,
+        mut f: F,
+    ) -> Result<Update<V>, MemcacheError>
+    where
+        V: FromMemcacheValueExt + ToMemcacheValue<Vec<u8>>,
+        F: FnMut(Option<V>) -> Option<V>,
+    {
+        let key = key.as_ref();
+        self.key(key)?;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (current, cas) = decode_update(self.gets(&[key]).await?.remove(key))?;
+            let value = match f(current) {
+                Some(value) => value,
+                None => return Ok(Update { value: None, attempts }),
+            };
+            let options = StoreOptions {
+                expiration,
+                cas,
+                ..Default::default()
+            };
+            let stored = match cas {
+                Some(_) => self.set_with(key, Borrowed(&value), &options).await?,
+                None => self.add_with(key, Borrowed(&value), &options).await?,
+            };
+            if stored {
+                return Ok(Update {
+                    value: Some(value),
+                    attempts,
+                });
+            }
+            if attempts > max_retries {
+                Err(CommandError::KeyExists)?
+            }
+            tokio::time::sleep(update_backoff(attempts)).await;
+        }
+    }
+
+    /// Add a key with associate value into memcached server with expiration seconds.
+    pub async fn add<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.add(&key, value, expiration).await
+    }
+
+    /// Replace a key with associate value into memcached server with expiration seconds.
+    pub async fn replace<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.replace(&key, value, expiration).await
+    }
+
+    /// Append value to the key.
+    pub async fn append<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.append(&key, value).await
+    }
+
+    /// Prepend value to the key.
+    pub async fn prepend<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.prepend(&key, value).await
+    }
+
+    /// Set a key with the expiration, client flags, noreply and cas token of `options`, returning whether the
+    /// value was stored, like [`Client::set_with`](crate::Client::set_with).
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// use memcache::StoreOptions;
+    ///
+    /// let client = memcache::aio::Client::connect(&["memcache://localhost:12345"]).await.unwrap();
+    /// let options = StoreOptions {
+    ///     expiration: 10,
+    ///     noreply: true,
+    ///     ..Default::default()
+    /// };
+    /// assert!(client.set_with("aio_set_with", "bar", &options).await.unwrap());
+    /// # client.flush().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn set_with<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.set_with(&key, value, options).await
+    }
+
+    /// Add a key with the options of `options`, returning `false` if the key already exists.
+    pub async fn add_with<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.add_with(&key, value, options).await
+    }
+
+    /// Replace a key with the options of `options`, returning `false` if the key doesn't exist.
+    pub async fn replace_with<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.replace_with(&key, value, options).await
+    }
+
+    /// Append value to the key with the noreply and cas token of `options`.
+    pub async fn append_with<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.append_with(&key, value, options).await
+    }
+
+    /// Prepend value to the key with the noreply and cas token of `options`.
+    pub async fn prepend_with<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.prepend_with(&key, value, options).await
+    }
+
+    /// Delete a key from memcached server.
+    pub async fn delete(&self, key: impl AsRef<[u8]>) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.delete(&key).await
+    }
+
+    /// Increment the value with amount.
+    pub async fn increment(&self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.increment(&key, amount).await
+    }
+
+    /// Decrement the value with amount.
+    pub async fn decrement(&self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.decrement(&key, amount).await
+    }
+
+    /// Increment the value with amount, or create it with the initial value and expiration when it is missing.
+    /// An expiration of `u32::MAX` is rejected with a `ClientError`.
+    pub async fn increment_or_init(
+        &self,
+        key: impl AsRef<[u8]>,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)
+            .await?
+            .increment_or_init(&key, amount, initial, expiration)
+            .await
+    }
+
+    /// Set a new expiration time for a exist key.
+    pub async fn touch(&self, key: impl AsRef<[u8]>, expiration: u32) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key).await?.touch(&key, expiration).await
+    }
+
+    /// Get all servers' statistics, paired with the server url.
+    pub async fn stats(&self) -> Result<Vec<(String, Stats)>, MemcacheError> {
+        let mut result = Vec::with_capacity(self.pools.len());
+        for pool in &self.pools {
+            let mut connection = pool.get().await?;
+            let url = connection.url.to_string();
+            result.push((url, connection.stats().await?));
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use url::Url;
+
+use super::protocol::{AsciiProtocol, BinaryProtocol, MetaProtocol, Protocol};
+use super::stream::Stream;
+use crate::client::Stats;
+use crate::compression::{Compression, Encoded};
+use crate::connection::{ProtocolKind, TcpOptions, Timeouts, Transport};
+use crate::error::{ClientError, MemcacheError};
+use crate::protocol::ascii::StoreCommand;
+use crate::protocol::{Item, MetaGetOptions, MetaValue, StoreOptions};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+
+/// An asynchronous connection to the memcached server
+pub struct Connection {
+    pub protocol: Protocol,
+    pub url: Arc<String>,
+    poisoned: bool,
+    timeout: Option<Duration>,
+    compression: Option<Compression>,
+}
+
+/// Run a command on the protocol, poisoning the connection unless the command completes cleanly. A future
+/// dropped half way through a response, like on a timeout, leaves the connection poisoned.
+macro_rules! track {
+    ($self:ident, $command:expr) => {{
+        let poisoned = $self.poisoned;
+        $self.poisoned = true;
+        let timeout = $self.timeout;
+        let command = async {
+            $self.protocol.settle().await?;
+            $command.await
+        };
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, command)
+                .await
+                .unwrap_or(Err(MemcacheError::Timeout)),
+            None => command.await,
+        };
+        $self.poisoned = poisoned || matches!(result, Err(ref e) if MemcacheError::is_fatal(e));
+        result
+    }};
+}
+
+impl DerefMut for Connection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.protocol
+    }
+}
+
+impl Deref for Connection {
+    type Target = Protocol;
+    fn deref(&self) -> &Self::Target {
+        &self.protocol
+    }
+}
+
+/// Memcache connection manager implementing bb8 Pool ManageConnection
+#[derive(Debug)]
+pub struct ConnectionManager {
+    url: Url,
+}
+
+impl ConnectionManager {
+    /// Initialize connection manager with given Url
+    pub fn new(target: impl AsRef<str>) -> Result<Self, MemcacheError> {
+        let url = Url::parse(target.as_ref())?;
+        Ok(Self { url })
+    }
+}
+
+impl bb8::ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = MemcacheError;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let url = &self.url;
+        let mut connection = Connection::connect(url).await?;
+        if url.has_authority() && !url.username().is_empty() && url.password().is_some() {
+            let username = url.username();
+            let password = url.password().unwrap();
+            connection.auth(username, password).await?;
+        }
+        Ok(connection)
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        conn.version().await.map(|_| ())
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.is_broken()
+    }
+}
+
+async fn tcp_stream(url: &Url, opts: &TcpOptions, timeouts: &Timeouts) -> Result<TcpStream, MemcacheError> {
+    let addrs = url.socket_addrs(|| None)?;
+    let tcp_stream = with_timeout(timeouts.connect, TcpStream::connect(&*addrs)).await?;
+    tcp_stream.set_nodelay(opts.nodelay)?;
+    Ok(tcp_stream)
+}
+
+async fn with_timeout<T, E, F>(timeout: Option<Duration>, future: F) -> Result<T, MemcacheError>
+where
+    F: std::future::Future<Output = Result<T, E>>,
+    MemcacheError: From<E>,
+{
+    match timeout {
+        Some(timeout) => Ok(tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| MemcacheError::Timeout)??),
+        None => Ok(future.await?),
+    }
+}
+
+impl Connection {
+    pub(crate) async fn connect(url: &Url) -> Result<Self, MemcacheError> {
+        let timeouts = Timeouts::from_url(url)?;
+        let stream: Stream = match Transport::from_url(url)? {
+            Transport::Tcp(options) => Stream::Tcp(tcp_stream(url, &options, &timeouts).await?),
+            Transport::Udp => Err(ClientError::Error(
+                "udp transport is not supported by the async client".into(),
+            ))?,
+            #[cfg(unix)]
+            Transport::Unix => Stream::Unix(with_timeout(timeouts.connect, UnixStream::connect(url.path())).await?),
+            #[cfg(all(feature = "tls", not(feature = "tokio-tls")))]
+            Transport::Tls(_) => Err(ClientError::Error(
+                "tls transport of the async client requires the tokio-tls feature".into(),
+            ))?,
+            #[cfg(feature = "tokio-tls")]
+            Transport::Tls(options) => {
+                use std::io;
+                use std::pin::Pin;
+
+                let host = url
+                    .host_str()
+                    .ok_or(MemcacheError::BadURL("host required for TLS connection".into()))?;
+                let ssl = options.connector()?.configure()?.into_ssl(host)?;
+                let tcp_stream = tcp_stream(url, &options.tcp_options, &timeouts).await?;
+                let mut tls_stream = tokio_openssl::SslStream::new(ssl, tcp_stream)?;
+                let handshake = Pin::new(&mut tls_stream).connect();
+                with_timeout(timeouts.connect, async { handshake.await.map_err(io::Error::other) }).await?;
+                Stream::Tls(tls_stream)
+            }
+        };
+
+        let protocol = match ProtocolKind::from_url(url) {
+            ProtocolKind::Ascii => Protocol::Ascii(AsciiProtocol::new(stream)),
+            ProtocolKind::Meta => Protocol::Meta(MetaProtocol::new(stream)),
+            ProtocolKind::Binary => Protocol::Binary(BinaryProtocol::new(stream)),
+        };
+
+        Ok(Connection {
+            url: Arc::new(url.to_string()),
+            protocol,
+            poisoned: false,
+            // the async client has no socket level timeouts, a command is bounded by the read timeout instead
+            timeout: timeouts.read,
+            compression: Compression::from_url(url)?,
+        })
+    }
+
+    /// Whether a command failed with an IO, parse or protocol error, or did not read its whole response,
+    /// leaving the connection unusable. The pool drops broken connections instead of handing them out again.
+    pub fn is_broken(&self) -> bool {
+        self.poisoned
+    }
+
+    // Commands go through the connection rather than the protocol it derefs to, to track its state.
+
+    pub async fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        track!(self, self.protocol.auth(username, password))
+    }
+
+    pub async fn version(&mut self) -> Result<String, MemcacheError> {
+        track!(self, self.protocol.version())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), MemcacheError> {
+        track!(self, self.protocol.flush())
+    }
+
+    pub async fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        track!(self, self.protocol.flush_with_delay(delay))
+    }
+
+    pub async fn get<V: FromMemcacheValueExt>(&mut self, key: impl AsRef<[u8]>) -> Result<Option<V>, MemcacheError> {
+        track!(self, self.protocol.get(key.as_ref()))
+    }
+
+    pub async fn gets<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        track!(self, self.protocol.gets(keys))
+    }
+
+    pub async fn get_items(
+        &mut self,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        track!(self, self.protocol.get_items(keys))
+    }
+
+    pub async fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        track!(self, self.protocol.get_and_touch(key.as_ref(), expiration))
+    }
+
+    pub async fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[impl AsRef<[u8]>],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        track!(self, self.protocol.get_and_touch_multi(keys, expiration))
+    }
+
+    pub async fn set<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => track!(self, self.protocol.set(key.as_ref(), value, expiration)),
+            None => track!(self, self.protocol.set(key.as_ref(), value, expiration)),
+        }
+    }
+
+    pub async fn cas<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => track!(self, self.protocol.cas(key.as_ref(), value, expiration, cas)),
+            None => track!(self, self.protocol.cas(key.as_ref(), value, expiration, cas)),
+        }
+    }
+
+    pub async fn add<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => track!(self, self.protocol.add(key.as_ref(), value, expiration)),
+            None => track!(self, self.protocol.add(key.as_ref(), value, expiration)),
+        }
+    }
+
+    pub async fn replace<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => track!(self, self.protocol.replace(key.as_ref(), value, expiration)),
+            None => track!(self, self.protocol.replace(key.as_ref(), value, expiration)),
+        }
+    }
+
+    pub async fn append<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        track!(self, self.protocol.append(key.as_ref(), value))
+    }
+
+    pub async fn prepend<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        track!(self, self.protocol.prepend(key.as_ref(), value))
+    }
+
+    pub async fn delete(&mut self, key: impl AsRef<[u8]>) -> Result<bool, MemcacheError> {
+        track!(self, self.protocol.delete(key.as_ref()))
+    }
+
+    pub async fn increment(&mut self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        track!(self, self.protocol.increment(key.as_ref(), amount))
+    }
+
+    pub async fn decrement(&mut self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        track!(self, self.protocol.decrement(key.as_ref(), amount))
+    }
+
+    pub async fn increment_or_init(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        track!(
+            self,
+            self.protocol
+                .increment_or_init(key.as_ref(), amount, initial, expiration)
+        )
+    }
+
+    pub async fn touch(&mut self, key: impl AsRef<[u8]>, expiration: u32) -> Result<bool, MemcacheError> {
+        track!(self, self.protocol.touch(key.as_ref(), expiration))
+    }
+
+    pub async fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        track!(self, self.protocol.stats())
+    }
+
+    /// Set a key with the expiration, flags, noreply and cas of `options`, returning whether it was stored.
+    pub async fn set_with<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Set, key.as_ref(), value, options).await
+    }
+
+    /// Add a key with the options of `options`, returning whether it was stored.
+    pub async fn add_with<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Add, key.as_ref(), value, options).await
+    }
+
+    /// Replace a key with the options of `options`, returning whether it was stored.
+    pub async fn replace_with<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Replace, key.as_ref(), value, options).await
+    }
+
+    /// Append to a key with the noreply and cas of `options`, returning whether it was stored.
+    pub async fn append_with<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Append, key.as_ref(), value, options).await
+    }
+
+    /// Prepend to a key with the noreply and cas of `options`, returning whether it was stored.
+    pub async fn prepend_with<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Prepend, key.as_ref(), value, options).await
+    }
+
+    async fn store<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let encoded = match command {
+            StoreCommand::Append | StoreCommand::Prepend => None,
+            _ => self.encode(&value, options.flags)?,
+        };
+        match encoded {
+            Some(value) => {
+                // the flags override is already part of the encoded flags
+                let options = StoreOptions {
+                    flags: None,
+                    ..options.clone()
+                };
+                track!(self, self.protocol.store(command, key, value, &options))
+            }
+            None => track!(self, self.protocol.store(command, key, value, options)),
+        }
+    }
+
+    /// Encode a value to compress it, if compression is enabled and the value is over the threshold, with
+    /// `flags` overriding the flags of the value. Appended and prepended values are never compressed, as
+    /// they're concatenated to the stored value.
+    fn encode<V: ToMemcacheValue<Vec<u8>>>(
+        &self,
+        value: &V,
+        flags: Option<u32>,
+    ) -> Result<Option<Encoded>, MemcacheError> {
+        match self.compression {
+            Some(compression) if compression.applies(value.get_length()) => {
+                let mut data = Vec::with_capacity(value.get_length());
+                value.write_to(&mut data)?;
+                Ok(Some(
+                    compression.encode(data, flags.unwrap_or_else(|| value.get_flags())),
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Get a key along with the item metadata selected by `options`. Only supported by the meta protocol.
+    pub async fn meta_get<V: FromMemcacheValueExt>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        track!(self, self.protocol.meta_get(key.as_ref(), options))
+    }
+
+    /// Get the internal metadata memcached holds for a key. Only supported by the meta protocol.
+    pub async fn meta_debug(
+        &mut self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        track!(self, self.protocol.meta_debug(key.as_ref()))
+    }
+}
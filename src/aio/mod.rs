@@ -0,0 +1,31 @@
+/*!
+Asynchronous memcached client running on [tokio](https://tokio.rs/), enabled by the `tokio` feature.
+
+It speaks the same binary and ASCII protocols as the blocking [`Client`](crate::Client) over TCP and UNIX domain
+socket connections, and over TLS with the `tokio-tls` feature. Connections are pooled with
+[bb8](https://docs.rs/bb8).
+
+```rust,no_run
+# async fn run() -> Result<(), memcache::MemcacheError> {
+let client = memcache::aio::Client::connect(&["memcache://localhost:12345"]).await?;
+client.set("foo", "bar", 0).await?;
+let value: Option<String> = client.get("foo").await?;
+assert_eq!(value, Some(String::from("bar")));
+# Ok(())
+# }
+```
+!*/
+
+mod client;
+mod connection;
+mod protocol;
+mod stream;
+
+pub use self::client::Client;
+pub use self::connection::{Connection, ConnectionManager};
+pub use self::protocol::Protocol;
+pub use self::stream::Stream;
+pub use bb8::RunError as PoolError;
+
+/// bb8 connection pool
+pub type Pool = bb8::Pool<ConnectionManager>;
@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+
+use crate::aio::stream::Stream;
+use crate::client::{check_text_key, check_text_keys, Stats};
+use crate::error::{ClientError, CommandError, MemcacheError, ServerError};
+use crate::protocol::ascii::{self, StoreCommand};
+use crate::protocol::{raw_items, Item, StoreOptions};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+
+const MAX_LINE_LENGTH: u64 = 2048;
+
+pub struct AsciiProtocol {
+    stream: BufStream<Stream>,
+    line: Vec<u8>,
+}
+
+impl AsciiProtocol {
+    pub(crate) fn new(stream: Stream) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+            line: Vec::new(),
+        }
+    }
+
+    async fn send(&mut self, request: &[u8]) -> Result<(), MemcacheError> {
+        self.stream.write_all(request).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Read a CRLF terminated line, capped like the blocking protocol to suit headers and short responses.
+    async fn read_line(&mut self) -> Result<&str, MemcacheError> {
+        self.line.clear();
+        let read = (&mut self.stream)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut self.line)
+            .await?;
+        if read == 0 {
+            Err(ClientError::Error(Cow::Borrowed("Ascii protocol no line found")))?
+        }
+        if !self.line.ends_with(b"\r\n") {
+            Err(ClientError::Error(Cow::Borrowed("Ascii protocol response too long")))?
+        }
+        Ok(std::str::from_utf8(&self.line)?)
+    }
+
+    pub(crate) async fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        self.set(b"auth", format!("{} {}", username, password), 0).await
+    }
+
+    pub(crate) async fn version(&mut self) -> Result<String, MemcacheError> {
+        self.send(b"version\r\n").await?;
+        ascii::parse_version_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<(), MemcacheError> {
+        self.send(b"flush_all\r\n").await?;
+        ascii::parse_ok_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        self.send(format!("flush_all {}\r\n", delay).as_bytes()).await?;
+        ascii::parse_ok_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
+        let key = check_text_key(key)?;
+        self.send(format!("get {}\r\n", key).as_bytes()).await?;
+
+        if let Some((k, v)) = self.parse_get_response(false).await? {
+            if k != key {
+                Err(ServerError::BadResponse(Cow::Borrowed(
+                    "key doesn't match in the response",
+                )))?
+            } else if self.parse_get_response::<V>(false).await?.is_none() {
+                Ok(Some(v))
+            } else {
+                Err(ServerError::BadResponse(Cow::Borrowed("Expected end of get response")))?
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) async fn gets<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let keys = check_text_keys(keys)?;
+        self.send(format!("gets {}\r\n", keys.join(" ")).as_bytes()).await?;
+        self.parse_gets_response(keys.len()).await
+    }
+
+    pub(crate) async fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        Ok(raw_items(self.gets(keys).await?))
+    }
+
+    pub(crate) async fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        Ok(self.get_and_touch_multi(&[key], expiration).await?.remove(key))
+    }
+
+    pub(crate) async fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let keys = check_text_keys(keys)?;
+        self.send(format!("gats {} {}\r\n", expiration, keys.join(" ")).as_bytes())
+            .await?;
+        self.parse_gets_response(keys.len()).await
+    }
+
+    pub(crate) async fn cas<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            cas: Some(cas),
+            ..Default::default()
+        };
+        match self.store(StoreCommand::Cas, key, value, &options).await {
+            Ok(t) => Ok(t),
+            Err(MemcacheError::CommandError(e)) if e == CommandError::KeyExists || e == CommandError::KeyNotFound => {
+                Ok(false)
+            }
+            e => e,
+        }
+    }
+
+    pub(crate) async fn set<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Set, key, value, &options).await.map(|_| ())
+    }
+
+    pub(crate) async fn add<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Add, key, value, &options).await.map(|_| ())
+    }
+
+    pub(crate) async fn replace<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Replace, key, value, &options)
+            .await
+            .map(|_| ())
+    }
+
+    pub(crate) async fn append<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        self.store(StoreCommand::Append, key, value, &Default::default())
+            .await
+            .map(|_| ())
+    }
+
+    pub(crate) async fn prepend<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        self.store(StoreCommand::Prepend, key, value, &Default::default())
+            .await
+            .map(|_| ())
+    }
+
+    pub(crate) async fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
+        let key = check_text_key(key)?;
+        self.send(format!("delete {}\r\n", key).as_bytes()).await?;
+        ascii::parse_delete_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        let key = check_text_key(key)?;
+        self.send(format!("incr {} {}\r\n", key, amount).as_bytes()).await?;
+        ascii::parse_u64_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        let key = check_text_key(key)?;
+        self.send(format!("decr {} {}\r\n", key, amount).as_bytes()).await?;
+        ascii::parse_u64_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        // the text protocol has no counter creation, a missing counter is added instead, and incremented if
+        // another client added it first
+        match self.increment(key, amount).await {
+            Err(MemcacheError::CommandError(CommandError::KeyNotFound)) => {}
+            result => return result,
+        }
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        if self
+            .store(StoreCommand::Add, key, initial.to_string(), &options)
+            .await?
+        {
+            Ok(initial)
+        } else {
+            self.increment(key, amount).await
+        }
+    }
+
+    pub(crate) async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        let key = check_text_key(key)?;
+        self.send(format!("touch {} {}\r\n", key, expiration).as_bytes())
+            .await?;
+        ascii::parse_touch_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        self.send(b"stats\r\n").await?;
+
+        let mut stats: Stats = HashMap::new();
+        while let Some((key, value)) = ascii::parse_stat_line(self.read_line().await?)? {
+            stats.insert(key, value);
+        }
+        Ok(stats)
+    }
+
+    pub(crate) async fn store<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = check_text_key(key)?;
+        let command = ascii::cas_command(command, options)?;
+        let noreply = if options.noreply { " noreply" } else { "" };
+        let mut request = Vec::with_capacity(key.len() + value.get_length() + 64);
+        write!(
+            request,
+            "{command} {key} {flags} {exptime} {vlen}",
+            command = command,
+            key = key,
+            flags = options.flags.unwrap_or_else(|| value.get_flags()),
+            exptime = options.expiration,
+            vlen = value.get_length(),
+        )?;
+        if let Some(cas) = options.cas {
+            write!(request, " {}", cas)?;
+        }
+        write!(request, "{}\r\n", noreply)?;
+        value.write_to(&mut request)?;
+        request.extend_from_slice(b"\r\n");
+        self.send(&request).await?;
+
+        if options.noreply {
+            return Ok(true);
+        }
+
+        ascii::parse_store_response(self.read_line().await?)
+    }
+
+    async fn parse_get_response<V: FromMemcacheValueExt>(
+        &mut self,
+        has_cas: bool,
+    ) -> Result<Option<(String, V)>, MemcacheError> {
+        let header = ascii::parse_value_header(self.read_line().await?, has_cas)?;
+        match header {
+            Some((key, flags, length, cas)) => {
+                let mut value = vec![0u8; length + 2];
+                self.stream.read_exact(value.as_mut_slice()).await?;
+                let value = ascii::parse_value_data(value, flags, cas)?;
+                Ok(Some((key, value)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn parse_gets_response<V: FromMemcacheValueExt>(
+        &mut self,
+        max_values: usize,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let mut result: HashMap<Vec<u8>, V> = HashMap::with_capacity(max_values);
+        // there will be atmost max_values "VALUE <...>" responses and one END response
+        for _ in 0..=max_values {
+            match self.parse_get_response(true).await? {
+                Some((key, value)) => {
+                    result.insert(key.into_bytes(), value);
+                }
+                None => return Ok(result),
+            }
+        }
+
+        Err(ServerError::BadResponse(Cow::Borrowed("Expected end of gets response")))?
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use tokio::io::{AsyncReadExt, BufStream};
+
+use crate::aio::stream::Stream;
+use crate::client::Stats;
+use crate::error::{MemcacheError, ServerError};
+use crate::protocol::ascii::StoreCommand;
+use crate::protocol::binary_packet::{self, Magic, Opcode, PacketHeader};
+use crate::protocol::{raw_items, Item, StoreOptions};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+
+const HEADER_LENGTH: usize = 24;
+
+pub struct BinaryProtocol {
+    stream: BufStream<Stream>,
+    /// Whether quiet commands were sent since the last reply read, whose failures may still be pending.
+    quiet: bool,
+}
+
+fn key_request(opcode: Opcode, key: &[u8]) -> Result<Vec<u8>, MemcacheError> {
+    let mut request = Vec::with_capacity(HEADER_LENGTH + key.len());
+    PacketHeader {
+        magic: Magic::Request as u8,
+        opcode: opcode as u8,
+        key_length: key.len() as u16,
+        total_body_length: key.len() as u32,
+        ..Default::default()
+    }
+    .write(&mut request)?;
+    request.write_all(key)?;
+    Ok(request)
+}
+
+fn touch_request(opcode: Opcode, key: &[u8], expiration: u32) -> Result<Vec<u8>, MemcacheError> {
+    let mut request = Vec::with_capacity(HEADER_LENGTH + 4 + key.len());
+    PacketHeader {
+        magic: Magic::Request as u8,
+        opcode: opcode as u8,
+        key_length: key.len() as u16,
+        extras_length: 4,
+        total_body_length: (key.len() as u32 + 4),
+        ..Default::default()
+    }
+    .write(&mut request)?;
+    request.write_u32::<BigEndian>(expiration)?;
+    request.write_all(key)?;
+    Ok(request)
+}
+
+fn empty_request(opcode: Opcode) -> Result<Vec<u8>, MemcacheError> {
+    let mut request = Vec::with_capacity(HEADER_LENGTH);
+    PacketHeader {
+        magic: Magic::Request as u8,
+        opcode: opcode as u8,
+        ..Default::default()
+    }
+    .write(&mut request)?;
+    Ok(request)
+}
+
+impl BinaryProtocol {
+    pub(crate) fn new(stream: Stream) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+            quiet: false,
+        }
+    }
+
+    /// Skip the failures answered to the quiet commands sent since the last reply, up to a noop.
+    pub(crate) async fn settle(&mut self) -> Result<(), MemcacheError> {
+        if !self.quiet {
+            return Ok(());
+        }
+        self.send(&empty_request(Opcode::Noop)?).await?;
+        loop {
+            let mut buf = Vec::new();
+            if self.read_packet(&mut buf).await?.opcode == Opcode::Noop as u8 {
+                break;
+            }
+        }
+        self.quiet = false;
+        Ok(())
+    }
+
+    async fn send(&mut self, request: &[u8]) -> Result<(), MemcacheError> {
+        tokio::io::AsyncWriteExt::write_all(&mut self.stream, request).await?;
+        tokio::io::AsyncWriteExt::flush(&mut self.stream).await?;
+        Ok(())
+    }
+
+    /// Read one whole response packet and append it to `buf`, returning its header. The packet can then be
+    /// decoded by the blocking `binary_packet` parsers.
+    async fn read_packet(&mut self, buf: &mut Vec<u8>) -> Result<PacketHeader, MemcacheError> {
+        let start = buf.len();
+        buf.resize(start + HEADER_LENGTH, 0);
+        self.stream.read_exact(&mut buf[start..]).await?;
+        let header = PacketHeader::read(&mut &buf[start..])?;
+        let body_length = header.total_body_length as usize;
+        if body_length < header.key_length as usize + header.extras_length as usize {
+            return Err(ServerError::BadResponse(
+                "body length is shorter than key and extras".into(),
+            ))?;
+        }
+        buf.resize(start + HEADER_LENGTH + body_length, 0);
+        self.stream.read_exact(&mut buf[start + HEADER_LENGTH..]).await?;
+        Ok(header)
+    }
+
+    async fn read_response(&mut self) -> Result<Cursor<Vec<u8>>, MemcacheError> {
+        let mut buf = Vec::new();
+        self.read_packet(&mut buf).await?;
+        Ok(Cursor::new(buf))
+    }
+
+    pub(crate) async fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        let key = "PLAIN";
+        let mut request = Vec::new();
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::StartAuth as u8,
+            key_length: key.len() as u16,
+            total_body_length: (key.len() + username.len() + password.len() + 2) as u32,
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        request.write_all(key.as_bytes())?;
+        write!(request, "\x00{}\x00{}", username, password)?;
+        self.send(&request).await?;
+        binary_packet::parse_start_auth_response(&mut self.read_response().await?).map(|_| ())
+    }
+
+    pub(crate) async fn version(&mut self) -> Result<String, MemcacheError> {
+        self.send(&empty_request(Opcode::Version)?).await?;
+        binary_packet::parse_version_response(&mut self.read_response().await?)
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<(), MemcacheError> {
+        self.send(&empty_request(Opcode::Flush)?).await?;
+        binary_packet::parse_response(&mut self.read_response().await?)?
+            .err()
+            .map(|_| ())
+    }
+
+    pub(crate) async fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        let mut request = Vec::with_capacity(HEADER_LENGTH + 4);
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Flush as u8,
+            extras_length: 4,
+            total_body_length: 4,
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        request.write_u32::<BigEndian>(delay)?;
+        self.send(&request).await?;
+        binary_packet::parse_response(&mut self.read_response().await?)?
+            .err()
+            .map(|_| ())
+    }
+
+    pub(crate) async fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
+        self.send(&key_request(Opcode::Get, key)?).await?;
+        binary_packet::parse_get_response(&mut self.read_response().await?)
+    }
+
+    pub(crate) async fn gets<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let mut request = Vec::new();
+        for key in keys {
+            request.extend(key_request(Opcode::GetKQ, key)?);
+        }
+        request.extend(empty_request(Opcode::Noop)?);
+        self.send(&request).await?;
+
+        let mut buf = Vec::new();
+        for _ in 0..=keys.len() {
+            if self.read_packet(&mut buf).await?.opcode == Opcode::Noop as u8 {
+                break;
+            }
+        }
+        binary_packet::parse_gets_response(&mut Cursor::new(buf), keys.len())
+    }
+
+    pub(crate) async fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        Ok(raw_items(self.gets(keys).await?))
+    }
+
+    pub(crate) async fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        self.send(&touch_request(Opcode::GetAndTouch, key, expiration)?).await?;
+        binary_packet::parse_get_response(&mut self.read_response().await?)
+    }
+
+    pub(crate) async fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let mut request = Vec::new();
+        for key in keys {
+            request.extend(touch_request(Opcode::GetAndTouchKQ, key, expiration)?);
+        }
+        request.extend(empty_request(Opcode::Noop)?);
+        self.send(&request).await?;
+
+        let mut buf = Vec::new();
+        for _ in 0..=keys.len() {
+            if self.read_packet(&mut buf).await?.opcode == Opcode::Noop as u8 {
+                break;
+            }
+        }
+        binary_packet::parse_gets_response(&mut Cursor::new(buf), keys.len())
+    }
+
+    pub(crate) async fn cas<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        self.send_request(Opcode::Set, key, value, expiration, Some(cas))
+            .await?;
+        binary_packet::parse_cas_response(&mut self.read_response().await?)
+    }
+
+    pub(crate) async fn set<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        self.store(Opcode::Set, key, value, expiration).await
+    }
+
+    pub(crate) async fn add<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        self.store(Opcode::Add, key, value, expiration).await
+    }
+
+    pub(crate) async fn replace<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        self.store(Opcode::Replace, key, value, expiration).await
+    }
+
+    pub(crate) async fn append<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        self.concat(Opcode::Append, key, value).await
+    }
+
+    pub(crate) async fn prepend<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        self.concat(Opcode::Prepend, key, value).await
+    }
+
+    pub(crate) async fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
+        self.send(&key_request(Opcode::Delete, key)?).await?;
+        binary_packet::parse_delete_response(&mut self.read_response().await?)
+    }
+
+    pub(crate) async fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.counter(Opcode::Increment, key, amount, 0, binary_packet::COUNTER_NO_INIT)
+            .await
+    }
+
+    pub(crate) async fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.counter(Opcode::Decrement, key, amount, 0, binary_packet::COUNTER_NO_INIT)
+            .await
+    }
+
+    pub(crate) async fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        self.counter(Opcode::Increment, key, amount, initial, expiration).await
+    }
+
+    pub(crate) async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        self.send(&touch_request(Opcode::Touch, key, expiration)?).await?;
+        binary_packet::parse_touch_response(&mut self.read_response().await?)
+    }
+
+    pub(crate) async fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        self.send(&empty_request(Opcode::Stat)?).await?;
+        let mut buf = Vec::new();
+        loop {
+            let header = self.read_packet(&mut buf).await?;
+            if header.total_body_length == 0 || header.vbucket_id_or_status != 0 {
+                break;
+            }
+        }
+        binary_packet::parse_stats_response(&mut Cursor::new(buf))
+    }
+
+    /// Store with the options of `StoreOptions`. A noreply store is sent with the quiet opcode, which the server
+    /// only answers on failure.
+    pub(crate) async fn store_with<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let (opcode, extras_length) = binary_packet::store_opcode(command, options.noreply);
+        let body_length = extras_length as usize + key.len() + value.get_length();
+        let mut request = Vec::with_capacity(HEADER_LENGTH + body_length);
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length,
+            total_body_length: body_length as u32,
+            cas: options.cas.unwrap_or(0),
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        if extras_length > 0 {
+            request.write_u32::<BigEndian>(options.flags.unwrap_or_else(|| value.get_flags()))?;
+            request.write_u32::<BigEndian>(options.expiration)?;
+        }
+        request.write_all(key)?;
+        value.write_to(&mut request)?;
+        self.send(&request).await?;
+
+        if options.noreply {
+            self.quiet = true;
+            return Ok(true);
+        }
+        binary_packet::parse_response(&mut self.read_response().await?)?
+            .err()
+            .map(|_| true)
+    }
+
+    async fn send_request<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        opcode: Opcode,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+        cas: Option<u64>,
+    ) -> Result<(), MemcacheError> {
+        let mut request = Vec::with_capacity(HEADER_LENGTH + 8 + key.len() + value.get_length());
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length: 8,
+            total_body_length: (8 + key.len() + value.get_length()) as u32,
+            cas: cas.unwrap_or(0),
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        let extras = binary_packet::StoreExtras {
+            flags: value.get_flags(),
+            expiration,
+        };
+        request.write_u32::<BigEndian>(extras.flags)?;
+        request.write_u32::<BigEndian>(extras.expiration)?;
+        request.write_all(key)?;
+        value.write_to(&mut request)?;
+        self.send(&request).await
+    }
+
+    async fn store<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        opcode: Opcode,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        self.send_request(opcode, key, value, expiration, None).await?;
+        binary_packet::parse_response(&mut self.read_response().await?)?
+            .err()
+            .map(|_| ())
+    }
+
+    async fn concat<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        opcode: Opcode,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        let mut request = Vec::with_capacity(HEADER_LENGTH + key.len() + value.get_length());
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            total_body_length: (key.len() + value.get_length()) as u32,
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        request.write_all(key)?;
+        value.write_to(&mut request)?;
+        self.send(&request).await?;
+        binary_packet::parse_response(&mut self.read_response().await?)?
+            .err()
+            .map(|_| ())
+    }
+
+    async fn counter(
+        &mut self,
+        opcode: Opcode,
+        key: &[u8],
+        amount: u64,
+        initial_value: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        let mut request = Vec::with_capacity(HEADER_LENGTH + 20 + key.len());
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length: 20,
+            total_body_length: (20 + key.len()) as u32,
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        let extras = binary_packet::CounterExtras {
+            amount,
+            initial_value,
+            expiration,
+        };
+        request.write_u64::<BigEndian>(extras.amount)?;
+        request.write_u64::<BigEndian>(extras.initial_value)?;
+        request.write_u32::<BigEndian>(extras.expiration)?;
+        request.write_all(key)?;
+        self.send(&request).await?;
+        binary_packet::parse_counter_response(&mut self.read_response().await?)
+    }
+}
@@ -0,0 +1,375 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+
+use crate::aio::stream::Stream;
+use crate::client::Stats;
+use crate::error::{ClientError, CommandError, MemcacheError};
+use crate::protocol::ascii::{self, StoreCommand};
+use crate::protocol::meta::{self, MetaGetOptions, MetaValue, Response, Status};
+use crate::protocol::{Item, StoreOptions};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+
+const MAX_LINE_LENGTH: u64 = 2048;
+
+pub struct MetaProtocol {
+    stream: BufStream<Stream>,
+    line: Vec<u8>,
+    /// Whether quiet commands were sent since the last reply read, whose failures may still be pending.
+    quiet: bool,
+}
+
+impl MetaProtocol {
+    pub(crate) fn new(stream: Stream) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+            line: Vec::new(),
+            quiet: false,
+        }
+    }
+
+    /// Skip the failures answered to the quiet commands sent since the last reply, up to a noop.
+    pub(crate) async fn settle(&mut self) -> Result<(), MemcacheError> {
+        if !self.quiet {
+            return Ok(());
+        }
+        self.send(b"mn\r\n").await?;
+        loop {
+            let response = self.read_response().await?;
+            match response.status {
+                Status::Noop => break,
+                Status::Value(length) => {
+                    self.read_data(length).await?;
+                }
+                _ => (),
+            }
+        }
+        self.quiet = false;
+        Ok(())
+    }
+
+    async fn send(&mut self, request: &[u8]) -> Result<(), MemcacheError> {
+        self.stream.write_all(request).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Read a CRLF terminated line, capped like the blocking protocol to suit headers and short responses.
+    async fn read_line(&mut self) -> Result<&str, MemcacheError> {
+        self.line.clear();
+        let read = (&mut self.stream)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut self.line)
+            .await?;
+        if read == 0 {
+            Err(ClientError::Error(Cow::Borrowed("Ascii protocol no line found")))?
+        }
+        if !self.line.ends_with(b"\r\n") {
+            Err(ClientError::Error(Cow::Borrowed("Ascii protocol response too long")))?
+        }
+        Ok(std::str::from_utf8(&self.line)?)
+    }
+
+    async fn read_response(&mut self) -> Result<Response, MemcacheError> {
+        meta::parse_response(self.read_line().await?)
+    }
+
+    async fn read_data(&mut self, length: usize) -> Result<Vec<u8>, MemcacheError> {
+        let mut data = vec![0u8; length + 2];
+        self.stream.read_exact(data.as_mut_slice()).await?;
+        Ok(data)
+    }
+
+    pub(crate) async fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        let credentials = format!("{} {}", username, password);
+        self.send(format!("set auth 0 0 {}\r\n{}\r\n", credentials.len(), credentials).as_bytes())
+            .await?;
+        ascii::parse_store_response(self.read_line().await?).map(|_| ())
+    }
+
+    pub(crate) async fn version(&mut self) -> Result<String, MemcacheError> {
+        self.send(b"version\r\n").await?;
+        ascii::parse_version_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<(), MemcacheError> {
+        self.send(b"flush_all\r\n").await?;
+        ascii::parse_ok_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        self.send(format!("flush_all {}\r\n", delay).as_bytes()).await?;
+        ascii::parse_ok_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
+        // values are read as stored, for the raw tuple to keep its compression bits
+        Ok(self
+            .read_meta_value(key, &Default::default(), meta::parse_meta_value)
+            .await?
+            .map(|value| value.value))
+    }
+
+    pub(crate) async fn meta_get<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        self.read_meta_value(key, options, meta::parse_meta_get_value).await
+    }
+
+    /// Send a meta get and decode its value with `parse`.
+    async fn read_meta_value<V>(
+        &mut self,
+        key: &[u8],
+        options: &MetaGetOptions,
+        parse: fn(&Response, Vec<u8>) -> Result<MetaValue<V>, MemcacheError>,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        self.send(meta::command_line("mg", key, &options.flags()).as_bytes())
+            .await?;
+        let response = self.read_response().await?;
+        match response.status {
+            Status::Value(length) => {
+                let data = self.read_data(length).await?;
+                Ok(Some(parse(&response, data)?))
+            }
+            Status::Miss => Ok(None),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    pub(crate) async fn meta_debug(&mut self, key: &[u8]) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        self.send(meta::command_line("me", key, "").as_bytes()).await?;
+        meta::parse_debug_response(self.read_line().await?)
+    }
+
+    pub(crate) async fn gets<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        Ok(meta::values(self.multi_get(keys, "v f c").await?))
+    }
+
+    pub(crate) async fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        Ok(meta::items(self.multi_get(keys, "v f c t l").await?))
+    }
+
+    pub(crate) async fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        let options = MetaGetOptions {
+            cas: true,
+            touch: Some(expiration),
+            ..Default::default()
+        };
+        Ok(self.meta_get(key, &options).await?.map(|value| value.value))
+    }
+
+    pub(crate) async fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        Ok(meta::values(
+            self.multi_get(keys, &format!("v f c T{}", expiration)).await?,
+        ))
+    }
+
+    async fn multi_get<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        flags: &str,
+    ) -> Result<HashMap<Vec<u8>, MetaValue<V>>, MemcacheError> {
+        // quiet gets only answer hits, tagged with the key index as opaque, and the noop marks the end
+        let mut request = String::new();
+        for (index, key) in keys.iter().enumerate() {
+            request.push_str(&meta::command_line("mg", key, &format!("{} q O{}", flags, index)));
+        }
+        request.push_str("mn\r\n");
+        self.send(request.as_bytes()).await?;
+
+        let mut result: HashMap<Vec<u8>, MetaValue<V>> = HashMap::with_capacity(keys.len());
+        loop {
+            let response = self.read_response().await?;
+            match response.status {
+                Status::Value(length) => {
+                    let data = self.read_data(length).await?;
+                    let key = match response.parse_flag::<usize>('O')?.and_then(|index| keys.get(index)) {
+                        Some(key) => key,
+                        None => return Err(response.unexpected()),
+                    };
+                    result.insert(key.to_vec(), meta::parse_meta_value(&response, data)?);
+                }
+                Status::Noop => return Ok(result),
+                _ => return Err(response.unexpected()),
+            }
+        }
+    }
+
+    pub(crate) async fn cas<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            cas: Some(cas),
+            ..Default::default()
+        };
+        match self.store(StoreCommand::Cas, key, value, &options).await {
+            Ok(t) => Ok(t),
+            Err(MemcacheError::CommandError(e)) if e == CommandError::KeyExists || e == CommandError::KeyNotFound => {
+                Ok(false)
+            }
+            e => e,
+        }
+    }
+
+    pub(crate) async fn set<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Set, key, value, &options).await.map(|_| ())
+    }
+
+    pub(crate) async fn add<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Add, key, value, &options).await.map(|_| ())
+    }
+
+    pub(crate) async fn replace<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Replace, key, value, &options)
+            .await
+            .map(|_| ())
+    }
+
+    pub(crate) async fn append<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        self.store(StoreCommand::Append, key, value, &Default::default())
+            .await
+            .map(|_| ())
+    }
+
+    pub(crate) async fn prepend<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        self.store(StoreCommand::Prepend, key, value, &Default::default())
+            .await
+            .map(|_| ())
+    }
+
+    pub(crate) async fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
+        self.send(meta::command_line("md", key, "").as_bytes()).await?;
+        let response = self.read_response().await?;
+        match response.status {
+            Status::Header => Ok(true),
+            Status::NotFound => Ok(false),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    pub(crate) async fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.arithmetic(key, &format!("v D{}", amount)).await
+    }
+
+    pub(crate) async fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.arithmetic(key, &format!("v MD D{}", amount)).await
+    }
+
+    pub(crate) async fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        // the N flag creates a missing counter with the J initial value, and its ttl
+        self.arithmetic(key, &format!("v N{} J{} D{}", expiration, initial, amount))
+            .await
+    }
+
+    pub(crate) async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        self.send(meta::command_line("mg", key, &format!("T{}", expiration)).as_bytes())
+            .await?;
+        let response = self.read_response().await?;
+        match response.status {
+            Status::Header => Ok(true),
+            Status::Miss => Ok(false),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    pub(crate) async fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        self.send(b"stats\r\n").await?;
+        let mut stats: Stats = HashMap::new();
+        while let Some((key, value)) = ascii::parse_stat_line(self.read_line().await?)? {
+            stats.insert(key, value);
+        }
+        Ok(stats)
+    }
+
+    pub(crate) async fn store<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let line = meta::store_command_line(command, key, value.get_length(), value.get_flags(), options)?;
+        let mut request = line.into_bytes();
+        value.write_to(&mut request)?;
+        request.extend_from_slice(b"\r\n");
+        self.send(&request).await?;
+
+        if options.noreply {
+            // quiet stores are still answered on failure
+            self.quiet = true;
+            return Ok(true);
+        }
+
+        meta::parse_store_response(self.read_response().await?)
+    }
+
+    async fn arithmetic(&mut self, key: &[u8], flags: &str) -> Result<u64, MemcacheError> {
+        self.send(meta::command_line("ma", key, flags).as_bytes()).await?;
+        let response = self.read_response().await?;
+        match response.status {
+            Status::Value(length) => meta::parse_u64_data(self.read_data(length).await?),
+            Status::NotFound => Err(CommandError::KeyNotFound)?,
+            _ => Err(response.unexpected()),
+        }
+    }
+}
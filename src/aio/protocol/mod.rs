@@ -0,0 +1,212 @@
+mod ascii;
+mod binary;
+mod meta;
+
+pub(crate) use self::ascii::AsciiProtocol;
+pub(crate) use self::binary::BinaryProtocol;
+pub(crate) use self::meta::MetaProtocol;
+use crate::client::Stats;
+use crate::error::{ClientError, MemcacheError};
+use crate::protocol::ascii::StoreCommand;
+use crate::protocol::{check_counter_expiration, not_stored, Item, MetaGetOptions, MetaValue, StoreOptions};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+use std::collections::HashMap;
+
+/// Asynchronous counterpart of the blocking protocols, with the same command set as `ProtocolTrait`.
+pub enum Protocol {
+    Ascii(AsciiProtocol),
+    Binary(BinaryProtocol),
+    Meta(MetaProtocol),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $method:ident($($arg:expr),*)) => {
+        match $self {
+            Protocol::Ascii(ref mut protocol) => protocol.$method($($arg),*).await,
+            Protocol::Binary(ref mut protocol) => protocol.$method($($arg),*).await,
+            Protocol::Meta(ref mut protocol) => protocol.$method($($arg),*).await,
+        }
+    };
+}
+
+impl Protocol {
+    pub async fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        dispatch!(self, auth(username, password))
+    }
+
+    pub async fn version(&mut self) -> Result<String, MemcacheError> {
+        dispatch!(self, version())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), MemcacheError> {
+        dispatch!(self, flush())
+    }
+
+    pub async fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        dispatch!(self, flush_with_delay(delay))
+    }
+
+    pub async fn get<V: FromMemcacheValueExt>(&mut self, key: impl AsRef<[u8]>) -> Result<Option<V>, MemcacheError> {
+        dispatch!(self, get(key.as_ref()))
+    }
+
+    pub async fn gets<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let keys: Vec<&[u8]> = keys.iter().map(AsRef::as_ref).collect();
+        dispatch!(self, gets(&keys))
+    }
+
+    pub async fn get_items(
+        &mut self,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        let keys: Vec<&[u8]> = keys.iter().map(AsRef::as_ref).collect();
+        dispatch!(self, get_items(&keys))
+    }
+
+    pub async fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        dispatch!(self, get_and_touch(key.as_ref(), expiration))
+    }
+
+    pub async fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[impl AsRef<[u8]>],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let keys: Vec<&[u8]> = keys.iter().map(AsRef::as_ref).collect();
+        dispatch!(self, get_and_touch_multi(&keys, expiration))
+    }
+
+    pub async fn set<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        dispatch!(self, set(key.as_ref(), value, expiration))
+    }
+
+    pub async fn cas<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        dispatch!(self, cas(key.as_ref(), value, expiration, cas))
+    }
+
+    pub async fn add<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        dispatch!(self, add(key.as_ref(), value, expiration))
+    }
+
+    pub async fn replace<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        dispatch!(self, replace(key.as_ref(), value, expiration))
+    }
+
+    pub async fn append<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        dispatch!(self, append(key.as_ref(), value))
+    }
+
+    pub async fn prepend<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+    ) -> Result<(), MemcacheError> {
+        dispatch!(self, prepend(key.as_ref(), value))
+    }
+
+    pub async fn delete(&mut self, key: impl AsRef<[u8]>) -> Result<bool, MemcacheError> {
+        dispatch!(self, delete(key.as_ref()))
+    }
+
+    pub async fn increment(&mut self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        dispatch!(self, increment(key.as_ref(), amount))
+    }
+
+    pub async fn decrement(&mut self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        dispatch!(self, decrement(key.as_ref(), amount))
+    }
+
+    pub async fn increment_or_init(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        check_counter_expiration(expiration)?;
+        dispatch!(self, increment_or_init(key.as_ref(), amount, initial, expiration))
+    }
+
+    pub async fn touch(&mut self, key: impl AsRef<[u8]>, expiration: u32) -> Result<bool, MemcacheError> {
+        dispatch!(self, touch(key.as_ref(), expiration))
+    }
+
+    pub async fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        dispatch!(self, stats())
+    }
+
+    /// Run a store command with options, returning whether the value was stored.
+    pub(crate) async fn store<V: ToMemcacheValue<Vec<u8>>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let result = match self {
+            Protocol::Ascii(ref mut protocol) => protocol.store(command, key, value, options).await,
+            Protocol::Binary(ref mut protocol) => protocol.store_with(command, key, value, options).await,
+            Protocol::Meta(ref mut protocol) => protocol.store(command, key, value, options).await,
+        };
+        not_stored(result)
+    }
+
+    /// Read and discard the replies left on the connection by the last noreply commands.
+    pub(crate) async fn settle(&mut self) -> Result<(), MemcacheError> {
+        match self {
+            Protocol::Ascii(_) => Ok(()),
+            Protocol::Binary(ref mut protocol) => protocol.settle().await,
+            Protocol::Meta(ref mut protocol) => protocol.settle().await,
+        }
+    }
+
+    pub(crate) async fn meta_get<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        match self {
+            Protocol::Meta(ref mut protocol) => protocol.meta_get(key, options).await,
+            _ => Err(ClientError::Error("meta_get requires the meta protocol".into()))?,
+        }
+    }
+
+    pub(crate) async fn meta_debug(&mut self, key: &[u8]) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        match self {
+            Protocol::Meta(ref mut protocol) => protocol.meta_debug(key).await,
+            _ => Err(ClientError::Error("meta_debug requires the meta protocol".into()))?,
+        }
+    }
+}
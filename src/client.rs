@@ -1,41 +1,343 @@
 use r2d2::Pool;
 use r2d2::PooledConnection;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::connection::ConnectionManager;
-use crate::error::{ClientError, MemcacheError};
-use crate::protocol::ProtocolTrait;
+use crate::connection::{Connection, ConnectionManager};
+use crate::error::{ClientError, CommandError, MemcacheError, ServerError};
+use crate::ketama::Ketama;
+use crate::key_policy::KeyPolicy;
+use crate::pipeline::{self, Command};
+use crate::protocol::ascii::StoreCommand;
+use crate::protocol::{Item, MetaGetOptions, MetaValue, Protocol, ProtocolTrait, StoreOptions};
 use crate::stream::Stream;
-use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+use crate::value::{Borrowed, FromMemcacheValueExt, ToMemcacheValue};
 
 pub type Stats = HashMap<String, String>;
 
+/// Seconds a caller of `Client::get_or_compute` has to recompute a value before another caller takes over.
+const COMPUTE_LEASE: u32 = 30;
+
+/// The outcome of a read-modify-write with `Client::update`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update<V> {
+    /// The value that was stored, or `None` when the closure left the item unchanged.
+    pub value: Option<V>,
+    /// How many times the value was read and written back, one more than the number of conflicts.
+    pub attempts: u32,
+}
+
 #[derive(Clone, Debug)]
-pub struct Client(Pool<ConnectionManager>);
+pub struct Client {
+    pools: Vec<Pool<ConnectionManager>>,
+    ketama: Option<Arc<Ketama>>,
+    pub hash_function: fn(&[u8]) -> u64,
+    namespace: Arc<str>,
+    key_policy: Arc<KeyPolicy>,
+}
+
+pub(crate) fn default_hash_function(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // hashed like a `str`, so that text keys keep their server
+    hasher.write(key);
+    hasher.write_u8(0xff);
+    hasher.finish()
+}
+
+/// Pick the server owning the key, by the ketama ring when there is one and by `hash_function` otherwise.
+pub(crate) fn server_index(
+    servers: usize,
+    ketama: Option<&Ketama>,
+    hash_function: fn(&[u8]) -> u64,
+    key: &[u8],
+) -> usize {
+    if servers == 1 {
+        return 0;
+    }
+    if let Some(ketama) = ketama {
+        return ketama.get_server(key);
+    }
+    (hash_function(key) % servers as u64) as usize
+}
+
+/// Time to wait before retrying after the given number of failed attempts, doubling from 2ms up to 128ms.
+pub(crate) fn update_backoff(attempts: u32) -> Duration {
+    Duration::from_millis(1 << attempts.min(7))
+}
 
-pub(crate) fn check_key_len(key: &str) -> Result<(), MemcacheError> {
+/// Sleep before retrying after the given number of failed attempts, or fail with `MemcacheError::Timeout` when
+/// the deadline has passed.
+fn wait_until(deadline: Instant, attempts: u32) -> Result<(), MemcacheError> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        Err(MemcacheError::Timeout)?
+    }
+    thread::sleep(update_backoff(attempts).min(remaining));
+    Ok(())
+}
+
+/// Decode the value of a gets reply along with its cas token.
+pub(crate) fn decode_update<V: FromMemcacheValueExt>(
+    item: Option<(Vec<u8>, u32, Option<u64>)>,
+) -> Result<(Option<V>, Option<u64>), MemcacheError> {
+    match item {
+        Some((_, _, None)) => Err(ClientError::Error("update requires the cas token of the value".into()))?,
+        Some((value, flags, cas)) => Ok((Some(V::from_memcache_value(value, flags, cas)?), cas)),
+        None => Ok((None, None)),
+    }
+}
+
+pub(crate) fn check_key_len(key: &[u8]) -> Result<(), MemcacheError> {
     if key.len() > 250 {
         Err(ClientError::KeyTooLong)?
     }
     Ok(())
 }
 
+/// Check that a key can be written as is on a command line of the ascii protocol, which has no way to escape
+/// whitespace, control characters or invalid UTF-8, and return it as text.
+pub(crate) fn check_text_key(key: &[u8]) -> Result<&str, MemcacheError> {
+    check_key_len(key)?;
+    if key.iter().any(|b| b.is_ascii_whitespace() || b.is_ascii_control()) {
+        Err(ClientError::InvalidKey)?
+    }
+    Ok(std::str::from_utf8(key).map_err(|_| ClientError::InvalidKey)?)
+}
+
+pub(crate) fn check_text_keys<'a>(keys: &[&'a [u8]]) -> Result<Vec<&'a str>, MemcacheError> {
+    keys.iter().map(|key| check_text_key(key)).collect()
+}
+
+/// Prefix a key with the namespace of a client, checking the length of the whole key.
+pub(crate) fn namespaced<'a>(namespace: &str, key: &'a [u8]) -> Result<Cow<'a, [u8]>, MemcacheError> {
+    let key = match namespace {
+        "" => Cow::Borrowed(key),
+        namespace => Cow::Owned([namespace.as_bytes(), key].concat()),
+    };
+    check_key_len(&key)?;
+    Ok(key)
+}
+
+/// Map the keys sent to the servers back to the keys given by the caller, which differ by the namespace of the
+/// client and by the keys hashed by its key policy.
+pub(crate) fn caller_keys<'a, K>(keys: &'a [K], sent: &'a [Cow<'a, [u8]>]) -> HashMap<&'a [u8], &'a K> {
+    sent.iter().map(|key| key.as_ref()).zip(keys).collect()
+}
+
+/// The key given by the caller for a key returned by a server.
+pub(crate) fn caller_key<K: Clone>(callers: &HashMap<&[u8], &K>, key: &[u8]) -> Result<K, MemcacheError> {
+    match callers.get(key) {
+        Some(caller) => Ok((*caller).clone()),
+        None => Err(ServerError::BadResponse(Cow::Borrowed(
+            "unexpected key in the response",
+        )))?,
+    }
+}
+
 impl Client {
     pub fn with_pool(pool: Pool<ConnectionManager>) -> Self {
-        Self(pool)
+        Self {
+            pools: vec![pool],
+            ketama: None,
+            hash_function: default_hash_function,
+            namespace: Arc::from(""),
+            key_policy: Arc::new(KeyPolicy::Strict),
+        }
+    }
+
+    /// Create a client over several memcached servers, one pool per server. Keys are routed to a server with
+    /// `hash_function`, which can be replaced after construction.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pools = vec!["memcache://localhost:12345", "memcache://localhost:12346"]
+    ///     .into_iter()
+    ///     .map(|url| {
+    ///         memcache::Pool::builder()
+    ///             .connection_timeout(std::time::Duration::from_secs(1))
+    ///             .build(memcache::ConnectionManager::new(url).unwrap())
+    ///             .unwrap()
+    ///     })
+    ///     .collect();
+    /// let mut client = memcache::Client::with_pools(pools).unwrap();
+    /// client.hash_function = |key: &[u8]| -> u64 { key.len() as u64 };
+    /// client.set("foo", "bar", 0).unwrap();
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn with_pools(pools: Vec<Pool<ConnectionManager>>) -> Result<Self, MemcacheError> {
+        if pools.is_empty() {
+            Err(ClientError::Error("at least one pool is required".into()))?
+        }
+        Ok(Self {
+            pools,
+            ketama: None,
+            hash_function: default_hash_function,
+            namespace: Arc::from(""),
+            key_policy: Arc::new(KeyPolicy::Strict),
+        })
+    }
+
+    /// Create a client over several memcached servers, routing keys with a ketama consistent hashing ring.
+    /// The pools must be in the same order as the servers the ring was built from. `hash_function` is not
+    /// used by such a client.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let servers = [("memcache://localhost:12345", 1), ("memcache://localhost:12346", 2)];
+    /// let ring = memcache::Ketama::from_urls(&servers).unwrap();
+    /// let pools = servers
+    ///     .iter()
+    ///     .map(|(url, _)| {
+    ///         memcache::Pool::builder()
+    ///             .connection_timeout(std::time::Duration::from_secs(1))
+    ///             .build(memcache::ConnectionManager::new(url).unwrap())
+    ///             .unwrap()
+    ///     })
+    ///     .collect();
+    /// let client = memcache::Client::with_ketama(pools, ring).unwrap();
+    /// client.set("foo", "bar", 0).unwrap();
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn with_ketama(pools: Vec<Pool<ConnectionManager>>, ring: Ketama) -> Result<Self, MemcacheError> {
+        if pools.len() != ring.len() {
+            Err(ClientError::Error(
+                "ketama ring and pools have different server counts".into(),
+            ))?
+        }
+        let mut client = Self::with_pools(pools)?;
+        client.ketama = Some(Arc::new(ring));
+        Ok(client)
+    }
+
+    /// Create a client with a default connection pool for each of the given server urls.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let client = memcache::Client::connect(&["memcache://localhost:12345", "memcache://localhost:12346"]).unwrap();
+    /// client.version().unwrap();
+    /// ```
+    pub fn connect<S: AsRef<str>>(urls: &[S]) -> Result<Self, MemcacheError> {
+        let pools = urls
+            .iter()
+            .map(|url| Ok(Pool::new(ConnectionManager::new(url)?)?))
+            .collect::<Result<_, MemcacheError>>()?;
+        Self::with_pools(pools)
+    }
+
+    /// Get pool connection for the server which owns the key
+    pub fn get_connection(&self, key: impl AsRef<[u8]>) -> Result<PooledConnection<ConnectionManager>, MemcacheError> {
+        Ok(self.get_pool(key).get()?)
+    }
+
+    /// Get ConnectionManager pool for the server which owns the key
+    pub fn get_pool(&self, key: impl AsRef<[u8]>) -> Pool<ConnectionManager> {
+        self.pools[self.server_index(key.as_ref())].clone()
+    }
+
+    /// Get ConnectionManager pools of all servers
+    pub fn get_pools(&self) -> &[Pool<ConnectionManager>] {
+        &self.pools
+    }
+
+    /// Get a handle on the same servers which prefixes every key with `namespace`, on top of the namespace of
+    /// this client. The prefix counts in the 250 bytes limit of the keys, and is removed from the keys of the
+    /// maps returned by `gets` and the like. The connections returned by `get_connection` don't prefix keys.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// let service = client.with_namespace("svc:v2:");
+    /// service.set("namespace_foo", "bar", 0).unwrap();
+    /// assert_eq!(client.get::<String>("svc:v2:namespace_foo").unwrap().as_deref(), Some("bar"));
+    /// let values: std::collections::HashMap<&str, String> = service.gets(&["namespace_foo"]).unwrap();
+    /// assert_eq!(values["namespace_foo"], "bar");
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn with_namespace(&self, namespace: &str) -> Self {
+        Self {
+            namespace: Arc::from(format!("{}{}", self.namespace, namespace)),
+            ..self.clone()
+        }
+    }
+
+    /// The prefix added to every key, empty by default.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Get a handle on the same servers which sends the keys over 250 bytes, or which the ascii protocol can't
+    /// send, as set by `policy`. The default `KeyPolicy::Strict` rejects them.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use memcache::KeyPolicy;
+    ///
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// let long = "k".repeat(300);
+    /// assert!(client.set(&long, "bar", 0).is_err());
+    /// let hashing = client.with_key_policy(KeyPolicy::Hash { prefix: "h:".to_string(), log: None });
+    /// hashing.set(&long, "bar", 0).unwrap();
+    /// let values: std::collections::HashMap<&String, String> = hashing.gets(&[&long]).unwrap();
+    /// assert_eq!(values[&long], "bar");
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn with_key_policy(&self, policy: KeyPolicy) -> Self {
+        Self {
+            key_policy: Arc::new(policy),
+            ..self.clone()
+        }
+    }
+
+    /// The policy applied to the keys the servers can't take as is.
+    pub fn key_policy(&self) -> &KeyPolicy {
+        &self.key_policy
     }
 
-    /// Get pool connection
-    pub fn get_connection(&self) -> Result<PooledConnection<ConnectionManager>, MemcacheError> {
-        Ok(self.0.get()?)
+    fn key<'a>(&self, key: &'a [u8]) -> Result<Cow<'a, [u8]>, MemcacheError> {
+        self.key_policy.apply(&self.namespace, key)
     }
 
-    /// Get ConnectionManager pool
-    pub fn get_pool(&self) -> Pool<ConnectionManager> {
-        self.0.clone()
+    fn keys<'a, K: AsRef<[u8]>>(&self, keys: &'a [K]) -> Result<Vec<Cow<'a, [u8]>>, MemcacheError> {
+        keys.iter().map(|key| self.key(key.as_ref())).collect()
     }
 
-    /// Get the memcached server version.
+    fn server_index(&self, key: &[u8]) -> usize {
+        server_index(self.pools.len(), self.ketama.as_deref(), self.hash_function, key)
+    }
+
+    /// Get a connection to the server which owns a key of any bytes.
+    fn connection(&self, key: &[u8]) -> Result<PooledConnection<ConnectionManager>, MemcacheError> {
+        self.get_connection(key)
+    }
+
+    fn distribute_keys<'a, K: AsRef<[u8]>>(&self, keys: &'a [K]) -> HashMap<usize, Vec<&'a [u8]>> {
+        let mut distribution: HashMap<usize, Vec<&'a [u8]>> = HashMap::new();
+        for key in keys {
+            let key = key.as_ref();
+            distribution.entry(self.server_index(key)).or_default().push(key);
+        }
+        distribution
+    }
+
+    /// Get the memcached server version of every server, paired with the server url.
     ///
     /// Example:
     ///
@@ -47,8 +349,14 @@ impl Client {
     /// let client = memcache::Client::with_pool(pool);
     /// client.version().unwrap();
     /// ```
-    pub fn version(&self) -> Result<String, MemcacheError> {
-        self.get_connection()?.version()
+    pub fn version(&self) -> Result<Vec<(String, String)>, MemcacheError> {
+        let mut result = Vec::with_capacity(self.pools.len());
+        for pool in &self.pools {
+            let mut connection = pool.get()?;
+            let url = connection.url.to_string();
+            result.push((url, connection.version()?));
+        }
+        Ok(result)
     }
 
     /// Flush all cache on memcached server immediately.
@@ -64,7 +372,10 @@ impl Client {
     /// client.flush().unwrap();
     /// ```
     pub fn flush(&self) -> Result<(), MemcacheError> {
-        self.get_connection()?.flush()
+        for pool in &self.pools {
+            pool.get()?.flush()?;
+        }
+        Ok(())
     }
 
     /// Flush all cache on memcached server with a delay seconds.
@@ -80,11 +391,18 @@ impl Client {
     /// client.flush_with_delay(10).unwrap();
     /// ```
     pub fn flush_with_delay(&self, delay: u32) -> Result<(), MemcacheError> {
-        self.get_connection()?.flush_with_delay(delay)
+        for pool in &self.pools {
+            pool.get()?.flush_with_delay(delay)?;
+        }
+        Ok(())
     }
 
     /// Get a key from memcached server.
     ///
+    /// The single key commands take keys of any bytes. The binary protocol sends them as is and the meta
+    /// protocol base64 encodes them, while the ascii protocol fails with `ClientError::InvalidKey` on keys
+    /// holding whitespace, control characters or invalid UTF-8.
+    ///
     /// Example:
     ///
     /// ```rust
@@ -94,13 +412,15 @@ impl Client {
     /// .unwrap();
     /// let client = memcache::Client::with_pool(pool);
     /// let _: Option<String> = client.get("foo").unwrap();
+    /// let _: Option<String> = client.get(b"binary\r\nkey\xff").unwrap();
     /// ```
-    pub fn get<V: FromMemcacheValueExt>(&self, key: &str) -> Result<Option<V>, MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.get(key)
+    pub fn get<V: FromMemcacheValueExt>(&self, key: impl AsRef<[u8]>) -> Result<Option<V>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.get(&key)
     }
 
     /// Get multiple keys from memcached server. Using this function instead of calling `get` multiple times can reduce network workloads.
+    /// The result is keyed by the given keys, which may be of any bytes like with `get`.
     ///
     /// Example:
     ///
@@ -111,15 +431,169 @@ impl Client {
     /// .unwrap();
     /// let client = memcache::Client::with_pool(pool);
     /// client.set("foo", "42", 0).unwrap();
-    /// let result: std::collections::HashMap<String, String> = client.gets(&["foo", "bar", "baz"]).unwrap();
+    /// let result: std::collections::HashMap<&str, String> = client.gets(&["foo", "bar", "baz"]).unwrap();
     /// assert_eq!(result.len(), 1);
     /// assert_eq!(result["foo"], "42");
+    /// let result: std::collections::HashMap<&[u8], String> = client.gets(&[&b"foo"[..], b"bar\xff"]).unwrap();
+    /// assert_eq!(result[&b"foo"[..]], "42");
     /// ```
-    pub fn gets<V: FromMemcacheValueExt>(&self, keys: &[&str]) -> Result<HashMap<String, V>, MemcacheError> {
-        for key in keys {
-            check_key_len(key)?;
+    pub fn gets<V, K>(&self, keys: &[K]) -> Result<HashMap<K, V>, MemcacheError>
+    where
+        V: FromMemcacheValueExt,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let sent = self.keys(keys)?;
+        let callers = caller_keys(keys, &sent);
+        let mut result = HashMap::with_capacity(sent.len());
+        for (index, keys) in self.distribute_keys(&sent) {
+            for (key, value) in self.pools[index].get()?.gets(&keys)? {
+                result.insert(caller_key(&callers, &key)?, value);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get a key along with the metadata of its item: its flags, cas token and size, plus its remaining time
+    /// to live and last access time with the meta protocol.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345?protocol=meta").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("item_foo", "bar", 60).unwrap();
+    /// let item: memcache::Item<String> = client.get_item("item_foo").unwrap().unwrap();
+    /// assert_eq!(item.value, "bar");
+    /// assert_eq!(item.size, 3);
+    /// assert!(item.cas.is_some());
+    /// assert!(item.ttl_remaining.unwrap() <= 60);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn get_item<V: FromMemcacheValueExt>(&self, key: impl AsRef<[u8]>) -> Result<Option<Item<V>>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        match self.connection(&key)?.get_items(&[&key])?.remove(key.as_ref()) {
+            Some(item) => Ok(Some(item.decode()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get multiple keys along with the metadata of their items, like `get_item`, with one request per server.
+    /// Missing keys are left out of the result.
+    pub fn get_items<V, K>(&self, keys: &[K]) -> Result<HashMap<K, Item<V>>, MemcacheError>
+    where
+        V: FromMemcacheValueExt,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let sent = self.keys(keys)?;
+        let callers = caller_keys(keys, &sent);
+        let mut result = HashMap::with_capacity(sent.len());
+        for (index, keys) in self.distribute_keys(&sent) {
+            for (key, item) in self.pools[index].get()?.get_items(&keys)? {
+                result.insert(caller_key(&callers, &key)?, item.decode()?);
+            }
         }
-        self.get_connection()?.gets(keys)
+        Ok(result)
+    }
+
+    /// Get a key and update its expiration in a single round trip, for sliding expiry. The value is decoded with
+    /// its cas token, which a `(Vec<u8>, u32, Option<u64>)` value exposes.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("session", "data", 60).unwrap();
+    /// let value: Option<String> = client.get_and_touch("session", 600).unwrap();
+    /// assert_eq!(value, Some("data".into()));
+    /// let (_, _, cas): (Vec<u8>, u32, Option<u64>) = client.get_and_touch("session", 600).unwrap().unwrap();
+    /// assert!(cas.is_some());
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn get_and_touch<V: FromMemcacheValueExt>(
+        &self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.get_and_touch(&key, expiration)
+    }
+
+    /// Get multiple keys and update their expiration, like `get_and_touch`, with a single round trip per server.
+    /// Missing keys are left out of the result.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("session_a", "a", 60).unwrap();
+    /// let result: std::collections::HashMap<&str, String> =
+    ///     client.get_and_touch_multi(&["session_a", "session_b"], 600).unwrap();
+    /// assert_eq!(result.len(), 1);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn get_and_touch_multi<V, K>(&self, keys: &[K], expiration: u32) -> Result<HashMap<K, V>, MemcacheError>
+    where
+        V: FromMemcacheValueExt,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let sent = self.keys(keys)?;
+        let callers = caller_keys(keys, &sent);
+        let mut result = HashMap::with_capacity(sent.len());
+        for (index, keys) in self.distribute_keys(&sent) {
+            for (key, value) in self.pools[index].get()?.get_and_touch_multi(&keys, expiration)? {
+                result.insert(caller_key(&callers, &key)?, value);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get a key along with the item metadata selected by `options`, like its remaining time to live, the last
+    /// access time and whether it was fetched before. Only supported by the meta protocol.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345?protocol=meta").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("foo", "bar", 10).unwrap();
+    /// let options = memcache::MetaGetOptions {
+    ///     ttl: true,
+    ///     hit: true,
+    ///     ..Default::default()
+    /// };
+    /// let value: memcache::MetaValue<String> = client.meta_get("foo", &options).unwrap().unwrap();
+    /// assert_eq!(value.value, "bar");
+    /// assert_eq!(value.hit, Some(false));
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn meta_get<V: FromMemcacheValueExt>(
+        &self,
+        key: impl AsRef<[u8]>,
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.meta_get(&key, options)
+    }
+
+    /// Get the internal metadata memcached holds for a key, without fetching or bumping it. Only supported by
+    /// the meta protocol.
+    pub fn meta_debug(&self, key: impl AsRef<[u8]>) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.meta_debug(&key)
     }
 
     /// Set a key with associate value into memcached server with expiration seconds.
@@ -135,9 +609,14 @@ impl Client {
     /// client.set("foo", "bar", 10).unwrap();
     /// # client.flush().unwrap();
     /// ```
-    pub fn set<V: ToMemcacheValue<Stream>>(&self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.set(key, value, expiration)
+    pub fn set<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.set(&key, value, expiration)
     }
 
     /// Compare and swap a key with the associate value into memcached server with expiration seconds.
@@ -153,7 +632,7 @@ impl Client {
     /// .unwrap();
     /// let client = memcache::Client::with_pool(pool);
     /// client.set("foo", "bar", 10).unwrap();
-    /// let result: HashMap<String, (Vec<u8>, u32, Option<u64>)> = client.gets(&["foo"]).unwrap();
+    /// let result: HashMap<&str, (Vec<u8>, u32, Option<u64>)> = client.gets(&["foo"]).unwrap();
     /// let (_, _, cas) = result.get("foo").unwrap();
     /// let cas = cas.unwrap();
     /// assert_eq!(true, client.cas("foo", "bar2", 10, cas).unwrap());
@@ -161,13 +640,186 @@ impl Client {
     /// ```
     pub fn cas<V: ToMemcacheValue<Stream>>(
         &self,
-        key: &str,
+        key: impl AsRef<[u8]>,
         value: V,
         expiration: u32,
         cas_id: u64,
     ) -> Result<bool, MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.cas(key, value, expiration, cas_id)
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.cas(&key, value, expiration, cas_id)
+    }
+
+    /// Get a key, or compute and store its value when it is missing, making sure that a single caller at a time
+    /// computes it. With the meta protocol, the caller winning the lease of the key computes the value while the
+    /// others are served the stale value when there is one, or wait for the new value. A value with less than a
+    /// tenth of its expiration left is recomputed ahead of time by a single caller. The other protocols take a
+    /// lock with an `add` of the `<key>:lock` key instead. The lease or lock is released when `compute` fails,
+    /// and expires after 30 seconds if the caller holding it goes away. A caller waiting for the value of another
+    /// one fails with `MemcacheError::Timeout` once it has waited for `wait`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345?protocol=meta").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// # client.delete("report").unwrap();
+    /// let report: String = client
+    ///     .get_or_compute("report", 60, Duration::from_secs(5), || {
+    ///         Ok::<_, memcache::MemcacheError>("expensive".to_string())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(report, "expensive");
+    /// assert_eq!(client.get::<String>("report").unwrap(), Some(report));
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn get_or_compute<V, E, F>(
+        &self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+        wait: Duration,
+        compute: F,
+    ) -> Result<V, E>
+    where
+        V: FromMemcacheValueExt + ToMemcacheValue<Stream>,
+        E: From<MemcacheError>,
+        F: FnOnce() -> Result<V, E>,
+    {
+        let key = key.as_ref();
+        let namespaced = self.key(key)?;
+        if !matches!(self.connection(&namespaced)?.protocol, Protocol::Meta(_)) {
+            return self.get_or_compute_with_lock(key, expiration, wait, compute);
+        }
+        let recache = Some(expiration / 10).filter(|recache| *recache > 0);
+        let deadline = Instant::now() + wait;
+        let mut attempts = 0;
+        loop {
+            // the connection goes back to the pool while computing, which may use the client too
+            let lease = self
+                .connection(&namespaced)?
+                .lease_get(&namespaced, COMPUTE_LEASE, recache)?;
+            if lease.win {
+                return match compute() {
+                    Ok(value) => {
+                        self.set(key, Borrowed(&value), expiration)?;
+                        Ok(value)
+                    }
+                    Err(e) => {
+                        // the lease expires anyway if it can't be released
+                        let _ = self
+                            .connection(&namespaced)
+                            .and_then(|mut c| c.release_lease(&namespaced, &lease));
+                        Err(e)
+                    }
+                };
+            }
+            if let Some((value, flags)) = lease.value {
+                return Ok(V::from_memcache_value(value, flags, lease.cas)?);
+            }
+            attempts += 1;
+            wait_until(deadline, attempts)?;
+        }
+    }
+
+    fn get_or_compute_with_lock<V, E, F>(&self, key: &[u8], expiration: u32, wait: Duration, compute: F) -> Result<V, E>
+    where
+        V: FromMemcacheValueExt + ToMemcacheValue<Stream>,
+        E: From<MemcacheError>,
+        F: FnOnce() -> Result<V, E>,
+    {
+        let lock = [key, b":lock"].concat();
+        self.key(&lock)?;
+        let options = StoreOptions {
+            expiration: COMPUTE_LEASE,
+            ..Default::default()
+        };
+        let deadline = Instant::now() + wait;
+        let mut attempts = 0;
+        loop {
+            if let Some(value) = self.get(key)? {
+                return Ok(value);
+            }
+            if self.add_with(&lock, "1", &options)? {
+                let result = compute();
+                let stored = match &result {
+                    Ok(value) => self.set(key, Borrowed(value), expiration),
+                    Err(_) => Ok(()),
+                };
+                let released = self.delete(&lock);
+                stored?;
+                released?;
+                return result;
+            }
+            attempts += 1;
+            wait_until(deadline, attempts)?;
+        }
+    }
+
+    /// Read-modify-write a key with optimistic locking. The current value, or `None` when the key is missing, is
+    /// passed to `f`, and the value it returns is stored with the cas token that was read, or with `add` when the
+    /// key was missing. When another client changed the key in the meantime, this is retried up to `max_retries`
+    /// times with a growing backoff, and then fails with `CommandError::KeyExists`. Returning `None` from `f`
+    /// leaves the item unchanged.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// # client.delete("visits").unwrap();
+    /// let update = client.update("visits", 0, 5, |visits: Option<u64>| Some(visits.unwrap_or(0) + 1)).unwrap();
+    /// assert_eq!(update.value, Some(1));
+    /// assert_eq!(update.attempts, 1);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn update<V, F>(
+        &self,
+        key: impl AsRef<[u8]>,
+        expiration: u32,
+        max_retries: u32,
+        mut f: F,
+    ) -> Result<Update<V>, MemcacheError>
+    where
+        V: FromMemcacheValueExt + ToMemcacheValue<Stream>,
+        F: FnMut(Option<V>) -> Option<V>,
+    {
+        let key = key.as_ref();
+        self.key(key)?;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (current, cas) = decode_update(self.gets(&[key])?.remove(key))?;
+            let value = match f(current) {
+                Some(value) => value,
+                None => return Ok(Update { value: None, attempts }),
+            };
+            let options = StoreOptions {
+                expiration,
+                cas,
+                ..Default::default()
+            };
+            let stored = match cas {
+                Some(_) => self.set_with(key, Borrowed(&value), &options)?,
+                None => self.add_with(key, Borrowed(&value), &options)?,
+            };
+            if stored {
+                return Ok(Update {
+                    value: Some(value),
+                    attempts,
+                });
+            }
+            if attempts > max_retries {
+                Err(CommandError::KeyExists)?
+            }
+            thread::sleep(update_backoff(attempts));
+        }
     }
 
     /// Add a key with associate value into memcached server with expiration seconds.
@@ -185,9 +837,14 @@ impl Client {
     /// client.add(key, "bar", 100000000).unwrap();
     /// # client.flush().unwrap();
     /// ```
-    pub fn add<V: ToMemcacheValue<Stream>>(&self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.add(key, value, expiration)
+    pub fn add<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.add(&key, value, expiration)
     }
 
     /// Replace a key with associate value into memcached server with expiration seconds.
@@ -207,12 +864,12 @@ impl Client {
     /// ```
     pub fn replace<V: ToMemcacheValue<Stream>>(
         &self,
-        key: &str,
+        key: impl AsRef<[u8]>,
         value: V,
         expiration: u32,
     ) -> Result<(), MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.replace(key, value, expiration)
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.replace(&key, value, expiration)
     }
 
     /// Append value to the key.
@@ -232,9 +889,9 @@ impl Client {
     /// assert_eq!(result, "hello, world!");
     /// # client.flush().unwrap();
     /// ```
-    pub fn append<V: ToMemcacheValue<Stream>>(&self, key: &str, value: V) -> Result<(), MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.append(key, value)
+    pub fn append<V: ToMemcacheValue<Stream>>(&self, key: impl AsRef<[u8]>, value: V) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.append(&key, value)
     }
 
     /// Prepend value to the key.
@@ -254,9 +911,112 @@ impl Client {
     /// assert_eq!(result, "hello, world!");
     /// # client.flush().unwrap();
     /// ```
-    pub fn prepend<V: ToMemcacheValue<Stream>>(&self, key: &str, value: V) -> Result<(), MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.prepend(key, value)
+    pub fn prepend<V: ToMemcacheValue<Stream>>(&self, key: impl AsRef<[u8]>, value: V) -> Result<(), MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.prepend(&key, value)
+    }
+
+    /// Set a key with the expiration, client flags, noreply and cas token of `options`. Returns whether the
+    /// value was stored, which is `false` when the cas token doesn't match, and always `true` with noreply.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use memcache::StoreOptions;
+    ///
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// let options = StoreOptions {
+    ///     expiration: 10,
+    ///     flags: Some(0x100),
+    ///     ..Default::default()
+    /// };
+    /// assert!(client.set_with("set_with", "bar", &options).unwrap());
+    /// let (_, flags, cas): (Vec<u8>, u32, Option<u64>) = client.get("set_with").unwrap().unwrap();
+    /// assert_eq!(flags, 0x100);
+    /// let options = StoreOptions {
+    ///     cas: Some(cas.unwrap_or(0) + 1),
+    ///     ..Default::default()
+    /// };
+    /// assert!(!client.set_with("set_with", "baz", &options).unwrap());
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn set_with<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.set_with(&key, value, options)
+    }
+
+    /// Add a key with the options of `options`, see [`set_with`](Client::set_with). Returns `false` if the key
+    /// already exists.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use memcache::StoreOptions;
+    ///
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.delete("add_with").unwrap();
+    /// assert!(client.add_with("add_with", "bar", &StoreOptions::default()).unwrap());
+    /// assert!(!client.add_with("add_with", "baz", &StoreOptions::default()).unwrap());
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn add_with<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.add_with(&key, value, options)
+    }
+
+    /// Replace a key with the options of `options`, see [`set_with`](Client::set_with). Returns `false` if the
+    /// key doesn't exist.
+    pub fn replace_with<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.replace_with(&key, value, options)
+    }
+
+    /// Append value to the key with the noreply and cas token of `options`, the expiration and flags of the item
+    /// are left untouched. Returns `false` if the key doesn't exist. A cas token is not supported by the ascii
+    /// protocol on append.
+    pub fn append_with<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.append_with(&key, value, options)
+    }
+
+    /// Prepend value to the key with the noreply and cas token of `options`, like
+    /// [`append_with`](Client::append_with).
+    pub fn prepend_with<V: ToMemcacheValue<Stream>>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.prepend_with(&key, value, options)
     }
 
     /// Delete a key from memcached server.
@@ -272,12 +1032,13 @@ impl Client {
     /// client.delete("foo").unwrap();
     /// # client.flush().unwrap();
     /// ```
-    pub fn delete(&self, key: &str) -> Result<bool, MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.delete(key)
+    pub fn delete(&self, key: impl AsRef<[u8]>) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.delete(&key)
     }
 
-    /// Increment the value with amount.
+    /// Increment the value with amount. A missing key is an error with every protocol, use `increment_or_init` to
+    /// create counters.
     ///
     /// Example:
     ///
@@ -287,15 +1048,17 @@ impl Client {
     /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
     /// .unwrap();
     /// let client = memcache::Client::with_pool(pool);
+    /// client.set("counter", 100, 0).unwrap();
     /// client.increment("counter", 42).unwrap();
     /// # client.flush().unwrap();
     /// ```
-    pub fn increment(&self, key: &str, amount: u64) -> Result<u64, MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.increment(key, amount)
+    pub fn increment(&self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.increment(&key, amount)
     }
 
-    /// Decrement the value with amount.
+    /// Decrement the value with amount. A missing key is an error with every protocol, use `increment_or_init` to
+    /// create counters.
     ///
     /// Example:
     ///
@@ -305,12 +1068,43 @@ impl Client {
     /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
     /// .unwrap();
     /// let client = memcache::Client::with_pool(pool);
+    /// client.set("counter", 100, 0).unwrap();
     /// client.decrement("counter", 42).unwrap();
     /// # client.flush().unwrap();
     /// ```
-    pub fn decrement(&self, key: &str, amount: u64) -> Result<u64, MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.decrement(key, amount)
+    pub fn decrement(&self, key: impl AsRef<[u8]>, amount: u64) -> Result<u64, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.decrement(&key, amount)
+    }
+
+    /// Increment the value with amount, or create it with the initial value and expiration when it is missing.
+    /// The binary and meta protocols create the counter atomically, the ascii protocol falls back to `add`. The
+    /// new value is returned, which is the initial value when the counter was created. An expiration of
+    /// `u32::MAX` is rejected with a `ClientError`, as the binary protocol takes it as not to create the counter.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// # client.delete("requests").unwrap();
+    /// assert_eq!(client.increment_or_init("requests", 1, 1, 60).unwrap(), 1);
+    /// assert_eq!(client.increment_or_init("requests", 1, 1, 60).unwrap(), 2);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn increment_or_init(
+        &self,
+        key: impl AsRef<[u8]>,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?
+            .increment_or_init(&key, amount, initial, expiration)
     }
 
     /// Set a new expiration time for a exist key.
@@ -328,12 +1122,169 @@ impl Client {
     /// assert_eq!(client.touch("foo", 12345).unwrap(), true);
     /// # client.flush().unwrap();
     /// ```
-    pub fn touch(&self, key: &str, expiration: u32) -> Result<bool, MemcacheError> {
-        check_key_len(key)?;
-        self.get_connection()?.touch(key, expiration)
+    pub fn touch(&self, key: impl AsRef<[u8]>, expiration: u32) -> Result<bool, MemcacheError> {
+        let key = self.key(key.as_ref())?;
+        self.connection(&key)?.touch(&key, expiration)
+    }
+
+    /// Set multiple keys with one round trip per server. The outcome of each key is whether its value was
+    /// stored, or the error the server answered for it, while the outer error is returned when a connection
+    /// failed. The binary protocol sends the writes with its quiet opcodes, and the other protocols pipeline them.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// let outcomes = client.set_multi(&[("multi_a", "a"), ("multi_b", "b")], 60).unwrap();
+    /// assert!(outcomes.values().all(|outcome| *outcome.as_ref().unwrap()));
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn set_multi<V, K>(
+        &self,
+        items: &[(K, V)],
+        expiration: u32,
+    ) -> Result<HashMap<K, Result<bool, MemcacheError>>, MemcacheError>
+    where
+        V: ToMemcacheValue<Vec<u8>>,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        self.store_multi(StoreCommand::Set, items, expiration)
+    }
+
+    /// Add multiple keys with one round trip per server, like `set_multi`. The outcome of a key which already
+    /// exists is `false`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("add_multi_a", "a", 60).unwrap();
+    /// # client.delete("add_multi_b").unwrap();
+    /// let outcomes = client.add_multi(&[("add_multi_a", "a"), ("add_multi_b", "b")], 60).unwrap();
+    /// assert_eq!(outcomes["add_multi_a"].as_ref().unwrap(), &false);
+    /// assert_eq!(outcomes["add_multi_b"].as_ref().unwrap(), &true);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn add_multi<V, K>(
+        &self,
+        items: &[(K, V)],
+        expiration: u32,
+    ) -> Result<HashMap<K, Result<bool, MemcacheError>>, MemcacheError>
+    where
+        V: ToMemcacheValue<Vec<u8>>,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        self.store_multi(StoreCommand::Add, items, expiration)
     }
 
-    /// Get all servers' statistics.
+    /// Delete multiple keys with one round trip per server. The outcome of each key is whether it was found.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("delete_multi_a", "a", 60).unwrap();
+    /// let outcomes = client.delete_multi(&["delete_multi_a", "not_exists_key"]).unwrap();
+    /// assert_eq!(outcomes["delete_multi_a"].as_ref().unwrap(), &true);
+    /// assert_eq!(outcomes["not_exists_key"].as_ref().unwrap(), &false);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn delete_multi<K>(&self, keys: &[K]) -> Result<HashMap<K, Result<bool, MemcacheError>>, MemcacheError>
+    where
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let keys: Vec<&K> = keys.iter().collect();
+        self.write_multi(&keys, |_, key, _| Command::Delete(key.into()))
+    }
+
+    /// Set a new expiration time for multiple keys with one round trip per server. The outcome of each key is
+    /// whether it was found.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("touch_multi_a", "a", 60).unwrap();
+    /// let outcomes = client.touch_multi(&["touch_multi_a", "not_exists_key"], 600).unwrap();
+    /// assert_eq!(outcomes["touch_multi_a"].as_ref().unwrap(), &true);
+    /// assert_eq!(outcomes["not_exists_key"].as_ref().unwrap(), &false);
+    /// # client.flush().unwrap();
+    /// ```
+    pub fn touch_multi<K>(
+        &self,
+        keys: &[K],
+        expiration: u32,
+    ) -> Result<HashMap<K, Result<bool, MemcacheError>>, MemcacheError>
+    where
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let keys: Vec<&K> = keys.iter().collect();
+        self.write_multi(&keys, |_, key, _| Command::Touch(key.into(), expiration))
+    }
+
+    fn store_multi<V, K>(
+        &self,
+        command: StoreCommand,
+        items: &[(K, V)],
+        expiration: u32,
+    ) -> Result<HashMap<K, Result<bool, MemcacheError>>, MemcacheError>
+    where
+        V: ToMemcacheValue<Vec<u8>>,
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+    {
+        let keys: Vec<&K> = items.iter().map(|(key, _)| key).collect();
+        self.write_multi(&keys, |connection, key, index| {
+            let value = &items[index].1;
+            pipeline::store_command(connection, command, key, Borrowed(value), expiration)
+        })
+    }
+
+    /// Send the write built for each key, given along with its index, to the server owning it, with one round
+    /// trip per server.
+    fn write_multi<K, F>(
+        &self,
+        keys: &[&K],
+        command: F,
+    ) -> Result<HashMap<K, Result<bool, MemcacheError>>, MemcacheError>
+    where
+        K: AsRef<[u8]> + Clone + Eq + Hash,
+        F: Fn(&Connection, &[u8], usize) -> Command,
+    {
+        let namespaced = self.keys(keys)?;
+        let mut distribution: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, key) in namespaced.iter().enumerate() {
+            distribution.entry(self.server_index(key)).or_default().push(index);
+        }
+        let mut result = HashMap::with_capacity(keys.len());
+        for (server, indexes) in distribution {
+            let mut connection = self.pools[server].get()?;
+            let commands = indexes
+                .iter()
+                .map(|&index| command(&connection, &namespaced[index], index))
+                .collect();
+            let outcomes = pipeline::write_multi(&mut connection, commands)?;
+            result.extend(indexes.iter().map(|&index| keys[index].clone()).zip(outcomes));
+        }
+        Ok(result)
+    }
+
+    /// Get all servers' statistics, paired with the server url.
     ///
     /// Example:
     /// ```rust
@@ -344,8 +1295,14 @@ impl Client {
     /// let client = memcache::Client::with_pool(pool);
     /// let stats = client.stats().unwrap();
     /// ```
-    pub fn stats(&self) -> Result<Stats, MemcacheError> {
-        self.get_connection()?.stats()
+    pub fn stats(&self) -> Result<Vec<(String, Stats)>, MemcacheError> {
+        let mut result = Vec::with_capacity(self.pools.len());
+        for pool in &self.pools {
+            let mut connection = pool.get()?;
+            let url = connection.url.to_string();
+            result.push((url, connection.stats()?));
+        }
+        Ok(result)
     }
 }
 
@@ -367,14 +1324,14 @@ mod tests {
     #[test]
     fn unix() {
         let client = connect("memcache:///tmp/memcached.sock").unwrap();
-        assert!(client.version().unwrap() != "");
+        assert!(client.version().unwrap()[0].1 != "");
     }
 
     #[cfg(feature = "tls")]
     #[test]
     fn ssl_noverify() {
         let client = connect("memcache+tls://localhost:12350?verify_mode=none").unwrap();
-        assert!(client.version().unwrap() != "");
+        assert!(client.version().unwrap()[0].1 != "");
     }
 
     #[cfg(feature = "tls")]
@@ -382,14 +1339,86 @@ mod tests {
     fn ssl_verify() {
         let client =
             connect("memcache+tls://localhost:12350?ca_path=tests/assets/RUST_MEMCACHE_TEST_CERT.crt").unwrap();
-        assert!(client.version().unwrap() != "");
+        assert!(client.version().unwrap()[0].1 != "");
     }
 
     #[cfg(feature = "tls")]
     #[test]
     fn ssl_client_certs() {
         let client = connect("memcache+tls://localhost:12351?key_path=tests/assets/client.key&cert_path=tests/assets/client.crt&ca_path=tests/assets/RUST_MEMCACHE_TEST_CERT.crt").unwrap();
-        assert!(client.version().unwrap() != "");
+        assert!(client.version().unwrap()[0].1 != "");
+    }
+
+    #[test]
+    fn distribute_keys() {
+        let pools = (12345..12348)
+            .map(|port| {
+                r2d2::Pool::builder()
+                    .build_unchecked(ConnectionManager::new(format!("memcache://localhost:{}", port)).unwrap())
+            })
+            .collect();
+        let mut client = Client::with_pools(pools).unwrap();
+        client.hash_function = |key: &[u8]| -> u64 { key.len() as u64 };
+        let distribution = client.distribute_keys(&["a", "bb", "ccc", "dddd"]);
+        assert_eq!(distribution[&0], vec![&b"ccc"[..]]);
+        assert_eq!(distribution[&1], vec![&b"a"[..], &b"dddd"[..]]);
+        assert_eq!(distribution[&2], vec![&b"bb"[..]]);
+
+        assert!(Client::with_pools(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn hash_bytes() {
+        // text keys hash like a str, keys which aren't UTF-8 are hashed as they are
+        let mut hasher = DefaultHasher::new();
+        "foo".hash(&mut hasher);
+        assert_eq!(default_hash_function(b"foo"), hasher.finish());
+        assert_ne!(default_hash_function(b"foo\xfe"), default_hash_function(b"foo\xff"));
+
+        let pools = (12345..12347)
+            .map(|port| {
+                r2d2::Pool::builder()
+                    .build_unchecked(ConnectionManager::new(format!("memcache://localhost:{}", port)).unwrap())
+            })
+            .collect();
+        let mut client = Client::with_pools(pools).unwrap();
+        client.hash_function = |key: &[u8]| -> u64 { u64::from(key[0]) };
+        assert_eq!(client.server_index(b"\xfe"), 0);
+        assert_eq!(client.server_index(b"\xff"), 1);
+    }
+
+    #[test]
+    fn distribute_keys_with_ketama() {
+        let servers: Vec<(String, u32)> = (12345..12348)
+            .map(|port| (format!("memcache://localhost:{}", port), 1))
+            .collect();
+        let ring = Ketama::from_urls(&servers).unwrap();
+        let pools: Vec<_> = servers
+            .iter()
+            .map(|(url, _)| r2d2::Pool::builder().build_unchecked(ConnectionManager::new(url).unwrap()))
+            .collect();
+        assert!(Client::with_ketama(pools[..2].to_vec(), ring.clone()).is_err());
+
+        let client = Client::with_ketama(pools, ring.clone()).unwrap();
+        let keys = ["a", "bb", "ccc", "dddd", "eeeee"];
+        for (index, keys) in client.distribute_keys(&keys) {
+            for key in keys {
+                assert_eq!(ring.get_server(key), index);
+            }
+        }
+    }
+
+    #[test]
+    fn text_keys() {
+        assert_eq!(check_text_key(b"foo:bar").unwrap(), "foo:bar");
+        assert_eq!(check_text_key("clé".as_bytes()).unwrap(), "clé");
+        for key in [&b"foo bar"[..], b"foo\r\n", b"\tfoo", b"foo\x7f", b"\xff"] {
+            assert!(matches!(
+                check_text_key(key),
+                Err(MemcacheError::ClientError(ClientError::InvalidKey))
+            ));
+        }
+        assert!(check_text_key(&[b'a'; 251]).is_err());
     }
 
     #[test]
@@ -0,0 +1,246 @@
+//! Values serialized with serde, wrapped in a type naming their format. Each format reserves a bit of the
+//! flags word, so that reading a value back checks it was stored in the expected format.
+
+use std::io::{self, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{ClientError, MemcacheError, ParseError};
+use crate::value::{Flags, FromMemcacheValue, ToMemcacheValue};
+
+/// Bits of the flags word naming the format of a value.
+const FORMAT_MASK: u32 = Flags::Json as u32 | Flags::MsgPack as u32 | Flags::Bincode as u32 | Flags::Cbor as u32;
+
+/// Counts the bytes written to it, to get the length of an encoded value without buffering it.
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn check_flags(flags: u32, expected: Flags, format: &str) -> Result<(), MemcacheError> {
+    if flags & FORMAT_MASK != expected as u32 {
+        Err(ClientError::Error(
+            format!("value is not encoded as {}, found flags {:#x}", format, flags).into(),
+        ))?
+    }
+    Ok(())
+}
+
+fn decode_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> MemcacheError {
+    ParseError::Codec(Box::new(err)).into()
+}
+
+macro_rules! serde_value {
+    ($(#[$meta:meta])* $feature:literal, $name:ident, $flag:expr, $format:literal, $encode:path, $decode:path) => {
+        $(#[$meta])*
+        #[cfg(feature = $feature)]
+        #[derive(Clone, Debug, Default, PartialEq, Eq)]
+        pub struct $name<T>(pub T);
+
+        #[cfg(feature = $feature)]
+        impl<T: Serialize, W: Write> ToMemcacheValue<W> for $name<T> {
+            fn get_flags(&self) -> u32 {
+                $flag as u32
+            }
+
+            fn get_length(&self) -> usize {
+                // the value is encoded twice rather than buffered, an encoding error is returned by write_to
+                let mut counter = Counter(0);
+                let _ = $encode(&mut counter, &self.0);
+                counter.0
+            }
+
+            fn write_to(&self, stream: &mut W) -> io::Result<()> {
+                $encode(stream, &self.0)
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl<T: DeserializeOwned> FromMemcacheValue for $name<T> {
+            fn from_memcache_value(value: Vec<u8>, flags: u32) -> Result<Self, MemcacheError> {
+                check_flags(flags, $flag, $format)?;
+                Ok($name($decode(&value)?))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use super::*;
+
+    pub(super) fn encode<W: Write, T: Serialize>(writer: W, value: &T) -> io::Result<()> {
+        Ok(serde_json::to_writer(writer, value)?)
+    }
+
+    pub(super) fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, MemcacheError> {
+        serde_json::from_slice(data).map_err(decode_error)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use super::*;
+
+    pub(super) fn encode<W: Write, T: Serialize>(mut writer: W, value: &T) -> io::Result<()> {
+        // structs are encoded as maps, so that adding a field doesn't break reading the cached values
+        rmp_serde::encode::write_named(&mut writer, value).map_err(io::Error::other)
+    }
+
+    pub(super) fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, MemcacheError> {
+        rmp_serde::from_slice(data).map_err(decode_error)
+    }
+}
+
+#[cfg(feature = "bincode")]
+mod bincode {
+    use super::*;
+
+    pub(super) fn encode<W: Write, T: Serialize>(writer: W, value: &T) -> io::Result<()> {
+        ::bincode::serialize_into(writer, value).map_err(io::Error::other)
+    }
+
+    pub(super) fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, MemcacheError> {
+        ::bincode::deserialize(data).map_err(decode_error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::*;
+
+    pub(super) fn encode<W: Write, T: Serialize>(writer: W, value: &T) -> io::Result<()> {
+        ciborium::into_writer(value, writer).map_err(|e| match e {
+            ciborium::ser::Error::Io(e) => e,
+            e => io::Error::other(e.to_string()),
+        })
+    }
+
+    pub(super) fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, MemcacheError> {
+        ciborium::from_reader(data).map_err(|e| decode_error(io::Error::other(e.to_string())))
+    }
+}
+
+serde_value!(
+    /// A value stored as JSON, with the `json` feature.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use memcache::Json;
+    ///
+    /// let pool = memcache::Pool::builder()
+    /// .connection_timeout(std::time::Duration::from_secs(1))
+    /// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+    /// .unwrap();
+    /// let client = memcache::Client::with_pool(pool);
+    /// client.set("json", Json(vec![1, 2, 3]), 0).unwrap();
+    /// let value: Option<Json<Vec<u32>>> = client.get("json").unwrap();
+    /// assert_eq!(value, Some(Json(vec![1, 2, 3])));
+    /// ```
+    "json", Json, Flags::Json, "json", json::encode, json::decode
+);
+
+serde_value!(
+    /// A value stored as MessagePack, with the `msgpack` feature.
+    "msgpack", MsgPack, Flags::MsgPack, "msgpack", msgpack::encode, msgpack::decode
+);
+
+serde_value!(
+    /// A value stored with bincode, with the `bincode` feature.
+    "bincode", Bincode, Flags::Bincode, "bincode", bincode::encode, bincode::decode
+);
+
+serde_value!(
+    /// A value stored as CBOR, with the `cbor` feature.
+    "cbor", Cbor, Flags::Cbor, "cbor", cbor::encode, cbor::decode
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+        scores: HashMap<String, f64>,
+    }
+
+    fn item() -> Item {
+        Item {
+            id: 42,
+            name: "memcache".into(),
+            tags: vec!["cache".into(), "rust".into()],
+            scores: vec![("hit".to_string(), 0.99)].into_iter().collect(),
+        }
+    }
+
+    fn round_trip<V>(value: V) -> (u32, Result<V, MemcacheError>)
+    where
+        V: ToMemcacheValue<Vec<u8>> + FromMemcacheValue,
+    {
+        let mut encoded = Vec::new();
+        value.write_to(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), value.get_length());
+        let flags = value.get_flags();
+        (flags, V::from_memcache_value(encoded, flags))
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let (flags, value) = round_trip(Json(item()));
+        assert_eq!(flags, Flags::Json as u32);
+        assert_eq!(value.unwrap(), Json(item()));
+        let value: Result<Json<Item>, _> = FromMemcacheValue::from_memcache_value(b"{}".to_vec(), Flags::Json as u32);
+        assert!(matches!(value, Err(MemcacheError::ParseError(ParseError::Codec(_)))));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let (flags, value) = round_trip(MsgPack(item()));
+        assert_eq!(flags, Flags::MsgPack as u32);
+        assert_eq!(value.unwrap(), MsgPack(item()));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode() {
+        let (flags, value) = round_trip(Bincode(item()));
+        assert_eq!(flags, Flags::Bincode as u32);
+        assert_eq!(value.unwrap(), Bincode(item()));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        let (flags, value) = round_trip(Cbor(item()));
+        assert_eq!(flags, Flags::Cbor as u32);
+        assert_eq!(value.unwrap(), Cbor(item()));
+    }
+
+    #[cfg(all(feature = "json", feature = "msgpack"))]
+    #[test]
+    fn test_format_mismatch() {
+        let mut encoded = Vec::new();
+        ToMemcacheValue::<Vec<u8>>::write_to(&MsgPack(item()), &mut encoded).unwrap();
+        let value: Result<Json<Item>, _> = FromMemcacheValue::from_memcache_value(encoded, Flags::MsgPack as u32);
+        assert!(matches!(value, Err(MemcacheError::ClientError(_))));
+        let value: Result<Json<Item>, _> = FromMemcacheValue::from_memcache_value(b"{}".to_vec(), 0);
+        assert!(matches!(value, Err(MemcacheError::ClientError(_))));
+    }
+}
@@ -0,0 +1,264 @@
+//! Compression of large values, enabled from the URL with `compression=zstd`, `lz4` or `gzip` (each behind the
+//! cargo feature of the same name) and `compression_threshold`, the size in bytes above which values are
+//! compressed, 1024 by default. A compressed value is marked with the bit of its algorithm in the flags word and
+//! decompressed when it's read back, whatever the settings of the reading connection, as long as the feature of
+//! the algorithm is enabled. Bits of the algorithms left out of the build are kept as is, so values flagged by
+//! other clients, like the 0x10 compressed bit of PHP's memcached, are read back as they were stored.
+
+use std::io;
+
+use url::Url;
+
+use crate::error::MemcacheError;
+use crate::value::{Flags, ToMemcacheValue};
+
+const DEFAULT_THRESHOLD: usize = 1024;
+
+/// Bits of the flags word marking a compressed value.
+pub(crate) const COMPRESSION_MASK: u32 = Flags::Zstd as u32 | Flags::Lz4 as u32 | Flags::Gzip as u32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    Zstd,
+    Lz4,
+    Gzip,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Algorithm::Zstd),
+            #[cfg(feature = "lz4")]
+            "lz4" => Some(Algorithm::Lz4),
+            #[cfg(feature = "gzip")]
+            "gzip" => Some(Algorithm::Gzip),
+            _ => None,
+        }
+    }
+
+    fn from_flags(flags: u32) -> Option<Self> {
+        match flags & COMPRESSION_MASK {
+            0 => None,
+            f if f == Flags::Zstd as u32 => Some(Algorithm::Zstd),
+            f if f == Flags::Lz4 as u32 => Some(Algorithm::Lz4),
+            f if f == Flags::Gzip as u32 => Some(Algorithm::Gzip),
+            _ => None,
+        }
+    }
+
+    fn flag(self) -> u32 {
+        match self {
+            Algorithm::Zstd => Flags::Zstd as u32,
+            Algorithm::Lz4 => Flags::Lz4 as u32,
+            Algorithm::Gzip => Flags::Gzip as u32,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Zstd => "zstd",
+            Algorithm::Lz4 => "lz4",
+            Algorithm::Gzip => "gzip",
+        }
+    }
+
+    // data is unused when no compression feature is enabled
+    #[allow(unused_variables)]
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+            #[cfg(feature = "lz4")]
+            Algorithm::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => {
+                use std::io::Write;
+
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(io::Error::other(format!("{} compression is not enabled", self.name()))),
+        }
+    }
+
+    /// Decompress a value, or `None` if the feature of the algorithm isn't enabled.
+    #[allow(unused_variables)]
+    fn decompress(self, data: &[u8]) -> Option<Result<Vec<u8>, MemcacheError>> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => Some(zstd::stream::decode_all(data).map_err(MemcacheError::from)),
+            #[cfg(feature = "lz4")]
+            Algorithm::Lz4 => {
+                Some(lz4_flex::decompress_size_prepended(data).map_err(|e| MemcacheError::from(io::Error::other(e))))
+            }
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => {
+                use std::io::Read;
+
+                let mut decompressed = Vec::new();
+                Some(
+                    flate2::read::GzDecoder::new(data)
+                        .read_to_end(&mut decompressed)
+                        .map(|_| decompressed)
+                        .map_err(MemcacheError::from),
+                )
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Compression {
+    algorithm: Algorithm,
+    threshold: usize,
+}
+
+impl Compression {
+    pub(crate) fn from_url(url: &Url) -> Result<Option<Self>, MemcacheError> {
+        let mut algorithm = None;
+        let mut threshold = DEFAULT_THRESHOLD;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "compression" => {
+                    algorithm = Some(Algorithm::from_name(&value).ok_or_else(|| {
+                        MemcacheError::BadURL(format!(
+                        "unknown compression '{}', expected 'zstd', 'lz4' or 'gzip' with the feature of the same name",
+                        value
+                    ))
+                    })?)
+                }
+                "compression_threshold" => {
+                    threshold = value.parse().map_err(|_| {
+                        MemcacheError::BadURL(format!(
+                            "compression_threshold should be a size in bytes, found '{}'",
+                            value
+                        ))
+                    })?
+                }
+                _ => (),
+            }
+        }
+        Ok(algorithm.map(|algorithm| Compression { algorithm, threshold }))
+    }
+
+    /// Whether a value of this length is to be compressed.
+    pub(crate) fn applies(&self, length: usize) -> bool {
+        length > self.threshold
+    }
+
+    /// Compress an encoded value, keeping it raw if it doesn't shrink.
+    pub(crate) fn encode(&self, data: Vec<u8>, flags: u32) -> Encoded {
+        match self.algorithm.compress(&data) {
+            Ok(compressed) if compressed.len() < data.len() => Encoded {
+                data: compressed,
+                flags: flags | self.algorithm.flag(),
+            },
+            _ => Encoded { data, flags },
+        }
+    }
+}
+
+/// Undo the compression of a value, returning it with the compression bit cleared from its flags. Values with
+/// no compression bit, or the bit of an algorithm which isn't enabled, are returned as is.
+pub(crate) fn decompress(value: Vec<u8>, flags: u32) -> Result<(Vec<u8>, u32), MemcacheError> {
+    match Algorithm::from_flags(flags).and_then(|algorithm| algorithm.decompress(&value)) {
+        Some(decompressed) => Ok((decompressed?, flags & !COMPRESSION_MASK)),
+        None => Ok((value, flags)),
+    }
+}
+
+/// A value already encoded, and compressed if it was worth it.
+pub(crate) struct Encoded {
+    data: Vec<u8>,
+    flags: u32,
+}
+
+impl<W: io::Write> ToMemcacheValue<W> for Encoded {
+    fn get_flags(&self) -> u32 {
+        self.flags
+    }
+
+    fn get_length(&self) -> usize {
+        self.data.len()
+    }
+
+    fn write_to(&self, stream: &mut W) -> io::Result<()> {
+        stream.write_all(&self.data)
+    }
+}
+
+impl Encoded {
+    pub(crate) fn into_parts(self) -> (Vec<u8>, u32) {
+        (self.data, self.flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compression(url: &str) -> Result<Option<Compression>, MemcacheError> {
+        Compression::from_url(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_compression_url() {
+        assert_eq!(compression("memcache://localhost:12345").unwrap(), None);
+        assert!(matches!(
+            compression("memcache://localhost:12345?compression=brotli"),
+            Err(MemcacheError::BadURL(_))
+        ));
+        assert!(matches!(
+            compression("memcache://localhost:12345?compression_threshold=big"),
+            Err(MemcacheError::BadURL(_))
+        ));
+    }
+
+    #[test]
+    fn test_uncompressed() {
+        let (value, flags) = decompress(b"raw".to_vec(), 0x1).unwrap();
+        assert_eq!(value, b"raw");
+        assert_eq!(flags, 0x1);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_algorithm_not_enabled() {
+        let (value, flags) = decompress(b"raw".to_vec(), 0x10).unwrap();
+        assert_eq!(value, b"raw");
+        assert_eq!(flags, 0x10);
+    }
+
+    #[cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+    #[test]
+    fn test_round_trip() {
+        let large = "memcache ".repeat(1000).into_bytes();
+        for name in &["zstd", "lz4", "gzip"] {
+            let url = format!(
+                "memcache://localhost:12345?compression={}&compression_threshold=100",
+                name
+            );
+            let compression = match compression(&url) {
+                Ok(Some(compression)) => compression,
+                _ => continue,
+            };
+            assert!(!compression.applies(100));
+            assert!(compression.applies(101));
+
+            let (data, flags) = compression.encode(large.clone(), 0x1).into_parts();
+            assert!(data.len() < large.len());
+            assert_eq!(flags, 0x1 | compression.algorithm.flag());
+            assert_eq!(decompress(data, flags).unwrap(), (large.clone(), 0x1));
+
+            // incompressible values are stored raw
+            let noise: Vec<u8> = (0..200).map(|_| rand::random::<u8>()).collect();
+            let (data, flags) = compression.encode(noise.clone(), 0).into_parts();
+            assert_eq!((data, flags), (noise, 0));
+        }
+    }
+}
@@ -1,16 +1,26 @@
 use std::collections::HashMap;
-use std::net::TcpStream;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
 use std::ops::{Deref, DerefMut};
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
 use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
-use crate::error::MemcacheError;
-
-use crate::protocol::{AsciiProtocol, BinaryProtocol, Protocol, ProtocolTrait};
-use crate::stream::Stream;
-use crate::stream::UdpStream;
+use crate::client::Stats;
+use crate::compression::{Compression, Encoded};
+use crate::error::{ClientError, MemcacheError};
+use crate::pipeline::{Pipeline, QuietBatch};
+
+use crate::protocol::ascii::StoreCommand;
+use crate::protocol::meta::Lease;
+use crate::protocol::{
+    check_counter_expiration, AsciiProtocol, BinaryProtocol, Item, MetaGetOptions, MetaProtocol, MetaValue, Protocol,
+    ProtocolTrait, StoreOptions,
+};
+use crate::stream::{Socket, Stream, UdpStream};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
 #[cfg(feature = "tls")]
 use openssl::ssl::{SslConnector, SslFiletype, SslMethod, SslVerifyMode};
 use r2d2::ManageConnection;
@@ -19,6 +29,8 @@ use r2d2::ManageConnection;
 pub struct Connection {
     pub protocol: Protocol,
     pub url: Arc<String>,
+    poisoned: bool,
+    pub(crate) compression: Option<Compression>,
 }
 
 impl DerefMut for Connection {
@@ -34,6 +46,131 @@ impl Deref for Connection {
     }
 }
 
+// Commands go through the connection rather than the protocol it derefs to, to track its state.
+impl ProtocolTrait for Connection {
+    fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        self.track(|protocol| protocol.auth(username, password))
+    }
+
+    fn version(&mut self) -> Result<String, MemcacheError> {
+        self.track(|protocol| protocol.version())
+    }
+
+    fn flush(&mut self) -> Result<(), MemcacheError> {
+        self.track(|protocol| protocol.flush())
+    }
+
+    fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        self.track(|protocol| protocol.flush_with_delay(delay))
+    }
+
+    fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
+        self.track(|protocol| protocol.get(key))
+    }
+
+    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        self.track(|protocol| protocol.gets(keys))
+    }
+
+    fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        self.track(|protocol| protocol.get_items(keys))
+    }
+
+    fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        self.track(|protocol| protocol.get_and_touch(key, expiration))
+    }
+
+    fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        self.track(|protocol| protocol.get_and_touch_multi(keys, expiration))
+    }
+
+    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => self.track(|protocol| protocol.set(key, value, expiration)),
+            None => self.track(|protocol| protocol.set(key, value, expiration)),
+        }
+    }
+
+    fn cas<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => self.track(|protocol| protocol.cas(key, value, expiration, cas)),
+            None => self.track(|protocol| protocol.cas(key, value, expiration, cas)),
+        }
+    }
+
+    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => self.track(|protocol| protocol.add(key, value, expiration)),
+            None => self.track(|protocol| protocol.add(key, value, expiration)),
+        }
+    }
+
+    fn replace<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        match self.encode(&value, None)? {
+            Some(value) => self.track(|protocol| protocol.replace(key, value, expiration)),
+            None => self.track(|protocol| protocol.replace(key, value, expiration)),
+        }
+    }
+
+    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
+        self.track(|protocol| protocol.append(key, value))
+    }
+
+    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
+        self.track(|protocol| protocol.prepend(key, value))
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
+        self.track(|protocol| protocol.delete(key))
+    }
+
+    fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.track(|protocol| protocol.increment(key, amount))
+    }
+
+    fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.track(|protocol| protocol.decrement(key, amount))
+    }
+
+    fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        check_counter_expiration(expiration)?;
+        self.track(|protocol| protocol.increment_or_init(key, amount, initial, expiration))
+    }
+
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        self.track(|protocol| protocol.touch(key, expiration))
+    }
+
+    fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        self.track(|protocol| protocol.stats())
+    }
+}
+
 /// Memcache connection manager implementing rd2d Pool ManageConnection
 #[derive(Debug)]
 pub struct ConnectionManager {
@@ -48,9 +185,18 @@ impl ConnectionManager {
     }
 
     /// Set ASCII protocol
-    pub fn set_ascii_protocol(mut self) -> Self {
+    pub fn set_ascii_protocol(self) -> Self {
+        self.set_protocol("ascii")
+    }
+
+    /// Set meta protocol
+    pub fn set_meta_protocol(self) -> Self {
+        self.set_protocol("meta")
+    }
+
+    fn set_protocol(mut self, protocol: &str) -> Self {
         let mut query_pairs: HashMap<String, String> = self.url.query_pairs().into_owned().collect();
-        let _ = query_pairs.insert("protocol".into(), "ascii".into());
+        let _ = query_pairs.insert("protocol".into(), protocol.into());
 
         {
             let mut query_pairs_mut = self.url.query_pairs_mut();
@@ -83,13 +229,12 @@ impl ManageConnection for ConnectionManager {
         conn.version().map(|_| ())
     }
 
-    fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
-        // TODO: fix this
-        false
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.is_broken()
     }
 }
 
-enum Transport {
+pub(crate) enum Transport {
     Tcp(TcpOptions),
     Udp,
     #[cfg(unix)]
@@ -99,16 +244,60 @@ enum Transport {
 }
 
 #[cfg(feature = "tls")]
-struct TlsOptions {
-    tcp_options: TcpOptions,
+pub(crate) struct TlsOptions {
+    pub(crate) tcp_options: TcpOptions,
     ca_path: Option<String>,
     key_path: Option<String>,
     cert_path: Option<String>,
     verify_mode: SslVerifyMode,
 }
 
-struct TcpOptions {
-    nodelay: bool,
+pub(crate) struct TcpOptions {
+    pub(crate) nodelay: bool,
+}
+
+/// Socket timeouts read from the `connect_timeout`, `read_timeout` and `write_timeout` URL parameters, in
+/// seconds. The `timeout` parameter sets all three at once, and the specific parameters take precedence.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Timeouts {
+    pub(crate) connect: Option<Duration>,
+    pub(crate) read: Option<Duration>,
+    pub(crate) write: Option<Duration>,
+}
+
+impl Timeouts {
+    pub(crate) fn from_url(url: &Url) -> Result<Self, MemcacheError> {
+        let mut timeouts = Timeouts::default();
+        let mut timeout = None;
+        for (key, value) in url.query_pairs() {
+            let slot = match key.as_ref() {
+                "timeout" => &mut timeout,
+                "connect_timeout" => &mut timeouts.connect,
+                "read_timeout" => &mut timeouts.read,
+                "write_timeout" => &mut timeouts.write,
+                _ => continue,
+            };
+            *slot = Some(parse_timeout(&key, &value)?);
+        }
+        timeouts.connect = timeouts.connect.or(timeout);
+        timeouts.read = timeouts.read.or(timeout);
+        timeouts.write = timeouts.write.or(timeout);
+        Ok(timeouts)
+    }
+}
+
+fn parse_timeout(key: &str, value: &str) -> Result<Duration, MemcacheError> {
+    match value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    {
+        Some(timeout) if !timeout.is_zero() => Ok(timeout),
+        _ => Err(MemcacheError::BadURL(format!(
+            "{} should be a positive number of seconds, found '{}'",
+            key, value
+        ))),
+    }
 }
 
 #[cfg(feature = "tls")]
@@ -155,6 +344,25 @@ impl TlsOptions {
             verify_mode: verify_mode,
         })
     }
+
+    pub(crate) fn connector(&self) -> Result<SslConnector, MemcacheError> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+        builder.set_verify(self.verify_mode);
+
+        if let Some(ref ca_path) = self.ca_path {
+            builder.set_ca_file(ca_path)?;
+        }
+
+        if let Some(ref key_path) = self.key_path {
+            builder.set_private_key_file(key_path, SslFiletype::PEM)?;
+        }
+
+        if let Some(ref cert_path) = self.cert_path {
+            builder.set_certificate_chain_file(cert_path)?;
+        }
+
+        Ok(builder.build())
+    }
 }
 
 impl TcpOptions {
@@ -168,7 +376,7 @@ impl TcpOptions {
 }
 
 impl Transport {
-    fn from_url(url: &Url) -> Result<Self, MemcacheError> {
+    pub(crate) fn from_url(url: &Url) -> Result<Self, MemcacheError> {
         let mut parts = url.scheme().splitn(2, "+");
         match parts.next() {
             Some(part) if part == "memcache" => (),
@@ -210,58 +418,254 @@ impl Transport {
     }
 }
 
-fn tcp_stream(url: &Url, opts: &TcpOptions) -> Result<TcpStream, MemcacheError> {
-    let tcp_stream = TcpStream::connect(&*url.socket_addrs(|| None)?)?;
+pub(crate) enum ProtocolKind {
+    Binary,
+    Ascii,
+    Meta,
+}
+
+impl ProtocolKind {
+    pub(crate) fn from_url(url: &Url) -> Self {
+        match url.query_pairs().find(|(ref k, _)| k == "protocol") {
+            Some((_, ref v)) if v == "ascii" => ProtocolKind::Ascii,
+            Some((_, ref v)) if v == "meta" => ProtocolKind::Meta,
+            _ => ProtocolKind::Binary,
+        }
+    }
+}
+
+fn tcp_stream(url: &Url, opts: &TcpOptions, timeouts: &Timeouts) -> Result<TcpStream, MemcacheError> {
+    let addrs = url.socket_addrs(|| None)?;
+    let tcp_stream = match timeouts.connect {
+        Some(timeout) => connect_timeout(&addrs, timeout)?,
+        None => TcpStream::connect(&*addrs)?,
+    };
     tcp_stream.set_nodelay(opts.nodelay)?;
+    tcp_stream.set_read_timeout(timeouts.read)?;
+    tcp_stream.set_write_timeout(timeouts.write)?;
     Ok(tcp_stream)
 }
 
+/// Like `TcpStream::connect`, trying each address in turn, but giving up on each after the timeout.
+fn connect_timeout(addrs: &[SocketAddr], timeout: Duration) -> Result<TcpStream, MemcacheError> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, timeout) {
+            Ok(tcp_stream) => return Ok(tcp_stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")))?
+}
+
 impl Connection {
+    /// Whether a command failed with an IO, parse or protocol error, or did not read its whole response,
+    /// leaving the connection unusable. The pool drops broken connections instead of handing them out again.
+    pub fn is_broken(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Run a command on the protocol, poisoning the connection if the command doesn't complete cleanly.
+    pub(crate) fn track<T, F>(&mut self, command: F) -> Result<T, MemcacheError>
+    where
+        F: FnOnce(&mut Protocol) -> Result<T, MemcacheError>,
+    {
+        let poisoned = self.poisoned;
+        // stays set if the command panics half way through its response
+        self.poisoned = true;
+        let result = self.protocol.settle().and_then(|_| command(&mut self.protocol));
+        self.poisoned = poisoned || matches!(result, Err(ref e) if e.is_fatal());
+        result
+    }
+
+    /// Set a key with the expiration, flags, noreply and cas of `options`, returning whether it was stored.
+    pub fn set_with<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Set, key.as_ref(), value, options)
+    }
+
+    /// Add a key with the options of `options`, returning whether it was stored.
+    pub fn add_with<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Add, key.as_ref(), value, options)
+    }
+
+    /// Replace a key with the options of `options`, returning whether it was stored.
+    pub fn replace_with<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Replace, key.as_ref(), value, options)
+    }
+
+    /// Append to a key with the noreply and cas of `options`, returning whether it was stored.
+    pub fn append_with<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Append, key.as_ref(), value, options)
+    }
+
+    /// Prepend to a key with the noreply and cas of `options`, returning whether it was stored.
+    pub fn prepend_with<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        self.store(StoreCommand::Prepend, key.as_ref(), value, options)
+    }
+
+    fn store<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let encoded = match command {
+            StoreCommand::Append | StoreCommand::Prepend => None,
+            _ => self.encode(&value, options.flags)?,
+        };
+        match encoded {
+            Some(value) => {
+                // the flags override is already part of the encoded flags
+                let options = StoreOptions {
+                    flags: None,
+                    ..options.clone()
+                };
+                self.track(|protocol| protocol.store(command, key, value, &options))
+            }
+            None => self.track(|protocol| protocol.store(command, key, value, options)),
+        }
+    }
+
+    /// Encode a value to compress it, if compression is enabled and the value is over the threshold, with
+    /// `flags` overriding the flags of the value. Appended and prepended values are never compressed, as
+    /// they're concatenated to the stored value.
+    fn encode<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        value: &V,
+        flags: Option<u32>,
+    ) -> Result<Option<Encoded>, MemcacheError> {
+        match self.compression {
+            Some(compression) if compression.applies(value.get_length()) => {
+                let data = self.protocol.stream().capture(|stream| value.write_to(stream))?;
+                Ok(Some(
+                    compression.encode(data, flags.unwrap_or_else(|| value.get_flags())),
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Get a key along with the item metadata selected by `options`. Only supported by the meta protocol.
+    pub fn meta_get<V: FromMemcacheValueExt>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        match self.protocol {
+            Protocol::Meta(_) => self.track(|protocol| match protocol {
+                Protocol::Meta(protocol) => protocol.meta_get(key.as_ref(), options),
+                _ => unreachable!(),
+            }),
+            _ => Err(ClientError::Error("meta_get requires the meta protocol".into()))?,
+        }
+    }
+
+    /// Get a key with a stampede protection lease. Only supported by the meta protocol.
+    pub(crate) fn lease_get(&mut self, key: &[u8], vivify: u32, recache: Option<u32>) -> Result<Lease, MemcacheError> {
+        self.track(|protocol| match protocol {
+            Protocol::Meta(protocol) => protocol.lease_get(key, vivify, recache),
+            _ => Err(ClientError::Error("leases require the meta protocol".into()))?,
+        })
+    }
+
+    /// Give up a lease won by `lease_get`, when the value could not be recomputed.
+    pub(crate) fn release_lease(&mut self, key: &[u8], lease: &Lease) -> Result<(), MemcacheError> {
+        self.track(|protocol| match protocol {
+            Protocol::Meta(protocol) => protocol.release_lease(key, lease),
+            _ => Err(ClientError::Error("leases require the meta protocol".into()))?,
+        })
+    }
+
+    /// Get the internal metadata memcached holds for a key. Only supported by the meta protocol.
+    pub fn meta_debug(&mut self, key: impl AsRef<[u8]>) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        match self.protocol {
+            Protocol::Meta(_) => self.track(|protocol| match protocol {
+                Protocol::Meta(protocol) => protocol.meta_debug(key.as_ref()),
+                _ => unreachable!(),
+            }),
+            _ => Err(ClientError::Error("meta_debug requires the meta protocol".into()))?,
+        }
+    }
+
+    /// Start a pipeline of commands, sent to the server of this connection in a single write.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline::new(self)
+    }
+
+    /// Start a batch of quiet writes, only answered by the server when they fail. Only supported by the binary
+    /// protocol.
+    pub fn quiet_batch(&mut self) -> QuietBatch<'_> {
+        QuietBatch::new(self)
+    }
+
     pub(crate) fn connect(url: &Url) -> Result<Self, MemcacheError> {
         let transport = Transport::from_url(url)?;
-        let is_ascii = url.query_pairs().any(|(ref k, ref v)| k == "protocol" && v == "ascii");
-        let stream: Stream = match transport {
-            Transport::Tcp(options) => Stream::Tcp(tcp_stream(url, &options)?),
-            Transport::Udp => Stream::Udp(UdpStream::new(url)?),
+        let timeouts = Timeouts::from_url(url)?;
+        let socket = match transport {
+            Transport::Tcp(options) => Socket::Tcp(tcp_stream(url, &options, &timeouts)?),
+            Transport::Udp => {
+                let udp_stream = UdpStream::new(url)?;
+                udp_stream.set_timeouts(&timeouts)?;
+                Socket::Udp(udp_stream)
+            }
             #[cfg(unix)]
-            Transport::Unix => Stream::Unix(UnixStream::connect(url.path())?),
+            Transport::Unix => {
+                let unix_stream = UnixStream::connect(url.path())?;
+                unix_stream.set_read_timeout(timeouts.read)?;
+                unix_stream.set_write_timeout(timeouts.write)?;
+                Socket::Unix(unix_stream)
+            }
             #[cfg(feature = "tls")]
             Transport::Tls(options) => {
                 let host = url
                     .host_str()
                     .ok_or(MemcacheError::BadURL("host required for TLS connection".into()))?;
 
-                let mut builder = SslConnector::builder(SslMethod::tls())?;
-                builder.set_verify(options.verify_mode);
-
-                if options.ca_path.is_some() {
-                    builder.set_ca_file(&options.ca_path.unwrap())?;
-                }
-
-                if options.key_path.is_some() {
-                    builder.set_private_key_file(options.key_path.unwrap(), SslFiletype::PEM)?;
-                }
-
-                if options.cert_path.is_some() {
-                    builder.set_certificate_chain_file(options.cert_path.unwrap())?;
-                }
-
-                let tls_conn = builder.build();
-                let tcp_stream = tcp_stream(url, &options.tcp_options)?;
+                let tls_conn = options.connector()?;
+                let tcp_stream = tcp_stream(url, &options.tcp_options, &timeouts)?;
                 let tls_stream = tls_conn.connect(host, tcp_stream)?;
-                Stream::Tls(tls_stream)
+                Socket::Tls(tls_stream)
             }
         };
+        let stream = Stream::new(socket);
 
-        let protocol = if is_ascii {
-            Protocol::Ascii(AsciiProtocol::new(stream))
-        } else {
-            Protocol::Binary(BinaryProtocol { stream })
+        let protocol = match ProtocolKind::from_url(url) {
+            ProtocolKind::Ascii => Protocol::Ascii(AsciiProtocol::new(stream)),
+            ProtocolKind::Meta => Protocol::Meta(MetaProtocol::new(stream)),
+            ProtocolKind::Binary => Protocol::Binary(BinaryProtocol::new(stream)),
         };
 
         Ok(Connection {
             url: Arc::new(url.to_string()),
             protocol,
+            poisoned: false,
+            compression: Compression::from_url(url)?,
         })
     }
 }
@@ -293,4 +697,37 @@ mod tests {
 
         assert!(cm.url.as_str().contains("protocol=ascii"));
     }
+
+    #[test]
+    fn test_timeouts_url() {
+        let timeouts = |url: &str| Timeouts::from_url(&Url::parse(url).unwrap());
+
+        assert_eq!(timeouts("memcache://localhost:12345").unwrap(), Timeouts::default());
+
+        let all = timeouts("memcache://localhost:12345?timeout=10").unwrap();
+        assert_eq!(all.connect, Some(Duration::from_secs(10)));
+        assert_eq!(all.read, Some(Duration::from_secs(10)));
+        assert_eq!(all.write, Some(Duration::from_secs(10)));
+
+        let mixed = timeouts("memcache://localhost:12345?read_timeout=0.25&timeout=2&connect_timeout=0.5").unwrap();
+        assert_eq!(mixed.connect, Some(Duration::from_millis(500)));
+        assert_eq!(mixed.read, Some(Duration::from_millis(250)));
+        assert_eq!(mixed.write, Some(Duration::from_secs(2)));
+
+        for url in &[
+            "memcache://localhost:12345?timeout=0",
+            "memcache://localhost:12345?read_timeout=-1",
+            "memcache://localhost:12345?write_timeout=soon",
+        ] {
+            assert!(matches!(timeouts(url), Err(MemcacheError::BadURL(_))));
+        }
+    }
+
+    #[test]
+    fn test_set_meta_protocol() {
+        let url = "memcache:///tmp/memcached.sock?protocol=ascii";
+        let cm = ConnectionManager::new(url).unwrap().set_meta_protocol();
+
+        assert_eq!(cm.url.as_str(), "memcache:///tmp/memcached.sock?protocol=meta");
+    }
 }
@@ -12,6 +12,8 @@ use std::string;
 pub enum ClientError {
     /// The key provided was longer than 250 bytes.
     KeyTooLong,
+    /// The key holds whitespace, control characters or invalid UTF-8, which the ascii protocol can't send.
+    InvalidKey,
     /// The server returned an error prefixed with CLIENT_ERROR in response to a command.
     Error(Cow<'static, str>),
 }
@@ -20,6 +22,7 @@ impl fmt::Display for ClientError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ClientError::KeyTooLong => write!(f, "The provided key was too long."),
+            ClientError::InvalidKey => write!(f, "The provided key can't be sent with the ascii protocol."),
             ClientError::Error(s) => write!(f, "{}", s),
         }
     }
@@ -73,6 +76,18 @@ pub enum CommandError {
 }
 
 impl MemcacheError {
+    /// Whether the error can leave a failed socket or unread response data behind, so that the connection
+    /// must not be used for further commands. Errors replied by the server to a command leave it usable.
+    pub(crate) fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            MemcacheError::CommandError(_)
+                | MemcacheError::ServerError(ServerError::Error(_))
+                | MemcacheError::ClientError(ClientError::KeyTooLong)
+                | MemcacheError::ClientError(ClientError::InvalidKey)
+        )
+    }
+
     pub(crate) fn try_from(s: &str) -> Result<&str, MemcacheError> {
         if s == "ERROR\r\n" {
             Err(CommandError::InvalidCommand)?
@@ -149,6 +164,8 @@ pub enum ParseError {
     String(string::FromUtf8Error),
     Str(std::str::Utf8Error),
     Url(url::ParseError),
+    /// A value failed to decode from its serde format.
+    Codec(Box<dyn error::Error + Send + Sync>),
 }
 
 impl error::Error for ParseError {
@@ -160,6 +177,7 @@ impl error::Error for ParseError {
             ParseError::String(ref e) => e.source(),
             ParseError::Str(ref e) => e.source(),
             ParseError::Url(ref e) => e.source(),
+            ParseError::Codec(ref e) => e.source(),
         }
     }
 }
@@ -173,6 +191,7 @@ impl fmt::Display for ParseError {
             ParseError::String(ref e) => e.fmt(f),
             ParseError::Str(ref e) => e.fmt(f),
             ParseError::Url(ref e) => e.fmt(f),
+            ParseError::Codec(ref e) => e.fmt(f),
         }
     }
 }
@@ -238,6 +257,9 @@ pub enum MemcacheError {
     ParseError(ParseError),
     /// ConnectionPool errors
     PoolError(r2d2::Error),
+    /// Connecting, or reading or writing the socket, took longer than the timeout set in the URL. The
+    /// async client also raises it when no pooled connection became available in time.
+    Timeout,
 }
 
 impl fmt::Display for MemcacheError {
@@ -252,6 +274,7 @@ impl fmt::Display for MemcacheError {
             MemcacheError::ServerError(ref err) => err.fmt(f),
             MemcacheError::CommandError(ref err) => err.fmt(f),
             MemcacheError::PoolError(ref err) => err.fmt(f),
+            MemcacheError::Timeout => write!(f, "Operation timed out."),
         }
     }
 }
@@ -268,13 +291,18 @@ impl error::Error for MemcacheError {
             MemcacheError::ServerError(_) => None,
             MemcacheError::CommandError(_) => None,
             MemcacheError::PoolError(ref p) => p.source(),
+            MemcacheError::Timeout => None,
         }
     }
 }
 
 impl From<io::Error> for MemcacheError {
     fn from(err: io::Error) -> MemcacheError {
-        MemcacheError::IOError(err)
+        match err.kind() {
+            // blocking sockets report an expired read or write timeout as `WouldBlock` on unix
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => MemcacheError::Timeout,
+            _ => MemcacheError::IOError(err),
+        }
     }
 }
 
@@ -288,7 +316,11 @@ impl From<openssl::error::ErrorStack> for MemcacheError {
 #[cfg(feature = "tls")]
 impl From<openssl::ssl::HandshakeError<std::net::TcpStream>> for MemcacheError {
     fn from(err: openssl::ssl::HandshakeError<std::net::TcpStream>) -> MemcacheError {
-        MemcacheError::OpensslError(err)
+        match err {
+            // the handshake only blocks past a read or write timeout of the socket
+            openssl::ssl::HandshakeError::WouldBlock(_) => MemcacheError::Timeout,
+            err => MemcacheError::OpensslError(err),
+        }
     }
 }
 
@@ -297,3 +329,13 @@ impl From<r2d2::Error> for MemcacheError {
         MemcacheError::PoolError(err)
     }
 }
+
+#[cfg(feature = "tokio")]
+impl From<bb8::RunError<MemcacheError>> for MemcacheError {
+    fn from(err: bb8::RunError<MemcacheError>) -> MemcacheError {
+        match err {
+            bb8::RunError::User(err) => err,
+            bb8::RunError::TimedOut => MemcacheError::Timeout,
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::net::{IpAddr, ToSocketAddrs};
+
+use crate::error::{ClientError, MemcacheError};
+use url::Url;
+
+const POINTS_PER_SERVER: f32 = 160.0;
+const POINTS_PER_HASH: usize = 4;
+const DEFAULT_PORT: u16 = 11211;
+
+/// Ketama consistent hashing ring, so the same key lands on the same server as in the PHP, C or Java clients
+/// sharing the fleet. Two layouts are supported: libmemcached's weighted ketama, built by [`Ketama::new`] and
+/// [`Ketama::from_urls`], and spymemcached's `KetamaNodeLocator`, built by [`Ketama::spymemcached`] and
+/// [`Ketama::spymemcached_from_urls`].
+///
+/// Every server gets virtual nodes, each placed on the ring by an MD5 digest of the server name. Adding or removing
+/// a server only remaps the keys owned by its virtual nodes.
+///
+/// Example:
+///
+/// ```rust
+/// let ring = memcache::Ketama::new(&[("10.0.0.1", 1), ("10.0.0.2", 1), ("10.0.0.3", 2)]).unwrap();
+/// assert!(ring.get_server("foo") < 3);
+/// let ring = memcache::Ketama::spymemcached(&["10.0.0.1:11211", "10.0.0.2:11211"]).unwrap();
+/// assert!(ring.get_server("foo") < 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Ketama {
+    points: Vec<(u32, usize)>,
+    servers: usize,
+}
+
+fn hash(digest: &[u8; 16], alignment: usize) -> u32 {
+    let offset = alignment * 4;
+    (u32::from(digest[3 + offset]) << 24)
+        | (u32::from(digest[2 + offset]) << 16)
+        | (u32::from(digest[1 + offset]) << 8)
+        | u32::from(digest[offset])
+}
+
+/// Name a server the way libmemcached does, `host` for the default port and `host:port` otherwise.
+fn server_name(url: &Url) -> Result<String, MemcacheError> {
+    match url.host_str() {
+        Some(host) => match url.port() {
+            None | Some(DEFAULT_PORT) => Ok(host.to_string()),
+            Some(port) => Ok(format!("{}:{}", host, port)),
+        },
+        None if !url.path().is_empty() => Ok(url.path().to_string()),
+        None => Err(MemcacheError::BadURL(format!(
+            "can not name server for ketama: {}",
+            url
+        ))),
+    }
+}
+
+/// Name a server the way spymemcached does, after the `toString` of its `InetSocketAddress`: `ip:port` for an ip
+/// address and `host/ip:port` for a host name, resolved to its first IPv4 address if it has one.
+fn spymemcached_name(url: &Url) -> Result<String, MemcacheError> {
+    let port = url.port().unwrap_or(DEFAULT_PORT);
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => {
+            return Err(MemcacheError::BadURL(format!(
+                "can not name server for spymemcached ketama: {}",
+                url
+            )))
+        }
+    };
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(format!("{}:{}", java_address(ip), port));
+    }
+    let addresses: Vec<IpAddr> = (host, port).to_socket_addrs()?.map(|address| address.ip()).collect();
+    match addresses.iter().find(|ip| ip.is_ipv4()).or_else(|| addresses.first()) {
+        Some(ip) => Ok(format!("{}/{}:{}", host, java_address(*ip), port)),
+        None => Err(MemcacheError::BadURL(format!(
+            "can not resolve server for ketama: {}",
+            url
+        ))),
+    }
+}
+
+/// Format an ip address like Java, which writes every group of an IPv6 address and brackets it.
+fn java_address(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => {
+            let groups: Vec<String> = ip.segments().iter().map(|group| format!("{:x}", group)).collect();
+            format!("[{}]", groups.join(":"))
+        }
+    }
+}
+
+/// Place the points of the server at `index`, four for each MD5 digest of its name followed by the digest number.
+fn place(points: &mut Vec<(u32, usize)>, name: &str, hashes: usize, index: usize) {
+    for i in 0..hashes {
+        let digest = md5::compute(format!("{}-{}", name, i)).0;
+        for alignment in 0..POINTS_PER_HASH {
+            points.push((hash(&digest, alignment), index));
+        }
+    }
+}
+
+impl Ketama {
+    /// Build a ring from server names and weights. The index of a server in `servers` is what
+    /// `get_server` returns.
+    pub fn new<S: AsRef<str>>(servers: &[(S, u32)]) -> Result<Self, MemcacheError> {
+        let total_weight: u64 = servers.iter().map(|(_, weight)| u64::from(*weight)).sum();
+        if total_weight == 0 {
+            Err(ClientError::Error(
+                "ketama requires at least one server with non-zero weight".into(),
+            ))?
+        }
+
+        let mut points = Vec::new();
+        for (index, (name, weight)) in servers.iter().enumerate() {
+            // libmemcached computes this in single precision, keep its rounding to place the same points
+            let percent = *weight as f32 / total_weight as f32;
+            let points_per_server = percent * POINTS_PER_SERVER / POINTS_PER_HASH as f32 * servers.len() as f32;
+            let hashes = (f64::from(points_per_server) + 0.0000000001).floor() as usize;
+            place(&mut points, name.as_ref(), hashes, index);
+        }
+        points.sort_unstable();
+
+        Ok(Ketama {
+            points,
+            servers: servers.len(),
+        })
+    }
+
+    /// Build a ring from memcache urls and weights, naming each server after its host and port.
+    pub fn from_urls<S: AsRef<str>>(servers: &[(S, u32)]) -> Result<Self, MemcacheError> {
+        let servers = servers
+            .iter()
+            .map(|(url, weight)| Ok((server_name(&Url::parse(url.as_ref())?)?, *weight)))
+            .collect::<Result<Vec<_>, MemcacheError>>()?;
+        Self::new(&servers)
+    }
+
+    /// Build a ring like spymemcached's `KetamaNodeLocator` from its node names, `ip:port` or `host/ip:port`. Every
+    /// server gets 160 points, as spymemcached has no weights. The index of a server in `servers` is what
+    /// `get_server` returns.
+    pub fn spymemcached<S: AsRef<str>>(servers: &[S]) -> Result<Self, MemcacheError> {
+        if servers.is_empty() {
+            Err(ClientError::Error("ketama requires at least one server".into()))?
+        }
+
+        let mut points = Vec::new();
+        for (index, name) in servers.iter().enumerate() {
+            place(
+                &mut points,
+                name.as_ref(),
+                POINTS_PER_SERVER as usize / POINTS_PER_HASH,
+                index,
+            );
+        }
+        // spymemcached keeps its points in a map, where a later server takes over a point already placed
+        points.sort_by_key(|&(point, _)| point);
+        points.reverse();
+        points.dedup_by_key(|&mut (point, _)| point);
+        points.reverse();
+
+        Ok(Ketama {
+            points,
+            servers: servers.len(),
+        })
+    }
+
+    /// Build a spymemcached ring from memcache urls, naming each server like spymemcached does. Host names are
+    /// resolved, as spymemcached names servers after their address.
+    pub fn spymemcached_from_urls<S: AsRef<str>>(servers: &[S]) -> Result<Self, MemcacheError> {
+        let servers = servers
+            .iter()
+            .map(|url| spymemcached_name(&Url::parse(url.as_ref())?))
+            .collect::<Result<Vec<_>, MemcacheError>>()?;
+        Self::spymemcached(&servers)
+    }
+
+    /// Number of servers in the ring.
+    pub fn len(&self) -> usize {
+        self.servers
+    }
+
+    /// Returns `true` if the ring has no servers.
+    pub fn is_empty(&self) -> bool {
+        self.servers == 0
+    }
+
+    /// Get the index of the server which owns the key.
+    pub fn get_server(&self, key: impl AsRef<[u8]>) -> usize {
+        let key_hash = hash(&md5::compute(key.as_ref()).0, 0);
+        let position = self.points.partition_point(|&(point, _)| point < key_hash);
+        if position == self.points.len() {
+            self.points[0].1
+        } else {
+            self.points[position].1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_names() {
+        let name = |url: &str| server_name(&Url::parse(url).unwrap()).unwrap();
+        assert_eq!(name("memcache://10.0.0.1:11211"), "10.0.0.1");
+        assert_eq!(name("memcache://10.0.0.1"), "10.0.0.1");
+        assert_eq!(name("memcache://cache.local:12345?protocol=ascii"), "cache.local:12345");
+        assert_eq!(name("memcache:///tmp/memcached.sock"), "/tmp/memcached.sock");
+    }
+
+    #[test]
+    fn points() {
+        let ring = Ketama::new(&[("10.0.0.1", 1), ("10.0.0.2", 1), ("10.0.0.3", 1)]).unwrap();
+        assert_eq!(ring.points.len(), 3 * 160);
+
+        let ring = Ketama::from_urls(&[
+            ("memcache://10.0.1.1:11211", 600),
+            ("memcache://10.0.1.2:11211", 300),
+            ("memcache://10.0.1.3:11211", 200),
+            ("memcache://10.0.1.4:11211", 350),
+        ])
+        .unwrap();
+        assert_eq!(ring.points.len(), 636);
+
+        let ring = Ketama::new(&[("10.0.0.1", 1), ("10.0.0.2", 3)]).unwrap();
+        assert_eq!(ring.points.iter().filter(|(_, index)| *index == 0).count(), 80);
+        assert_eq!(ring.points.iter().filter(|(_, index)| *index == 1).count(), 240);
+
+        assert!(Ketama::new::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn libmemcached_compatible() {
+        let ring = Ketama::from_urls(&[
+            ("memcache://10.0.1.1:11211", 600),
+            ("memcache://10.0.1.2:11211", 300),
+            ("memcache://10.0.1.3:11211", 200),
+            ("memcache://10.0.1.4:11211", 350),
+        ])
+        .unwrap();
+        let servers: Vec<usize> = [
+            "apple", "beat", "carrot", "daikon", "eggplant", "flower", "green", "hide",
+        ]
+        .iter()
+        .map(|key| ring.get_server(key))
+        .collect();
+        assert_eq!(servers, vec![0, 2, 2, 0, 2, 3, 2, 2]);
+    }
+
+    #[test]
+    fn spymemcached_names() {
+        let name = |url: &str| spymemcached_name(&Url::parse(url).unwrap());
+        assert_eq!(name("memcache://10.0.0.1").unwrap(), "10.0.0.1:11211");
+        assert_eq!(
+            name("memcache://10.0.0.1:12345?protocol=ascii").unwrap(),
+            "10.0.0.1:12345"
+        );
+        assert_eq!(name("memcache://[::1]:11211").unwrap(), "[0:0:0:0:0:0:0:1]:11211");
+        assert_eq!(name("memcache://localhost:11211").unwrap(), "localhost/127.0.0.1:11211");
+        assert!(name("memcache:///tmp/memcached.sock").is_err());
+    }
+
+    #[test]
+    fn spymemcached_compatible() {
+        let ring = Ketama::spymemcached_from_urls(&[
+            "memcache://10.0.1.1:11211",
+            "memcache://10.0.1.2:11211",
+            "memcache://10.0.1.3:11211",
+            "memcache://10.0.1.4:11211",
+        ])
+        .unwrap();
+        assert_eq!(ring.points.len(), 4 * 160);
+        let servers: Vec<usize> = [
+            "apple", "beat", "carrot", "daikon", "eggplant", "flower", "green", "hide",
+        ]
+        .iter()
+        .map(|key| ring.get_server(key))
+        .collect();
+        assert_eq!(servers, vec![0, 3, 3, 2, 2, 3, 3, 2]);
+
+        let ring = Ketama::spymemcached(&[
+            "10.0.1.1:11211",
+            "10.0.1.2:12345",
+            "[0:0:0:0:0:0:0:1]:11211",
+            "localhost/127.0.0.1:11211",
+        ])
+        .unwrap();
+        let servers: Vec<usize> = [
+            "apple", "beat", "carrot", "daikon", "eggplant", "flower", "green", "hide",
+        ]
+        .iter()
+        .map(|key| ring.get_server(key))
+        .collect();
+        assert_eq!(servers, vec![1, 3, 3, 1, 2, 3, 2, 1]);
+
+        assert!(Ketama::spymemcached::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn remap_fraction() {
+        let names: Vec<(String, u32)> = (1..=10).map(|i| (format!("10.0.0.{}", i), 1)).collect();
+        let before = Ketama::new(&names).unwrap();
+        let after = Ketama::new(&names[..9]).unwrap();
+        let keys: Vec<String> = (0..10000).map(|i| format!("key:{}", i)).collect();
+        let moved = keys
+            .iter()
+            .filter(|key| before.get_server(key) != after.get_server(key))
+            .count();
+        for key in &keys {
+            if before.get_server(key) != 9 {
+                assert_eq!(before.get_server(key), after.get_server(key));
+            }
+        }
+        assert!(moved > 500 && moved < 1500, "moved {} keys", moved);
+    }
+}
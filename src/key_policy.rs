@@ -0,0 +1,109 @@
+//! Handling of the keys the servers can't take as is, set on a client with
+//! [`Client::with_key_policy`](crate::Client::with_key_policy).
+
+use std::borrow::Cow;
+
+use sha2::{Digest, Sha256};
+
+use crate::client::{check_key_len, check_text_key, namespaced};
+use crate::error::MemcacheError;
+
+/// How a client sends keys which are over 250 bytes, or which hold whitespace, control characters or invalid
+/// UTF-8 and so can't be sent by the ascii protocol.
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::KeyPolicy;
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let client = memcache::Client::with_pool(pool).with_key_policy(KeyPolicy::Hash {
+///     prefix: "sha256:".to_string(),
+///     log: Some(|key, hashed| eprintln!("{} stored as {}", String::from_utf8_lossy(key), hashed)),
+/// });
+/// let url = format!("https://example.com/search?q={}", "memcache ".repeat(30));
+/// client.set(&url, "results", 0).unwrap();
+/// assert_eq!(client.get::<String>(&url).unwrap().as_deref(), Some("results"));
+/// # client.flush().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub enum KeyPolicy {
+    /// Send every key as is, failing with `ClientError::KeyTooLong` on long keys, and with
+    /// `ClientError::InvalidKey` when the ascii protocol can't send a key. This is the default.
+    #[default]
+    Strict,
+    /// Replace the long or illegal keys with `prefix` followed by the hex SHA-256 of the key, after the namespace
+    /// of the client. Other keys are sent as is, so the same keys are hashed with every protocol.
+    Hash {
+        prefix: String,
+        /// Called with the original key and its digest form whenever a key is hashed, to keep track of them.
+        log: Option<fn(&[u8], &str)>,
+    },
+}
+
+impl KeyPolicy {
+    /// The key sent to the servers for `key` in `namespace`.
+    pub(crate) fn apply<'a>(&self, namespace: &str, key: &'a [u8]) -> Result<Cow<'a, [u8]>, MemcacheError> {
+        match self.hash(namespace, key)? {
+            Some(hashed) => Ok(Cow::Owned(hashed.into_bytes())),
+            None => namespaced(namespace, key),
+        }
+    }
+
+    /// The digest form of a key, if the policy hashes it.
+    fn hash(&self, namespace: &str, key: &[u8]) -> Result<Option<String>, MemcacheError> {
+        let (prefix, log) = match self {
+            KeyPolicy::Strict => return Ok(None),
+            KeyPolicy::Hash { prefix, log } => (prefix, log),
+        };
+        if namespace.len() + key.len() <= 250 && check_text_key(key).is_ok() {
+            return Ok(None);
+        }
+        let hashed = format!("{}{}{:x}", namespace, prefix, Sha256::digest(key));
+        check_key_len(hashed.as_bytes())?;
+        if let Some(log) = log {
+            log(key, &hashed);
+        }
+        Ok(Some(hashed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyPolicy;
+    use crate::error::{ClientError, MemcacheError};
+
+    #[test]
+    fn hash_policy() {
+        let policy = KeyPolicy::Hash {
+            prefix: "h:".to_string(),
+            log: None,
+        };
+        assert_eq!(policy.apply("ns:", b"foo").unwrap().as_ref(), b"ns:foo");
+        assert_eq!(
+            policy.apply("", b"foo bar").unwrap().as_ref(),
+            b"h:fbc1a9f858ea9e177916964bd88c3d37b91a1e84412765e29950777f265c4b75"
+        );
+        assert_eq!(
+            policy.apply("ns:", b"foo bar").unwrap().as_ref(),
+            &b"ns:h:fbc1a9f858ea9e177916964bd88c3d37b91a1e84412765e29950777f265c4b75"[..]
+        );
+
+        // the namespace counts in the length deciding whether to hash
+        let key = "a".repeat(248);
+        assert_eq!(policy.apply("", key.as_bytes()).unwrap().as_ref(), key.as_bytes());
+        assert!(policy.apply("ns:", key.as_bytes()).unwrap().starts_with(b"ns:h:"));
+
+        assert!(matches!(
+            KeyPolicy::Strict.apply("", b"foo bar"),
+            Ok(key) if key.as_ref() == b"foo bar"
+        ));
+        assert!(matches!(
+            KeyPolicy::Strict.apply("ns:", key.as_bytes()),
+            Err(MemcacheError::ClientError(ClientError::KeyTooLong))
+        ));
+    }
+}
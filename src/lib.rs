@@ -15,16 +15,19 @@ memcache = "*"
 - <input type="checkbox"  disabled checked /> All memcached supported protocols
   - <input type="checkbox"  disabled checked /> Binary protocol
   - <input type="checkbox"  disabled checked /> ASCII protocol
+  - <input type="checkbox"  disabled checked /> Meta protocol
 - <input type="checkbox"  disabled checked /> All memcached supported connections
   - <input type="checkbox"  disabled checked /> TCP connection
   - <input type="checkbox"  disabled checked /> UDP connection
   - <input type="checkbox"  disabled checked/> UNIX Domain socket connection
   - <input type="checkbox"  disabled checked/> TLS connection
-- <input type="checkbox"  disabled /> Encodings
+- <input type="checkbox"  disabled checked /> Asynchronous client on tokio (`tokio` feature)
+- <input type="checkbox"  disabled checked /> Encodings
   - <input type="checkbox"  disabled checked /> Typed interface
-  - <input type="checkbox"  disabled /> Automatically compress
-  - <input type="checkbox"  disabled /> Automatically serialize to JSON / msgpack etc
+  - <input type="checkbox"  disabled checked /> Automatically compress (`zstd`, `lz4` or `gzip` feature)
+  - <input type="checkbox"  disabled checked /> Automatically serialize to JSON / msgpack etc (`serde` feature)
 - <input type="checkbox"  disabled checked /> Mutiple server support with custom key hash algorithm
+  - <input type="checkbox"  disabled checked /> Ketama consistent hashing compatible with libmemcached
 - <input type="checkbox"  disabled checked /> Authority
   - <input type="checkbox"  disabled checked /> Binary protocol (plain SASL authority)
   - <input type="checkbox"  disabled checked /> ASCII protocol
@@ -70,26 +73,53 @@ assert_eq!(answer, 42);
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::needless_return))]
 
+extern crate base64;
 extern crate byteorder;
 extern crate enum_dispatch;
+extern crate md5;
 #[cfg(feature = "tls")]
 extern crate openssl;
 extern crate r2d2;
 extern crate rand;
+extern crate sha2;
 extern crate url;
 
+#[cfg(feature = "tokio")]
+pub mod aio;
 mod client;
+#[cfg(any(feature = "json", feature = "msgpack", feature = "bincode", feature = "cbor"))]
+mod codec;
+mod compression;
 mod connection;
 mod error;
+mod ketama;
+mod key_policy;
+pub mod lock;
+pub mod namespace;
+pub mod near_cache;
+mod pipeline;
 mod protocol;
+pub mod ratelimit;
 mod stream;
 mod value;
 
-pub use crate::client::Client;
-pub use crate::connection::ConnectionManager;
+pub use crate::client::{Client, Update};
+#[cfg(feature = "bincode")]
+pub use crate::codec::Bincode;
+#[cfg(feature = "cbor")]
+pub use crate::codec::Cbor;
+#[cfg(feature = "json")]
+pub use crate::codec::Json;
+#[cfg(feature = "msgpack")]
+pub use crate::codec::MsgPack;
+pub use crate::connection::{Connection, ConnectionManager};
 pub use crate::error::{ClientError, CommandError, MemcacheError, ServerError};
+pub use crate::ketama::Ketama;
+pub use crate::key_policy::KeyPolicy;
+pub use crate::pipeline::{Pipeline, QuietBatch, Reply};
+pub use crate::protocol::{Item, MetaGetOptions, MetaValue, StoreOptions};
 pub use crate::stream::Stream;
-pub use crate::value::{FromMemcacheValue, FromMemcacheValueExt, ToMemcacheValue};
+pub use crate::value::{Flags, FromMemcacheValue, FromMemcacheValueExt, ToMemcacheValue};
 pub use r2d2::Error as PoolError;
 
 /// R2D2 connection pool
@@ -0,0 +1,147 @@
+use crate::client::{update_backoff, Client};
+use crate::error::MemcacheError;
+use crate::protocol::StoreOptions;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// An absolute expiration in 1970, which memcached takes as already expired. Storing it with a cas token
+/// removes the item only while it is unchanged, something a plain delete can't do on every protocol.
+const EXPIRED: u32 = 60 * 60 * 24 * 30 + 1;
+
+/// A distributed mutex on a memcached key, held for `expiration` seconds unless extended.
+///
+/// The lock is taken with an `add` of a random owner token, so only the owner can extend or release it. Each
+/// acquisition also increments the `<key>:fence` counter, whose value can be passed along to the protected
+/// resource as a fencing token to reject writes from an owner whose lock has expired in the meantime.
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::lock::Lock;
+/// use std::time::Duration;
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let client = memcache::Client::with_pool(pool);
+/// let mut lock = Lock::new(&client, "lock", 10);
+/// let mut other = Lock::new(&client, "lock", 10);
+/// assert!(lock.acquire(Duration::from_secs(1)).unwrap());
+/// assert!(!other.try_acquire().unwrap());
+/// let fence = lock.fencing_token().unwrap();
+/// assert!(lock.extend(20).unwrap());
+/// assert!(lock.release().unwrap());
+/// assert!(other.try_acquire().unwrap());
+/// assert!(other.fencing_token().unwrap() > fence);
+/// # other.release().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Lock {
+    client: Client,
+    key: String,
+    token: String,
+    expiration: u32,
+    fencing_token: Option<u64>,
+}
+
+impl Lock {
+    /// Create a lock on `key` held for `expiration` seconds, with a new random owner token.
+    pub fn new(client: &Client, key: &str, expiration: u32) -> Self {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(20)
+            .map(char::from)
+            .collect();
+        Lock {
+            client: client.clone(),
+            key: key.to_string(),
+            token,
+            expiration,
+            fencing_token: None,
+        }
+    }
+
+    /// The key of the lock.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The owner token stored in the lock key while it is held.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// The fencing token of the last acquisition, `None` if the lock was never acquired or has been released.
+    pub fn fencing_token(&self) -> Option<u64> {
+        self.fencing_token
+    }
+
+    /// Try to take the lock once. Returns `false` if it is already held, by this or another owner.
+    pub fn try_acquire(&mut self) -> Result<bool, MemcacheError> {
+        let options = StoreOptions {
+            expiration: self.expiration,
+            ..Default::default()
+        };
+        if !self.client.add_with(&self.key, self.token.as_str(), &options)? {
+            return Ok(false);
+        }
+        let fence = format!("{}:fence", self.key);
+        self.fencing_token = Some(self.client.increment_or_init(&fence, 1, 1, 0)?);
+        Ok(true)
+    }
+
+    /// Take the lock, retrying with a jittered backoff until `timeout` has elapsed. Returns `false` if the lock
+    /// could not be taken in time.
+    pub fn acquire(&mut self, timeout: Duration) -> Result<bool, MemcacheError> {
+        let deadline = Instant::now() + timeout;
+        let mut attempts = 0;
+        loop {
+            if self.try_acquire()? {
+                return Ok(true);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(false);
+            }
+            attempts += 1;
+            let backoff = update_backoff(attempts);
+            let jitter = rand::thread_rng().gen_range(Duration::from_secs(0)..=backoff);
+            thread::sleep((backoff / 2 + jitter / 2).min(remaining));
+        }
+    }
+
+    /// Hold the lock for `expiration` more seconds. The expiration is only touched if the key still holds the
+    /// owner token, checked with its cas token. Returns `false` if the lock is no longer held by this owner.
+    pub fn extend(&mut self, expiration: u32) -> Result<bool, MemcacheError> {
+        if !self.store_if_owner(expiration)? {
+            return Ok(false);
+        }
+        self.expiration = expiration;
+        Ok(true)
+    }
+
+    /// Release the lock if it is still held by this owner, returning `false` otherwise. A lock that expired and
+    /// was taken by another owner in the meantime is left alone.
+    pub fn release(&mut self) -> Result<bool, MemcacheError> {
+        self.fencing_token = None;
+        self.store_if_owner(EXPIRED)
+    }
+
+    fn store_if_owner(&self, expiration: u32) -> Result<bool, MemcacheError> {
+        let values: HashMap<&str, (Vec<u8>, u32, Option<u64>)> = self.client.gets(&[self.key.as_str()])?;
+        let cas = match values.get(self.key.as_str()) {
+            Some((value, _, Some(cas))) if value.as_slice() == self.token.as_bytes() => *cas,
+            _ => return Ok(false),
+        };
+        let options = StoreOptions {
+            expiration,
+            cas: Some(cas),
+            ..Default::default()
+        };
+        self.client.set_with(&self.key, self.token.as_str(), &options)
+    }
+}
@@ -0,0 +1,122 @@
+use crate::client::Client;
+use crate::error::MemcacheError;
+use crate::stream::Stream;
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A group of keys which can be invalidated at once, by bumping a generation counter embedded in the keys.
+///
+/// Keys are stored as `<name>:<generation>:<key>`, the generation being read from the `<name>:generation`
+/// counter and cached in process for `cache_ttl`. [`invalidate`](Namespace::invalidate) increments the counter,
+/// orphaning every key of the previous generation until it expires or is evicted. Other processes see the new
+/// generation once their cached one expires.
+///
+/// The counter starts at the current time in milliseconds, so that it doesn't go back to a previous generation
+/// if it is evicted.
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::namespace::Namespace;
+/// use std::time::Duration;
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let client = memcache::Client::with_pool(pool);
+/// let users = Namespace::new(&client, "users", Duration::from_secs(1));
+/// users.set("42", "alice", 0).unwrap();
+/// assert_eq!(users.get::<String>("42").unwrap().as_deref(), Some("alice"));
+/// users.invalidate().unwrap();
+/// assert_eq!(users.get::<String>("42").unwrap(), None);
+/// # client.flush().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Namespace {
+    client: Client,
+    name: String,
+    cache_ttl: Duration,
+    generation: Arc<Mutex<Option<(u64, Instant)>>>,
+}
+
+fn initial_generation() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl Namespace {
+    /// Create a namespace of keys prefixed by `name`, caching its generation for `cache_ttl`. A zero
+    /// `cache_ttl` reads the generation from the server on every command.
+    pub fn new(client: &Client, name: &str, cache_ttl: Duration) -> Self {
+        Namespace {
+            client: client.clone(),
+            name: name.to_string(),
+            cache_ttl,
+            generation: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The name of the namespace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counter(&self) -> String {
+        format!("{}:generation", self.name)
+    }
+
+    fn cache(&self, generation: u64) {
+        let mut cached = self.generation.lock().unwrap_or_else(|e| e.into_inner());
+        *cached = Some((generation, Instant::now() + self.cache_ttl));
+    }
+
+    /// The current generation of the namespace, created if it is missing.
+    pub fn generation(&self) -> Result<u64, MemcacheError> {
+        let cached = *self.generation.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((generation, expires_at)) = cached {
+            if Instant::now() < expires_at {
+                return Ok(generation);
+            }
+        }
+        let counter = self.counter();
+        let generation = match self.client.get(&counter)? {
+            Some(generation) => generation,
+            None => self.client.increment_or_init(&counter, 0, initial_generation(), 0)?,
+        };
+        self.cache(generation);
+        Ok(generation)
+    }
+
+    /// The key stored on the server for `key` in the current generation.
+    pub fn key(&self, key: &str) -> Result<String, MemcacheError> {
+        Ok(format!("{}:{}:{}", self.name, self.generation()?, key))
+    }
+
+    /// Get a key of the current generation.
+    pub fn get<V: FromMemcacheValueExt>(&self, key: &str) -> Result<Option<V>, MemcacheError> {
+        self.client.get(&self.key(key)?)
+    }
+
+    /// Set a key in the current generation.
+    pub fn set<V: ToMemcacheValue<Stream>>(&self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
+        self.client.set(&self.key(key)?, value, expiration)
+    }
+
+    /// Delete a key of the current generation.
+    pub fn delete(&self, key: &str) -> Result<bool, MemcacheError> {
+        self.client.delete(&self.key(key)?)
+    }
+
+    /// Move the namespace to a new generation, orphaning all of its keys. Returns the new generation.
+    pub fn invalidate(&self) -> Result<u64, MemcacheError> {
+        let generation = self
+            .client
+            .increment_or_init(self.counter(), 1, initial_generation(), 0)?;
+        self.cache(generation);
+        Ok(generation)
+    }
+}
@@ -0,0 +1,300 @@
+use crate::client::Client;
+use crate::error::MemcacheError;
+use crate::stream::Stream;
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Options of a [`NearCache`].
+#[derive(Clone, Debug)]
+pub struct NearCacheOptions {
+    /// How long a value is served from memory before being read from the server again.
+    pub ttl: Duration,
+    /// The maximum size of the cached keys and values in bytes, the least recently used ones being evicted
+    /// beyond it.
+    pub max_bytes: usize,
+    /// Only the keys starting with one of these prefixes are cached, every key if empty.
+    pub prefixes: Vec<String>,
+}
+
+impl Default for NearCacheOptions {
+    fn default() -> Self {
+        NearCacheOptions {
+            ttl: Duration::from_secs(1),
+            max_bytes: 16 * 1024 * 1024,
+            prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Hit and miss statistics of a [`NearCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NearCacheStats {
+    /// Reads served from memory.
+    pub hits: u64,
+    /// Reads of a cacheable key sent to the server.
+    pub misses: u64,
+    /// Values evicted to stay under `max_bytes`.
+    pub evictions: u64,
+    /// Values currently cached.
+    pub entries: usize,
+    /// Size of the keys and values currently cached.
+    pub bytes: usize,
+}
+
+struct Entry {
+    value: Vec<u8>,
+    flags: u32,
+    expires_at: Instant,
+    tick: u64,
+}
+
+/// A size bounded LRU map, ordering the entries by the tick of their last read.
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+    /// Bumped by every invalidation, so that a value read from the server before one isn't cached after it.
+    generation: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str, now: Instant) -> Option<(Vec<u8>, u32)> {
+        let expired = self.entries.get(key)?.expires_at <= now;
+        if expired {
+            self.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.tick);
+        entry.tick = self.tick;
+        self.recency.insert(self.tick, key.to_string());
+        Some((entry.value.clone(), entry.flags))
+    }
+
+    /// Insert a value, returning the number of entries evicted to make room for it.
+    fn insert(&mut self, key: &str, value: Vec<u8>, flags: u32, expires_at: Instant, max_bytes: usize) -> u64 {
+        self.remove(key);
+        let size = key.len() + value.len();
+        if size > max_bytes {
+            return 0;
+        }
+        let mut evictions = 0;
+        while self.bytes + size > max_bytes {
+            let oldest = match self.recency.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+            self.remove(&oldest);
+            evictions += 1;
+        }
+        self.tick += 1;
+        self.bytes += size;
+        self.recency.insert(self.tick, key.to_string());
+        let entry = Entry {
+            value,
+            flags,
+            expires_at,
+            tick: self.tick,
+        };
+        self.entries.insert(key.to_string(), entry);
+        evictions
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.tick);
+            self.bytes -= key.len() + entry.value.len();
+        }
+    }
+
+    fn invalidate(&mut self, key: &str) {
+        self.generation += 1;
+        self.remove(key);
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// An in-process cache in front of a [`Client`], serving hot keys from memory for a short time.
+///
+/// Values are kept for `ttl` at most, so a change made by another process is seen after that delay. Writes
+/// through the near cache invalidate the key locally right away. The cache is shared by the clones of a
+/// `NearCache`.
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::near_cache::{NearCache, NearCacheOptions};
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let client = memcache::Client::with_pool(pool);
+/// let options = NearCacheOptions {
+///     prefixes: vec!["config:".to_string()],
+///     ..Default::default()
+/// };
+/// let cache = NearCache::new(&client, options);
+/// cache.set("config:near_cache", "foo", 0).unwrap();
+/// let value: Option<String> = cache.get("config:near_cache").unwrap();
+/// assert_eq!(value.as_deref(), Some("foo"));
+/// let value: Option<String> = cache.get("config:near_cache").unwrap();
+/// assert_eq!(value.as_deref(), Some("foo"));
+/// assert_eq!(cache.stats().hits, 1);
+/// # client.flush().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct NearCache {
+    client: Client,
+    options: Arc<NearCacheOptions>,
+    shared: Arc<Shared>,
+}
+
+impl NearCache {
+    /// Create an empty near cache in front of `client`.
+    pub fn new(client: &Client, options: NearCacheOptions) -> Self {
+        NearCache {
+            client: client.clone(),
+            options: Arc::new(options),
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    /// The client behind the near cache. Writes made through it don't invalidate the near cache.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn cacheable(&self, key: &str) -> bool {
+        let prefixes = &self.options.prefixes;
+        prefixes.is_empty() || prefixes.iter().any(|prefix| key.starts_with(prefix.as_str()))
+    }
+
+    fn lru(&self) -> MutexGuard<'_, Lru> {
+        // the map is consistent after each of its operations, even if a holder panicked
+        self.shared.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get a key from memory, or from the server if it isn't cached or has expired. Keys not matching the
+    /// prefixes of the options are always read from the server.
+    pub fn get<V: FromMemcacheValueExt>(&self, key: &str) -> Result<Option<V>, MemcacheError> {
+        if !self.cacheable(key) {
+            return self.client.get(key);
+        }
+        let cached = self.lru().get(key, Instant::now());
+        if let Some((value, flags)) = cached {
+            self.shared.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(V::from_memcache_value(value, flags, None)?));
+        }
+        self.shared.misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.lru().generation;
+        let (value, flags, _) = match self.client.get::<(Vec<u8>, u32, Option<u64>)>(key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let decoded = V::from_memcache_value(value.clone(), flags, None)?;
+        let mut lru = self.lru();
+        if lru.generation == generation {
+            let expires_at = Instant::now() + self.options.ttl;
+            let evictions = lru.insert(key, value, flags, expires_at, self.options.max_bytes);
+            self.shared.evictions.fetch_add(evictions, Ordering::Relaxed);
+        }
+        Ok(Some(decoded))
+    }
+
+    /// Set a key on the server, invalidating it in memory.
+    pub fn set<V: ToMemcacheValue<Stream>>(&self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
+        self.invalidate(key);
+        let result = self.client.set(key, value, expiration);
+        // a read racing with the write may have cached the previous value
+        self.invalidate(key);
+        result
+    }
+
+    /// Delete a key on the server, invalidating it in memory.
+    pub fn delete(&self, key: &str) -> Result<bool, MemcacheError> {
+        self.invalidate(key);
+        let result = self.client.delete(key);
+        self.invalidate(key);
+        result
+    }
+
+    /// Drop a key from memory only, for instance after it was changed through [`client`](NearCache::client).
+    pub fn invalidate(&self, key: &str) {
+        self.lru().invalidate(key);
+    }
+
+    /// Drop every key from memory.
+    pub fn clear(&self) {
+        let mut lru = self.lru();
+        let generation = lru.generation + 1;
+        *lru = Lru {
+            generation,
+            ..Default::default()
+        };
+    }
+
+    /// The hit and miss statistics since the near cache was created.
+    pub fn stats(&self) -> NearCacheStats {
+        let lru = self.lru();
+        NearCacheStats {
+            hits: self.shared.hits.load(Ordering::Relaxed),
+            misses: self.shared.misses.load(Ordering::Relaxed),
+            evictions: self.shared.evictions.load(Ordering::Relaxed),
+            entries: lru.entries.len(),
+            bytes: lru.bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lru;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn lru_eviction() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(60);
+        let mut lru = Lru::default();
+        assert_eq!(lru.insert("a", vec![0; 9], 0, later, 30), 0);
+        assert_eq!(lru.insert("b", vec![0; 9], 0, later, 30), 0);
+        assert_eq!(lru.insert("c", vec![0; 9], 0, later, 30), 0);
+        assert_eq!(lru.bytes, 30);
+
+        // reading "a" makes "b" the least recently used
+        assert!(lru.get("a", now).is_some());
+        assert_eq!(lru.insert("d", vec![0; 9], 0, later, 30), 1);
+        assert!(lru.get("b", now).is_none());
+        assert!(lru.get("a", now).is_some());
+        assert_eq!(lru.bytes, 30);
+
+        // replacing a key only counts its new size
+        assert_eq!(lru.insert("a", vec![0; 4], 7, later, 30), 0);
+        assert_eq!(lru.get("a", now), Some((vec![0; 4], 7)));
+        assert_eq!(lru.bytes, 25);
+
+        // values larger than the whole cache aren't kept
+        assert_eq!(lru.insert("e", vec![0; 30], 0, later, 30), 0);
+        assert!(lru.get("e", now).is_none());
+
+        assert!(lru.get("c", later).is_none());
+        assert_eq!(lru.entries.len(), 2);
+        assert_eq!(lru.recency.len(), 2);
+        assert_eq!(lru.bytes, 15);
+    }
+}
@@ -0,0 +1,405 @@
+use crate::client::check_key_len;
+use crate::connection::Connection;
+use crate::error::{ClientError, MemcacheError, ServerError};
+use crate::protocol::ascii::StoreCommand;
+use crate::protocol::{not_stored, Protocol};
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+use std::borrow::Cow;
+
+/// A command queued in a pipeline, holding its already encoded value.
+pub(crate) enum Command {
+    Get(Vec<u8>),
+    Store {
+        command: StoreCommand,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        flags: u32,
+        expiration: u32,
+    },
+    Delete(Vec<u8>),
+    Increment(Vec<u8>, u64),
+    Decrement(Vec<u8>, u64),
+    Touch(Vec<u8>, u32),
+}
+
+impl Command {
+    pub(crate) fn key(&self) -> &[u8] {
+        match self {
+            Command::Get(key)
+            | Command::Store { key, .. }
+            | Command::Delete(key)
+            | Command::Increment(key, _)
+            | Command::Decrement(key, _)
+            | Command::Touch(key, _) => key,
+        }
+    }
+}
+
+/// Reply to a pipelined command.
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    /// Reply to `get`: the value with its flags and cas, or `None` if the key was not found.
+    Value(Option<(Vec<u8>, u32, Option<u64>)>),
+    /// Reply to `set`, `add`, `replace`, `append` and `prepend`: whether the value was stored.
+    Stored(bool),
+    /// Reply to `delete`: whether the key was found.
+    Deleted(bool),
+    /// Reply to `touch`: whether the key was found.
+    Touched(bool),
+    /// Reply to `increment` and `decrement`: the new value of the counter.
+    Counter(u64),
+}
+
+impl Reply {
+    /// Decode the value of a `get` reply.
+    pub fn value<V: FromMemcacheValueExt>(self) -> Result<Option<V>, MemcacheError> {
+        match self {
+            Reply::Value(Some((value, flags, cas))) => Ok(Some(V::from_memcache_value(value, flags, cas)?)),
+            Reply::Value(None) => Ok(None),
+            _ => Err(ClientError::Error("the reply is not for a get command".into()))?,
+        }
+    }
+}
+
+/// A batch of commands written to the server at once and answered in a single round trip, created with
+/// [`Connection::pipeline`](crate::Connection::pipeline).
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::Reply;
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let mut connection = pool.get().unwrap();
+/// let replies = connection
+///     .pipeline()
+///     .set("foo", "bar", 0)
+///     .get("foo")
+///     .delete("foo")
+///     .execute()
+///     .unwrap();
+/// assert_eq!(replies[0].as_ref().unwrap(), &Reply::Stored(true));
+/// let value: Option<String> = replies.into_iter().nth(1).unwrap().unwrap().value().unwrap();
+/// assert_eq!(value, Some("bar".into()));
+/// ```
+pub struct Pipeline<'a> {
+    connection: &'a mut Connection,
+    commands: Vec<Command>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub(crate) fn new(connection: &'a mut Connection) -> Self {
+        Self {
+            connection,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Queue a get of the key.
+    pub fn get(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.commands.push(Command::Get(key.as_ref().into()));
+        self
+    }
+
+    /// Queue a set of the key with expiration seconds.
+    pub fn set<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V, expiration: u32) -> Self {
+        self.store(StoreCommand::Set, key, value, expiration)
+    }
+
+    /// Queue an add of the key with expiration seconds.
+    pub fn add<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V, expiration: u32) -> Self {
+        self.store(StoreCommand::Add, key, value, expiration)
+    }
+
+    /// Queue a replace of the key with expiration seconds.
+    pub fn replace<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V, expiration: u32) -> Self {
+        self.store(StoreCommand::Replace, key, value, expiration)
+    }
+
+    /// Queue an append of the value to the key.
+    pub fn append<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V) -> Self {
+        self.store(StoreCommand::Append, key, value, 0)
+    }
+
+    /// Queue a prepend of the value to the key.
+    pub fn prepend<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V) -> Self {
+        self.store(StoreCommand::Prepend, key, value, 0)
+    }
+
+    /// Queue a delete of the key.
+    pub fn delete(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.commands.push(Command::Delete(key.as_ref().into()));
+        self
+    }
+
+    /// Queue an increment of the counter by amount.
+    pub fn increment(mut self, key: impl AsRef<[u8]>, amount: u64) -> Self {
+        self.commands.push(Command::Increment(key.as_ref().into(), amount));
+        self
+    }
+
+    /// Queue a decrement of the counter by amount.
+    pub fn decrement(mut self, key: impl AsRef<[u8]>, amount: u64) -> Self {
+        self.commands.push(Command::Decrement(key.as_ref().into(), amount));
+        self
+    }
+
+    /// Queue a touch of the key with a new expiration.
+    pub fn touch(mut self, key: impl AsRef<[u8]>, expiration: u32) -> Self {
+        self.commands.push(Command::Touch(key.as_ref().into(), expiration));
+        self
+    }
+
+    /// Number of queued commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Whether no command is queued.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Send all the queued commands in one write and read their replies, in the order the commands were
+    /// queued. A command failing on the server, like incrementing a missing key, only fails its own reply,
+    /// while the outer error is returned when the connection itself failed.
+    pub fn execute(self) -> Result<Vec<Result<Reply, MemcacheError>>, MemcacheError> {
+        if self.commands.is_empty() {
+            return Ok(Vec::new());
+        }
+        for command in &self.commands {
+            check_key_len(command.key())?;
+        }
+        let commands = self.commands;
+        self.connection.track(|protocol| protocol.execute(&commands))
+    }
+
+    fn store<V: ToMemcacheValue<Vec<u8>>>(
+        mut self,
+        command: StoreCommand,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Self {
+        let command = store_command(self.connection, command, key, value, expiration);
+        self.commands.push(command);
+        self
+    }
+}
+
+/// Encode a value for a queued store command, compressing it like `Connection::set` does.
+pub(crate) fn store_command<V: ToMemcacheValue<Vec<u8>>>(
+    connection: &Connection,
+    command: StoreCommand,
+    key: impl AsRef<[u8]>,
+    value: V,
+    expiration: u32,
+) -> Command {
+    let mut encoded = Vec::with_capacity(value.get_length());
+    // writing into a Vec can't fail
+    let _ = value.write_to(&mut encoded);
+    let mut flags = value.get_flags();
+    if let Some(compression) = connection.compression {
+        if compression.applies(encoded.len()) && command != StoreCommand::Append && command != StoreCommand::Prepend {
+            (encoded, flags) = compression.encode(encoded, flags).into_parts();
+        }
+    }
+    Command::Store {
+        command,
+        key: key.as_ref().into(),
+        value: encoded,
+        flags,
+        expiration,
+    }
+}
+
+/// A write queued in a quiet batch.
+pub(crate) enum QuietCommand {
+    Write(Command),
+    Flush,
+}
+
+impl QuietCommand {
+    fn key(&self) -> &[u8] {
+        match self {
+            QuietCommand::Write(command) => command.key(),
+            QuietCommand::Flush => b"",
+        }
+    }
+}
+
+/// A batch of writes sent with the quiet opcodes of the binary protocol, created with
+/// [`Connection::quiet_batch`](crate::Connection::quiet_batch). The server only answers the writes which
+/// failed, so a batch of any size costs a single round trip, ended by a noop.
+///
+/// Example:
+///
+/// ```rust
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let mut connection = pool.get().unwrap();
+/// let failures = connection
+///     .quiet_batch()
+///     .set("quiet_foo", "bar", 0)
+///     .add("quiet_foo", "baz", 0)
+///     .delete("quiet_bar")
+///     .execute()
+///     .unwrap();
+/// assert_eq!(failures.len(), 2);
+/// assert_eq!(failures[0].0, b"quiet_foo");
+/// ```
+pub struct QuietBatch<'a> {
+    connection: &'a mut Connection,
+    commands: Vec<QuietCommand>,
+}
+
+impl<'a> QuietBatch<'a> {
+    pub(crate) fn new(connection: &'a mut Connection) -> Self {
+        Self {
+            connection,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Queue a set of the key with expiration seconds.
+    pub fn set<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V, expiration: u32) -> Self {
+        self.store(StoreCommand::Set, key, value, expiration)
+    }
+
+    /// Queue an add of the key with expiration seconds.
+    pub fn add<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V, expiration: u32) -> Self {
+        self.store(StoreCommand::Add, key, value, expiration)
+    }
+
+    /// Queue a replace of the key with expiration seconds.
+    pub fn replace<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V, expiration: u32) -> Self {
+        self.store(StoreCommand::Replace, key, value, expiration)
+    }
+
+    /// Queue an append of the value to the key.
+    pub fn append<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V) -> Self {
+        self.store(StoreCommand::Append, key, value, 0)
+    }
+
+    /// Queue a prepend of the value to the key.
+    pub fn prepend<V: ToMemcacheValue<Vec<u8>>>(self, key: impl AsRef<[u8]>, value: V) -> Self {
+        self.store(StoreCommand::Prepend, key, value, 0)
+    }
+
+    /// Queue a delete of the key.
+    pub fn delete(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.commands
+            .push(QuietCommand::Write(Command::Delete(key.as_ref().into())));
+        self
+    }
+
+    /// Queue an increment of the counter by amount.
+    pub fn increment(mut self, key: impl AsRef<[u8]>, amount: u64) -> Self {
+        self.commands
+            .push(QuietCommand::Write(Command::Increment(key.as_ref().into(), amount)));
+        self
+    }
+
+    /// Queue a decrement of the counter by amount.
+    pub fn decrement(mut self, key: impl AsRef<[u8]>, amount: u64) -> Self {
+        self.commands
+            .push(QuietCommand::Write(Command::Decrement(key.as_ref().into(), amount)));
+        self
+    }
+
+    /// Queue a flush of all the items of the server.
+    pub fn flush(mut self) -> Self {
+        self.commands.push(QuietCommand::Flush);
+        self
+    }
+
+    /// Number of queued commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Whether no command is queued.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Send all the queued writes and wait for the noop closing the batch. Returns the writes which failed, in
+    /// the order they were queued, as their key along with the error, like `KeyExists` for an add of an
+    /// existing key. A failed flush is reported with an empty key. Only supported by the binary protocol.
+    pub fn execute(self) -> Result<Vec<(Vec<u8>, MemcacheError)>, MemcacheError> {
+        if self.commands.is_empty() {
+            return Ok(Vec::new());
+        }
+        for command in &self.commands {
+            if let QuietCommand::Write(command) = command {
+                check_key_len(command.key())?;
+            }
+        }
+        let commands = self.commands;
+        let failures = match self.connection.protocol {
+            Protocol::Binary(_) => self.connection.track(|protocol| match protocol {
+                Protocol::Binary(protocol) => protocol.execute_quiet(&commands),
+                _ => unreachable!(),
+            })?,
+            _ => Err(ClientError::Error("quiet batches require the binary protocol".into()))?,
+        };
+        Ok(failures
+            .into_iter()
+            .map(|(index, error)| (commands[index].key().to_vec(), error))
+            .collect())
+    }
+
+    fn store<V: ToMemcacheValue<Vec<u8>>>(
+        mut self,
+        command: StoreCommand,
+        key: impl AsRef<[u8]>,
+        value: V,
+        expiration: u32,
+    ) -> Self {
+        let command = store_command(self.connection, command, key, value, expiration);
+        self.commands.push(QuietCommand::Write(command));
+        self
+    }
+}
+
+/// Send writes in a single round trip and return whether each one took effect, like whether its value was
+/// stored or its key was found, in the order of the commands. Stores and deletes are sent with the quiet opcodes
+/// of the binary protocol, which only answer the failures, and the other writes and protocols are pipelined.
+pub(crate) fn write_multi(
+    connection: &mut Connection,
+    commands: Vec<Command>,
+) -> Result<Vec<Result<bool, MemcacheError>>, MemcacheError> {
+    if commands.is_empty() {
+        return Ok(Vec::new());
+    }
+    let quiet = matches!(connection.protocol, Protocol::Binary(_))
+        && commands
+            .iter()
+            .all(|command| matches!(command, Command::Store { .. } | Command::Delete(_)));
+    if quiet {
+        let commands: Vec<QuietCommand> = commands.into_iter().map(QuietCommand::Write).collect();
+        let failures = connection.track(|protocol| match protocol {
+            Protocol::Binary(protocol) => protocol.execute_quiet(&commands),
+            _ => unreachable!(),
+        })?;
+        let mut outcomes: Vec<Result<bool, MemcacheError>> = commands.iter().map(|_| Ok(true)).collect();
+        for (index, error) in failures {
+            outcomes[index] = not_stored(Err(error));
+        }
+        return Ok(outcomes);
+    }
+    let replies = connection.track(|protocol| protocol.execute(&commands))?;
+    Ok(replies
+        .into_iter()
+        .map(|reply| match reply {
+            Ok(Reply::Stored(done)) | Ok(Reply::Deleted(done)) | Ok(Reply::Touched(done)) => Ok(done),
+            Ok(_) => Err(ServerError::BadResponse(Cow::Borrowed("unexpected reply to a write")))?,
+            Err(e) => not_stored(Err(e)),
+        })
+        .collect())
+}
@@ -2,23 +2,16 @@ use std::collections::HashMap;
 use std::fmt;
 use std::io::{Read, Write};
 
-use super::ProtocolTrait;
-use crate::client::Stats;
+use super::{raw_items, Item, ProtocolTrait, StoreOptions};
+use crate::client::{check_text_key, check_text_keys, Stats};
 use crate::error::{ClientError, CommandError, MemcacheError, ServerError};
+use crate::pipeline::{Command, Reply};
 use crate::stream::Stream;
 use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
 use std::borrow::Cow;
 
-#[derive(Default)]
-pub struct Options {
-    pub noreply: bool,
-    pub exptime: u32,
-    pub flags: u32,
-    pub cas: Option<u64>,
-}
-
-#[derive(PartialEq)]
-enum StoreCommand {
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum StoreCommand {
     Cas,
     Set,
     Add,
@@ -29,6 +22,22 @@ enum StoreCommand {
 
 const END: &'static str = "END\r\n";
 
+/// The ascii protocol only checks a cas token with the `cas` command, which stores like `set`: a set or replace
+/// with a cas token is sent as a `cas`, which also fails on a missing key.
+pub(crate) fn cas_command(command: StoreCommand, options: &StoreOptions) -> Result<StoreCommand, MemcacheError> {
+    match (command, options.cas) {
+        (StoreCommand::Cas, None) => Err(ClientError::Error(Cow::Borrowed(
+            "cas_id should be present when using cas command",
+        )))?,
+        (StoreCommand::Set, Some(_)) | (StoreCommand::Replace, Some(_)) => Ok(StoreCommand::Cas),
+        (StoreCommand::Cas, _) | (_, None) => Ok(command),
+        (_, Some(_)) => Err(ClientError::Error(Cow::Owned(format!(
+            "the ascii protocol can't check a cas token on {}",
+            command
+        ))))?,
+    }
+}
+
 impl fmt::Display for StoreCommand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -42,7 +51,7 @@ impl fmt::Display for StoreCommand {
     }
 }
 
-struct CappedLineReader<C> {
+pub(crate) struct CappedLineReader<C> {
     inner: C,
     filled: usize,
     buf: [u8; 2048],
@@ -60,7 +69,7 @@ fn get_line(buf: &[u8]) -> Option<usize> {
 }
 
 impl<C: Read> CappedLineReader<C> {
-    fn new(inner: C) -> Self {
+    pub(crate) fn new(inner: C) -> Self {
         Self {
             inner,
             filled: 0,
@@ -72,7 +81,7 @@ impl<C: Read> CappedLineReader<C> {
         &mut self.inner
     }
 
-    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), MemcacheError> {
+    pub(crate) fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), MemcacheError> {
         let min = std::cmp::min(buf.len(), self.filled);
         let (to_fill, rest) = buf.split_at_mut(min);
         to_fill.copy_from_slice(&self.buf[..min]);
@@ -86,7 +95,7 @@ impl<C: Read> CappedLineReader<C> {
     /// Try to read a CRLF terminated line from the underlying reader.
     /// The length of the line is expected to be <= the length of the
     /// internal buffer, suited for reading headers or short responses.
-    fn read_line<T, F>(&mut self, mut cb: F) -> Result<T, MemcacheError>
+    pub(crate) fn read_line<T, F>(&mut self, mut cb: F) -> Result<T, MemcacheError>
     where
         F: FnMut(&str) -> Result<T, MemcacheError>,
     {
@@ -128,24 +137,18 @@ pub struct AsciiProtocol<C: Read + Write + Sized> {
 
 impl ProtocolTrait for AsciiProtocol<Stream> {
     fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
-        return self.set("auth", format!("{} {}", username, password), 0);
+        return self.set(b"auth", format!("{} {}", username, password), 0);
     }
 
     fn version(&mut self) -> Result<String, MemcacheError> {
         self.reader.get_mut().write(b"version\r\n")?;
         self.reader.get_mut().flush()?;
-        self.reader.read_line(|response| {
-            let response = MemcacheError::try_from(response)?;
-            if !response.starts_with("VERSION") {
-                Err(ServerError::BadResponse(Cow::Owned(response.into())))?
-            }
-            let version = response.trim_start_matches("VERSION ").trim_end_matches("\r\n");
-            Ok(version.to_string())
-        })
+        self.reader.read_line(parse_version_response)
     }
 
     fn flush(&mut self) -> Result<(), MemcacheError> {
         write!(self.reader.get_mut(), "flush_all\r\n")?;
+        self.reader.get_mut().flush()?;
         self.parse_ok_response()
     }
 
@@ -155,8 +158,10 @@ impl ProtocolTrait for AsciiProtocol<Stream> {
         self.parse_ok_response()
     }
 
-    fn get<V: FromMemcacheValueExt>(&mut self, key: &str) -> Result<Option<V>, MemcacheError> {
+    fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
+        let key = check_text_key(key)?;
         write!(self.reader.get_mut(), "get {}\r\n", key)?;
+        self.reader.get_mut().flush()?;
 
         if let Some((k, v)) = self.parse_get_response(false)? {
             if k != key {
@@ -173,32 +178,45 @@ impl ProtocolTrait for AsciiProtocol<Stream> {
         }
     }
 
-    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&str]) -> Result<HashMap<String, V>, MemcacheError> {
+    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let keys = check_text_keys(keys)?;
         write!(self.reader.get_mut(), "gets {}\r\n", keys.join(" "))?;
+        self.reader.get_mut().flush()?;
+        self.parse_gets_response(keys.len())
+    }
 
-        let mut result: HashMap<String, V> = HashMap::with_capacity(keys.len());
-        // there will be atmost keys.len() "VALUE <...>" responses and one END response
-        for _ in 0..=keys.len() {
-            match self.parse_get_response(true)? {
-                Some((key, value)) => {
-                    result.insert(key, value);
-                }
-                None => return Ok(result),
-            }
-        }
+    fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        Ok(raw_items(self.gets(keys)?))
+    }
 
-        Err(ServerError::BadResponse(Cow::Borrowed("Expected end of gets response")))?
+    fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        Ok(self.get_and_touch_multi(&[key], expiration)?.remove(key))
+    }
+
+    fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let keys = check_text_keys(keys)?;
+        write!(self.reader.get_mut(), "gats {} {}\r\n", expiration, keys.join(" "))?;
+        self.reader.get_mut().flush()?;
+        self.parse_gets_response(keys.len())
     }
 
     fn cas<V: ToMemcacheValue<Stream>>(
         &mut self,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
         cas: u64,
     ) -> Result<bool, MemcacheError> {
-        let options = Options {
-            exptime: expiration,
+        let options = StoreOptions {
+            expiration,
             cas: Some(cas),
             ..Default::default()
         };
@@ -211,17 +229,17 @@ impl ProtocolTrait for AsciiProtocol<Stream> {
         }
     }
 
-    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
-        let options = Options {
-            exptime: expiration,
+    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
             ..Default::default()
         };
         self.store(StoreCommand::Set, key, value, &options).map(|_| ())
     }
 
-    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
-        let options = Options {
-            exptime: expiration,
+    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
             ..Default::default()
         };
         self.store(StoreCommand::Add, key, value, &options).map(|_| ())
@@ -229,105 +247,87 @@ impl ProtocolTrait for AsciiProtocol<Stream> {
 
     fn replace<V: ToMemcacheValue<Stream>>(
         &mut self,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
     ) -> Result<(), MemcacheError> {
-        let options = Options {
-            exptime: expiration,
+        let options = StoreOptions {
+            expiration,
             ..Default::default()
         };
         self.store(StoreCommand::Replace, key, value, &options).map(|_| ())
     }
 
-    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V) -> Result<(), MemcacheError> {
+    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
         self.store(StoreCommand::Append, key, value, &Default::default())
             .map(|_| ())
     }
 
-    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V) -> Result<(), MemcacheError> {
+    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
         self.store(StoreCommand::Prepend, key, value, &Default::default())
             .map(|_| ())
     }
 
-    fn delete(&mut self, key: &str) -> Result<bool, MemcacheError> {
+    fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
+        let key = check_text_key(key)?;
         write!(self.reader.get_mut(), "delete {}\r\n", key)?;
         self.reader.get_mut().flush()?;
-        self.reader
-            .read_line(|response| match MemcacheError::try_from(response) {
-                Ok(s) => {
-                    if s == "DELETED\r\n" {
-                        Ok(true)
-                    } else {
-                        Err(ServerError::BadResponse(Cow::Owned(s.into())).into())
-                    }
-                }
-                Err(MemcacheError::CommandError(CommandError::KeyNotFound)) => Ok(false),
-                Err(e) => Err(e),
-            })
+        self.reader.read_line(parse_delete_response)
     }
 
-    fn increment(&mut self, key: &str, amount: u64) -> Result<u64, MemcacheError> {
+    fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        let key = check_text_key(key)?;
         write!(self.reader.get_mut(), "incr {} {}\r\n", key, amount)?;
+        self.reader.get_mut().flush()?;
         self.parse_u64_response()
     }
 
-    fn decrement(&mut self, key: &str, amount: u64) -> Result<u64, MemcacheError> {
+    fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        let key = check_text_key(key)?;
         write!(self.reader.get_mut(), "decr {} {}\r\n", key, amount)?;
+        self.reader.get_mut().flush()?;
         self.parse_u64_response()
     }
 
-    fn touch(&mut self, key: &str, expiration: u32) -> Result<bool, MemcacheError> {
+    fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        // the text protocol has no counter creation, a missing counter is added instead, and incremented if
+        // another client added it first
+        match self.increment(key, amount) {
+            Err(MemcacheError::CommandError(CommandError::KeyNotFound)) => {}
+            result => return result,
+        }
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        if self.store(StoreCommand::Add, key, initial.to_string(), &options)? {
+            Ok(initial)
+        } else {
+            self.increment(key, amount)
+        }
+    }
+
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        let key = check_text_key(key)?;
         write!(self.reader.get_mut(), "touch {} {}\r\n", key, expiration)?;
         self.reader.get_mut().flush()?;
-        self.reader
-            .read_line(|response| match MemcacheError::try_from(response) {
-                Ok(s) => {
-                    if s == "TOUCHED\r\n" {
-                        Ok(true)
-                    } else {
-                        Err(ServerError::BadResponse(Cow::Owned(s.into())).into())
-                    }
-                }
-                Err(MemcacheError::CommandError(CommandError::KeyNotFound)) => Ok(false),
-                Err(e) => Err(e),
-            })
+        self.reader.read_line(parse_touch_response)
     }
 
     fn stats(&mut self) -> Result<Stats, MemcacheError> {
         self.reader.get_mut().write(b"stats\r\n")?;
         self.reader.get_mut().flush()?;
-
-        enum Loop {
-            Break,
-            Continue,
-        }
-
         let mut stats: Stats = HashMap::new();
-        loop {
-            let status = self.reader.read_line(|response| {
-                if response != END {
-                    return Ok(Loop::Break);
-                }
-                let s = MemcacheError::try_from(response)?;
-                if !s.starts_with("STAT") {
-                    return Err(ServerError::BadResponse(Cow::Owned(s.into())))?;
-                }
-                let stat: Vec<_> = s.trim_end_matches("\r\n").split(" ").collect();
-                if stat.len() < 3 {
-                    return Err(ServerError::BadResponse(Cow::Owned(s.into())).into());
-                }
-                let key = stat[1];
-                let value = s.trim_start_matches(format!("STAT {}", key).as_str());
-                stats.insert(key.into(), value.into());
-
-                Ok(Loop::Continue)
-            })?;
-
-            if let Loop::Break = status {
-                break Ok(stats);
-            }
+        while let Some((key, value)) = self.reader.read_line(parse_stat_line)? {
+            stats.insert(key, value);
         }
+        Ok(stats)
     }
 }
 
@@ -338,29 +338,29 @@ impl AsciiProtocol<Stream> {
         }
     }
 
-    fn store<V: ToMemcacheValue<Stream>>(
+    pub(crate) fn stream(&mut self) -> &mut Stream {
+        self.reader.get_mut()
+    }
+
+    pub(crate) fn store<V: ToMemcacheValue<Stream>>(
         &mut self,
         command: StoreCommand,
-        key: &str,
+        key: &[u8],
         value: V,
-        options: &Options,
+        options: &StoreOptions,
     ) -> Result<bool, MemcacheError> {
-        if command == StoreCommand::Cas {
-            if options.cas.is_none() {
-                Err(ClientError::Error(Cow::Borrowed(
-                    "cas_id should be present when using cas command",
-                )))?;
-            }
-        }
+        let key = check_text_key(key)?;
+        let command = cas_command(command, options)?;
         let noreply = if options.noreply { " noreply" } else { "" };
+        let flags = options.flags.unwrap_or_else(|| value.get_flags());
         if options.cas.is_some() {
             write!(
                 self.reader.get_mut(),
                 "{command} {key} {flags} {exptime} {vlen} {cas}{noreply}\r\n",
                 command = command,
                 key = key,
-                flags = value.get_flags(),
-                exptime = options.exptime,
+                flags = flags,
+                exptime = options.expiration,
                 vlen = value.get_length(),
                 cas = options.cas.unwrap(),
                 noreply = noreply
@@ -371,8 +371,8 @@ impl AsciiProtocol<Stream> {
                 "{command} {key} {flags} {exptime} {vlen}{noreply}\r\n",
                 command = command,
                 key = key,
-                flags = value.get_flags(),
-                exptime = options.exptime,
+                flags = flags,
+                exptime = options.expiration,
                 vlen = value.get_length(),
                 noreply = noreply
             )?;
@@ -386,79 +386,263 @@ impl AsciiProtocol<Stream> {
             return Ok(true);
         }
 
-        self.reader.read_line(|response| {
-            let response = MemcacheError::try_from(response)?;
-            match response {
-                "STORED\r\n" => Ok(true),
-                "NOT_STORED\r\n" => Ok(false),
-                "EXISTS\r\n" => Err(CommandError::KeyExists)?,
-                "NOT_FOUND\r\n" => Err(CommandError::KeyNotFound)?,
-                response => Err(ServerError::BadResponse(Cow::Owned(response.into())))?,
+        self.reader.read_line(parse_store_response)
+    }
+
+    pub(crate) fn execute(&mut self, commands: &[Command]) -> Result<Vec<Result<Reply, MemcacheError>>, MemcacheError> {
+        let keys = commands
+            .iter()
+            .map(|command| check_text_key(command.key()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut request = Vec::new();
+        for (command, key) in commands.iter().zip(keys) {
+            match command {
+                Command::Get(_) => write!(request, "gets {}\r\n", key)?,
+                Command::Store {
+                    command,
+                    value,
+                    flags,
+                    expiration,
+                    ..
+                } => {
+                    write!(
+                        request,
+                        "{} {} {} {} {}\r\n",
+                        command,
+                        key,
+                        flags,
+                        expiration,
+                        value.len()
+                    )?;
+                    request.extend_from_slice(value);
+                    request.extend_from_slice(b"\r\n");
+                }
+                Command::Delete(_) => write!(request, "delete {}\r\n", key)?,
+                Command::Increment(_, amount) => write!(request, "incr {} {}\r\n", key, amount)?,
+                Command::Decrement(_, amount) => write!(request, "decr {} {}\r\n", key, amount)?,
+                Command::Touch(_, expiration) => write!(request, "touch {} {}\r\n", key, expiration)?,
             }
-        })
+        }
+        self.reader.get_mut().write_all(&request)?;
+        self.reader.get_mut().flush()?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for command in commands {
+            let reply = match command {
+                Command::Get(_) => self.parse_get_reply()?,
+                Command::Store { .. } => self
+                    .reader
+                    .read_line(|line| command_reply(line, parse_store_response))?
+                    .map(Reply::Stored),
+                Command::Delete(_) => self
+                    .reader
+                    .read_line(|line| command_reply(line, parse_delete_response))?
+                    .map(Reply::Deleted),
+                Command::Increment(..) | Command::Decrement(..) => self
+                    .reader
+                    .read_line(|line| command_reply(line, parse_u64_response))?
+                    .map(Reply::Counter),
+                Command::Touch(..) => self
+                    .reader
+                    .read_line(|line| command_reply(line, parse_touch_response))?
+                    .map(Reply::Touched),
+            };
+            replies.push(reply);
+        }
+        Ok(replies)
     }
 
-    fn parse_ok_response(&mut self) -> Result<(), MemcacheError> {
-        self.reader.read_line(|response| {
-            let response = MemcacheError::try_from(response)?;
-            if response == "OK\r\n" {
-                Ok(())
-            } else {
-                Err(ServerError::BadResponse(Cow::Owned(response.into())))?
+    fn parse_get_reply(&mut self) -> Result<Result<Reply, MemcacheError>, MemcacheError> {
+        let header = self
+            .reader
+            .read_line(|line| command_reply(line, |line| parse_value_header(line, true)))?;
+        match header {
+            Ok(Some((_, flags, length, cas))) => {
+                let mut value = vec![0u8; length + 2];
+                self.reader.read_exact(value.as_mut_slice())?;
+                let value = parse_value_data(value, flags, cas)?;
+                if self.reader.read_line(|line| parse_value_header(line, true))?.is_some() {
+                    Err(ServerError::BadResponse(Cow::Borrowed("Expected end of get response")))?
+                }
+                Ok(Ok(Reply::Value(Some(value))))
             }
-        })
+            Ok(None) => Ok(Ok(Reply::Value(None))),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    fn parse_ok_response(&mut self) -> Result<(), MemcacheError> {
+        self.reader.read_line(parse_ok_response)
     }
 
     fn parse_get_response<V: FromMemcacheValueExt>(
         &mut self,
         has_cas: bool,
     ) -> Result<Option<(String, V)>, MemcacheError> {
-        let result = self.reader.read_line(|buf| {
-            let buf = MemcacheError::try_from(buf)?;
-            if buf == END {
-                return Ok(None);
-            }
-            if !buf.starts_with("VALUE") {
-                return Err(ServerError::BadResponse(Cow::Owned(buf.into())))?;
-            }
-            let mut header = buf.trim_end_matches("\r\n").split(" ");
-            let mut next_or_err = || {
-                header
-                    .next()
-                    .ok_or_else(|| ServerError::BadResponse(Cow::Owned(buf.into())))
-            };
-            let _ = next_or_err()?;
-            let key = next_or_err()?;
-            let flags: u32 = next_or_err()?.parse()?;
-            let length: usize = next_or_err()?.parse()?;
-            let cas: Option<u64> = if has_cas { Some(next_or_err()?.parse()?) } else { None };
-            if header.next().is_some() {
-                return Err(ServerError::BadResponse(Cow::Owned(buf.into())))?;
-            }
-            Ok(Some((key.to_string(), flags, length, cas)))
-        })?;
+        let result = self.reader.read_line(|buf| parse_value_header(buf, has_cas))?;
         match result {
             Some((key, flags, length, cas)) => {
                 let mut value = vec![0u8; length + 2];
                 self.reader.read_exact(value.as_mut_slice())?;
-                if &value[length..] != b"\r\n" {
-                    return Err(ServerError::BadResponse(Cow::Owned(String::from_utf8(value)?)))?;
-                }
-                // remove the trailing \r\n
-                value.pop();
-                value.pop();
-                value.shrink_to_fit();
-                let value = FromMemcacheValueExt::from_memcache_value(value, flags, cas)?;
-                Ok(Some((key.to_string(), value)))
+                let value = parse_value_data(value, flags, cas)?;
+                Ok(Some((key, value)))
             }
             None => Ok(None),
         }
     }
 
+    fn parse_gets_response<V: FromMemcacheValueExt>(
+        &mut self,
+        max_values: usize,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        let mut result: HashMap<Vec<u8>, V> = HashMap::with_capacity(max_values);
+        // there will be atmost max_values "VALUE <...>" responses and one END response
+        for _ in 0..=max_values {
+            match self.parse_get_response(true)? {
+                Some((key, value)) => {
+                    result.insert(key.into_bytes(), value);
+                }
+                None => return Ok(result),
+            }
+        }
+
+        Err(ServerError::BadResponse(Cow::Borrowed("Expected end of gets response")))?
+    }
+
     fn parse_u64_response(&mut self) -> Result<u64, MemcacheError> {
-        self.reader.read_line(|response| {
-            let s = MemcacheError::try_from(response)?;
-            Ok(s.trim_end_matches("\r\n").parse::<u64>()?)
-        })
+        self.reader.read_line(parse_u64_response)
+    }
+}
+
+/// Parse the reply line to a pipelined command, keeping the error replies to the command itself, like
+/// `NOT_FOUND` or `CLIENT_ERROR`, apart from the errors of a broken response stream.
+pub(crate) fn command_reply<T, F>(line: &str, parse: F) -> Result<Result<T, MemcacheError>, MemcacheError>
+where
+    F: FnOnce(&str) -> Result<T, MemcacheError>,
+{
+    match parse(line) {
+        Err(e) if MemcacheError::try_from(line).is_err() => Ok(Err(e)),
+        result => result.map(Ok),
+    }
+}
+
+pub(crate) fn parse_version_response(response: &str) -> Result<String, MemcacheError> {
+    let response = MemcacheError::try_from(response)?;
+    if !response.starts_with("VERSION") {
+        Err(ServerError::BadResponse(Cow::Owned(response.into())))?
+    }
+    let version = response.trim_start_matches("VERSION ").trim_end_matches("\r\n");
+    Ok(version.to_string())
+}
+
+pub(crate) fn parse_ok_response(response: &str) -> Result<(), MemcacheError> {
+    let response = MemcacheError::try_from(response)?;
+    if response == "OK\r\n" {
+        Ok(())
+    } else {
+        Err(ServerError::BadResponse(Cow::Owned(response.into())))?
+    }
+}
+
+pub(crate) fn parse_store_response(response: &str) -> Result<bool, MemcacheError> {
+    let response = MemcacheError::try_from(response)?;
+    match response {
+        "STORED\r\n" => Ok(true),
+        "NOT_STORED\r\n" => Ok(false),
+        "EXISTS\r\n" => Err(CommandError::KeyExists)?,
+        "NOT_FOUND\r\n" => Err(CommandError::KeyNotFound)?,
+        response => Err(ServerError::BadResponse(Cow::Owned(response.into())))?,
+    }
+}
+
+pub(crate) fn parse_delete_response(response: &str) -> Result<bool, MemcacheError> {
+    match MemcacheError::try_from(response) {
+        Ok(s) => {
+            if s == "DELETED\r\n" {
+                Ok(true)
+            } else {
+                Err(ServerError::BadResponse(Cow::Owned(s.into())).into())
+            }
+        }
+        Err(MemcacheError::CommandError(CommandError::KeyNotFound)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn parse_touch_response(response: &str) -> Result<bool, MemcacheError> {
+    match MemcacheError::try_from(response) {
+        Ok(s) => {
+            if s == "TOUCHED\r\n" {
+                Ok(true)
+            } else {
+                Err(ServerError::BadResponse(Cow::Owned(s.into())).into())
+            }
+        }
+        Err(MemcacheError::CommandError(CommandError::KeyNotFound)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn parse_u64_response(response: &str) -> Result<u64, MemcacheError> {
+    let s = MemcacheError::try_from(response)?;
+    Ok(s.trim_end_matches("\r\n").parse::<u64>()?)
+}
+
+/// Parse a `STAT <name> <value>` line of a stats response, or `None` at its `END`.
+pub(crate) fn parse_stat_line(line: &str) -> Result<Option<(String, String)>, MemcacheError> {
+    let line = MemcacheError::try_from(line)?;
+    if line == END {
+        return Ok(None);
+    }
+    let mut stat = line.trim_end_matches("\r\n").splitn(3, ' ');
+    match (stat.next(), stat.next(), stat.next()) {
+        (Some("STAT"), Some(key), Some(value)) => Ok(Some((key.into(), value.into()))),
+        _ => Err(ServerError::BadResponse(Cow::Owned(line.into())))?,
+    }
+}
+
+/// Key, flags, data length and cas of a `VALUE` header line.
+pub(crate) type ValueHeader = (String, u32, usize, Option<u64>);
+
+/// Parse a `VALUE <key> <flags> <bytes> [<cas>]` header line, or `None` at the `END` of a get response.
+pub(crate) fn parse_value_header(buf: &str, has_cas: bool) -> Result<Option<ValueHeader>, MemcacheError> {
+    let buf = MemcacheError::try_from(buf)?;
+    if buf == END {
+        return Ok(None);
+    }
+    if !buf.starts_with("VALUE") {
+        Err(ServerError::BadResponse(Cow::Owned(buf.into())))?
+    }
+    let mut header = buf.trim_end_matches("\r\n").split(" ");
+    let mut next_or_err = || {
+        header
+            .next()
+            .ok_or_else(|| ServerError::BadResponse(Cow::Owned(buf.into())))
+    };
+    let _ = next_or_err()?;
+    let key = next_or_err()?;
+    let flags: u32 = next_or_err()?.parse()?;
+    let length: usize = next_or_err()?.parse()?;
+    let cas: Option<u64> = if has_cas { Some(next_or_err()?.parse()?) } else { None };
+    if header.next().is_some() {
+        Err(ServerError::BadResponse(Cow::Owned(buf.into())))?
+    }
+    Ok(Some((key.to_string(), flags, length, cas)))
+}
+
+/// Decode a data block read after a `VALUE` header, `value` holds the data and its trailing `\r\n`.
+pub(crate) fn parse_value_data<V: FromMemcacheValueExt>(
+    mut value: Vec<u8>,
+    flags: u32,
+    cas: Option<u64>,
+) -> Result<V, MemcacheError> {
+    let length = value.len() - 2;
+    if &value[length..] != b"\r\n" {
+        return Err(ServerError::BadResponse(Cow::Owned(String::from_utf8(value)?)).into());
     }
+    // remove the trailing \r\n
+    value.pop();
+    value.pop();
+    value.shrink_to_fit();
+    FromMemcacheValueExt::from_memcache_value(value, flags, cas)
 }
@@ -1,9 +1,12 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io::Write;
 
-use super::ProtocolTrait;
+use super::{raw_items, Item, ProtocolTrait, StoreOptions};
 use crate::client::Stats;
-use crate::error::MemcacheError;
+use crate::error::{MemcacheError, ServerError};
+use crate::pipeline::{Command, QuietCommand, Reply};
+use crate::protocol::ascii::StoreCommand;
 use crate::protocol::binary_packet::{self, Magic, Opcode, PacketHeader};
 use crate::stream::Stream;
 use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
@@ -11,6 +14,8 @@ use byteorder::{BigEndian, WriteBytesExt};
 
 pub struct BinaryProtocol {
     pub stream: Stream,
+    /// Whether quiet commands were sent since the last reply read, whose failures may still be pending.
+    quiet: bool,
 }
 
 impl ProtocolTrait for BinaryProtocol {
@@ -67,7 +72,7 @@ impl ProtocolTrait for BinaryProtocol {
         binary_packet::parse_response(&mut self.stream)?.err().map(|_| ())
     }
 
-    fn get<V: FromMemcacheValueExt>(&mut self, key: &str) -> Result<Option<V>, MemcacheError> {
+    fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
         let request_header = PacketHeader {
             magic: Magic::Request as u8,
             opcode: Opcode::Get as u8,
@@ -76,12 +81,12 @@ impl ProtocolTrait for BinaryProtocol {
             ..Default::default()
         };
         request_header.write(&mut self.stream)?;
-        self.stream.write_all(key.as_bytes())?;
+        self.stream.write_all(key)?;
         self.stream.flush()?;
         return binary_packet::parse_get_response(&mut self.stream);
     }
 
-    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&str]) -> Result<HashMap<String, V>, MemcacheError> {
+    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
         for key in keys {
             let request_header = PacketHeader {
                 magic: Magic::Request as u8,
@@ -91,7 +96,7 @@ impl ProtocolTrait for BinaryProtocol {
                 ..Default::default()
             };
             request_header.write(&mut self.stream)?;
-            self.stream.write_all(key.as_bytes())?;
+            self.stream.write_all(key)?;
         }
         let noop_request_header = PacketHeader {
             magic: Magic::Request as u8,
@@ -99,12 +104,46 @@ impl ProtocolTrait for BinaryProtocol {
             ..Default::default()
         };
         noop_request_header.write(&mut self.stream)?;
+        self.stream.flush()?;
         return binary_packet::parse_gets_response(&mut self.stream, keys.len());
     }
 
+    fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        Ok(raw_items(self.gets(keys)?))
+    }
+
+    fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        self.write_touch_request(Opcode::GetAndTouch, key, expiration)?;
+        self.stream.flush()?;
+        binary_packet::parse_get_response(&mut self.stream)
+    }
+
+    fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        // quiet requests only answer hits, with their key, and the noop marks the end
+        for key in keys {
+            self.write_touch_request(Opcode::GetAndTouchKQ, key, expiration)?;
+        }
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Noop as u8,
+            ..Default::default()
+        }
+        .write(&mut self.stream)?;
+        self.stream.flush()?;
+        binary_packet::parse_gets_response(&mut self.stream, keys.len())
+    }
+
     fn cas<V: ToMemcacheValue<Stream>>(
         &mut self,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
         cas: u64,
@@ -113,24 +152,24 @@ impl ProtocolTrait for BinaryProtocol {
         binary_packet::parse_cas_response(&mut self.stream)
     }
 
-    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
+    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
         return self.store(Opcode::Set, key, value, expiration, None);
     }
 
-    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError> {
+    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
         return self.store(Opcode::Add, key, value, expiration, None);
     }
 
     fn replace<V: ToMemcacheValue<Stream>>(
         &mut self,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
     ) -> Result<(), MemcacheError> {
         return self.store(Opcode::Replace, key, value, expiration, None);
     }
 
-    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V) -> Result<(), MemcacheError> {
+    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
         let request_header = PacketHeader {
             magic: Magic::Request as u8,
             opcode: Opcode::Append as u8,
@@ -139,13 +178,13 @@ impl ProtocolTrait for BinaryProtocol {
             ..Default::default()
         };
         request_header.write(&mut self.stream)?;
-        self.stream.write_all(key.as_bytes())?;
+        self.stream.write_all(key)?;
         value.write_to(&mut self.stream)?;
         self.stream.flush()?;
         binary_packet::parse_response(&mut self.stream)?.err().map(|_| ())
     }
 
-    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V) -> Result<(), MemcacheError> {
+    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
         let request_header = PacketHeader {
             magic: Magic::Request as u8,
             opcode: Opcode::Prepend as u8,
@@ -154,13 +193,13 @@ impl ProtocolTrait for BinaryProtocol {
             ..Default::default()
         };
         request_header.write(&mut self.stream)?;
-        self.stream.write_all(key.as_bytes())?;
+        self.stream.write_all(key)?;
         value.write_to(&mut self.stream)?;
         self.stream.flush()?;
-        binary_packet::parse_response(&mut self.stream).map(|_| ())
+        binary_packet::parse_response(&mut self.stream)?.err().map(|_| ())
     }
 
-    fn delete(&mut self, key: &str) -> Result<bool, MemcacheError> {
+    fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
         let request_header = PacketHeader {
             magic: Magic::Request as u8,
             opcode: Opcode::Delete as u8,
@@ -169,38 +208,187 @@ impl ProtocolTrait for BinaryProtocol {
             ..Default::default()
         };
         request_header.write(&mut self.stream)?;
-        self.stream.write_all(key.as_bytes())?;
+        self.stream.write_all(key)?;
         self.stream.flush()?;
         return binary_packet::parse_delete_response(&mut self.stream);
     }
 
-    fn increment(&mut self, key: &str, amount: u64) -> Result<u64, MemcacheError> {
+    fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.counter(Opcode::Increment, key, amount, 0, binary_packet::COUNTER_NO_INIT)
+    }
+
+    fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.counter(Opcode::Decrement, key, amount, 0, binary_packet::COUNTER_NO_INIT)
+    }
+
+    fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        self.counter(Opcode::Increment, key, amount, initial, expiration)
+    }
+
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        self.write_touch_request(Opcode::Touch, key, expiration)?;
+        self.stream.flush()?;
+        return binary_packet::parse_touch_response(&mut self.stream);
+    }
+
+    fn stats(&mut self) -> Result<Stats, MemcacheError> {
         let request_header = PacketHeader {
             magic: Magic::Request as u8,
-            opcode: Opcode::Increment as u8,
-            key_length: key.len() as u16,
-            extras_length: 20,
-            total_body_length: (20 + key.len()) as u32,
+            opcode: Opcode::Stat as u8,
             ..Default::default()
         };
-        let extras = binary_packet::CounterExtras {
-            amount,
-            initial_value: 0,
-            expiration: 0,
+        request_header.write(&mut self.stream)?;
+        self.stream.flush()?;
+        let stats_info = binary_packet::parse_stats_response(&mut self.stream)?;
+        return Ok(stats_info);
+    }
+}
+
+impl BinaryProtocol {
+    pub(crate) fn new(stream: Stream) -> Self {
+        Self { stream, quiet: false }
+    }
+
+    /// Store with the options of `StoreOptions`. A noreply store is sent with the quiet opcode, which the server
+    /// only answers on failure.
+    pub(crate) fn store_with<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let (opcode, extras_length) = binary_packet::store_opcode(command, options.noreply);
+        let request_header = PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: opcode as u8,
+            key_length: key.len() as u16,
+            extras_length,
+            total_body_length: (extras_length as usize + key.len() + value.get_length()) as u32,
+            cas: options.cas.unwrap_or(0),
+            ..Default::default()
         };
         request_header.write(&mut self.stream)?;
-        self.stream.write_u64::<BigEndian>(extras.amount)?;
-        self.stream.write_u64::<BigEndian>(extras.initial_value)?;
-        self.stream.write_u32::<BigEndian>(extras.expiration)?;
-        self.stream.write_all(key.as_bytes())?;
+        if extras_length > 0 {
+            self.stream
+                .write_u32::<BigEndian>(options.flags.unwrap_or_else(|| value.get_flags()))?;
+            self.stream.write_u32::<BigEndian>(options.expiration)?;
+        }
+        self.stream.write_all(key)?;
+        value.write_to(&mut self.stream)?;
+        self.stream.flush()?;
+
+        if options.noreply {
+            self.quiet = true;
+            return Ok(true);
+        }
+        binary_packet::parse_response(&mut self.stream)?.err().map(|_| true)
+    }
+
+    /// Skip the failures answered to the quiet commands sent since the last reply, up to a noop.
+    pub(crate) fn settle(&mut self) -> Result<(), MemcacheError> {
+        if !self.quiet {
+            return Ok(());
+        }
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Noop as u8,
+            ..Default::default()
+        }
+        .write(&mut self.stream)?;
+        self.stream.flush()?;
+        while binary_packet::parse_response(&mut self.stream)?.opcode() != Opcode::Noop as u8 {}
+        self.quiet = false;
+        Ok(())
+    }
+
+    pub(crate) fn execute(&mut self, commands: &[Command]) -> Result<Vec<Result<Reply, MemcacheError>>, MemcacheError> {
+        let mut request = Vec::new();
+        for (opaque, command) in commands.iter().enumerate() {
+            write_command(&mut request, command, opaque as u32, false)?;
+        }
+        self.stream.write_all(&request)?;
         self.stream.flush()?;
-        return binary_packet::parse_counter_response(&mut self.stream);
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for (opaque, command) in commands.iter().enumerate() {
+            let response = binary_packet::parse_response(&mut self.stream)?;
+            if response.opaque() != opaque as u32 {
+                Err(ServerError::BadResponse(Cow::Borrowed(
+                    "opaque doesn't match in the pipeline response",
+                )))?
+            }
+            replies.push(binary_packet::parse_reply(command, response));
+        }
+        Ok(replies)
     }
 
-    fn decrement(&mut self, key: &str, amount: u64) -> Result<u64, MemcacheError> {
+    /// Send a batch of writes with the quiet opcodes, closed by a noop. The server only answers the failed
+    /// writes, returned with the index of their command, taken from the opaque of the reply.
+    pub(crate) fn execute_quiet(
+        &mut self,
+        commands: &[QuietCommand],
+    ) -> Result<Vec<(usize, MemcacheError)>, MemcacheError> {
+        let mut request = Vec::new();
+        for (opaque, command) in commands.iter().enumerate() {
+            match command {
+                QuietCommand::Write(command) => write_command(&mut request, command, opaque as u32, true)?,
+                QuietCommand::Flush => PacketHeader {
+                    magic: Magic::Request as u8,
+                    opcode: Opcode::FlushQ as u8,
+                    opaque: opaque as u32,
+                    ..Default::default()
+                }
+                .write(&mut request)?,
+            }
+        }
+        PacketHeader {
+            magic: Magic::Request as u8,
+            opcode: Opcode::Noop as u8,
+            opaque: commands.len() as u32,
+            ..Default::default()
+        }
+        .write(&mut request)?;
+        self.stream.write_all(&request)?;
+        self.stream.flush()?;
+
+        let mut failures = Vec::new();
+        loop {
+            let response = binary_packet::parse_response(&mut self.stream)?;
+            if response.opcode() == Opcode::Noop as u8 {
+                return Ok(failures);
+            }
+            let index = response.opaque() as usize;
+            if index >= commands.len() {
+                Err(ServerError::BadResponse(Cow::Borrowed(
+                    "opaque doesn't match in the quiet batch response",
+                )))?
+            }
+            if let Err(e) = response.err() {
+                failures.push((index, e));
+            }
+        }
+    }
+
+    /// A missing key is created with the initial value and expiration of the extras, an expiration of
+    /// `COUNTER_NO_INIT` makes the command fail instead.
+    fn counter(
+        &mut self,
+        opcode: Opcode,
+        key: &[u8],
+        amount: u64,
+        initial_value: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
         let request_header = PacketHeader {
             magic: Magic::Request as u8,
-            opcode: Opcode::Decrement as u8,
+            opcode: opcode as u8,
             key_length: key.len() as u16,
             extras_length: 20,
             total_body_length: (20 + key.len()) as u32,
@@ -208,52 +396,38 @@ impl ProtocolTrait for BinaryProtocol {
         };
         let extras = binary_packet::CounterExtras {
             amount,
-            initial_value: 0,
-            expiration: 0,
+            initial_value,
+            expiration,
         };
         request_header.write(&mut self.stream)?;
         self.stream.write_u64::<BigEndian>(extras.amount)?;
         self.stream.write_u64::<BigEndian>(extras.initial_value)?;
         self.stream.write_u32::<BigEndian>(extras.expiration)?;
-        self.stream.write_all(key.as_bytes())?;
+        self.stream.write_all(key)?;
         self.stream.flush()?;
-        return binary_packet::parse_counter_response(&mut self.stream);
+        binary_packet::parse_counter_response(&mut self.stream)
     }
 
-    fn touch(&mut self, key: &str, expiration: u32) -> Result<bool, MemcacheError> {
-        let request_header = PacketHeader {
+    /// Write a request carrying the expiration in its extras, like touch and get and touch.
+    fn write_touch_request(&mut self, opcode: Opcode, key: &[u8], expiration: u32) -> Result<(), MemcacheError> {
+        PacketHeader {
             magic: Magic::Request as u8,
-            opcode: Opcode::Touch as u8,
+            opcode: opcode as u8,
             key_length: key.len() as u16,
             extras_length: 4,
             total_body_length: (key.len() as u32 + 4),
             ..Default::default()
-        };
-        request_header.write(&mut self.stream)?;
+        }
+        .write(&mut self.stream)?;
         self.stream.write_u32::<BigEndian>(expiration)?;
-        self.stream.write_all(key.as_bytes())?;
-        self.stream.flush()?;
-        return binary_packet::parse_touch_response(&mut self.stream);
+        self.stream.write_all(key)?;
+        Ok(())
     }
 
-    fn stats(&mut self) -> Result<Stats, MemcacheError> {
-        let request_header = PacketHeader {
-            magic: Magic::Request as u8,
-            opcode: Opcode::Stat as u8,
-            ..Default::default()
-        };
-        request_header.write(&mut self.stream)?;
-        self.stream.flush()?;
-        let stats_info = binary_packet::parse_stats_response(&mut self.stream)?;
-        return Ok(stats_info);
-    }
-}
-
-impl BinaryProtocol {
     fn send_request<V: ToMemcacheValue<Stream>>(
         &mut self,
         opcode: Opcode,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
         cas: Option<u64>,
@@ -274,7 +448,7 @@ impl BinaryProtocol {
         request_header.write(&mut self.stream)?;
         self.stream.write_u32::<BigEndian>(extras.flags)?;
         self.stream.write_u32::<BigEndian>(extras.expiration)?;
-        self.stream.write_all(key.as_bytes())?;
+        self.stream.write_all(key)?;
         value.write_to(&mut self.stream)?;
         self.stream.flush().map_err(Into::into)
     }
@@ -282,7 +456,7 @@ impl BinaryProtocol {
     fn store<V: ToMemcacheValue<Stream>>(
         &mut self,
         opcode: Opcode,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
         cas: Option<u64>,
@@ -291,3 +465,88 @@ impl BinaryProtocol {
         binary_packet::parse_response(&mut self.stream)?.err().map(|_| ())
     }
 }
+
+/// Write a pipelined command, with the quiet opcode of the writes when `quiet` is set.
+fn write_command(request: &mut Vec<u8>, command: &Command, opaque: u32, quiet: bool) -> Result<(), MemcacheError> {
+    match command {
+        Command::Get(key) | Command::Delete(key) => {
+            let opcode = match (command, quiet) {
+                (Command::Get(_), _) => Opcode::Get,
+                (_, false) => Opcode::Delete,
+                (_, true) => Opcode::DeleteQ,
+            };
+            PacketHeader {
+                magic: Magic::Request as u8,
+                opcode: opcode as u8,
+                key_length: key.len() as u16,
+                total_body_length: key.len() as u32,
+                opaque,
+                ..Default::default()
+            }
+            .write(request)?;
+            request.write_all(key)?;
+        }
+        Command::Store {
+            command,
+            key,
+            value,
+            flags,
+            expiration,
+        } => {
+            let (opcode, extras_length) = binary_packet::store_opcode(*command, quiet);
+            PacketHeader {
+                magic: Magic::Request as u8,
+                opcode: opcode as u8,
+                key_length: key.len() as u16,
+                extras_length,
+                total_body_length: (extras_length as usize + key.len() + value.len()) as u32,
+                opaque,
+                ..Default::default()
+            }
+            .write(request)?;
+            if extras_length > 0 {
+                request.write_u32::<BigEndian>(*flags)?;
+                request.write_u32::<BigEndian>(*expiration)?;
+            }
+            request.write_all(key)?;
+            request.write_all(value)?;
+        }
+        Command::Increment(key, amount) | Command::Decrement(key, amount) => {
+            let opcode = match (command, quiet) {
+                (Command::Increment(..), false) => Opcode::Increment,
+                (Command::Increment(..), true) => Opcode::IncrementQ,
+                (_, false) => Opcode::Decrement,
+                (_, true) => Opcode::DecrementQ,
+            };
+            PacketHeader {
+                magic: Magic::Request as u8,
+                opcode: opcode as u8,
+                key_length: key.len() as u16,
+                extras_length: 20,
+                total_body_length: (20 + key.len()) as u32,
+                opaque,
+                ..Default::default()
+            }
+            .write(request)?;
+            request.write_u64::<BigEndian>(*amount)?;
+            request.write_u64::<BigEndian>(0)?;
+            request.write_u32::<BigEndian>(binary_packet::COUNTER_NO_INIT)?;
+            request.write_all(key)?;
+        }
+        Command::Touch(key, expiration) => {
+            PacketHeader {
+                magic: Magic::Request as u8,
+                opcode: Opcode::Touch as u8,
+                key_length: key.len() as u16,
+                extras_length: 4,
+                total_body_length: (key.len() + 4) as u32,
+                opaque,
+                ..Default::default()
+            }
+            .write(request)?;
+            request.write_u32::<BigEndian>(*expiration)?;
+            request.write_all(key)?;
+        }
+    }
+    Ok(())
+}
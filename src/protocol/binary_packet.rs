@@ -1,4 +1,6 @@
 use crate::error::{CommandError, MemcacheError, ServerError};
+use crate::pipeline::{Command, Reply};
+use crate::protocol::ascii::StoreCommand;
 use crate::value::FromMemcacheValueExt;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::borrow::Cow;
@@ -23,10 +25,39 @@ pub enum Opcode {
     GetKQ = 0x0d,
     Append = 0x0e,
     Prepend = 0x0f,
+    SetQ = 0x11,
+    AddQ = 0x12,
+    ReplaceQ = 0x13,
+    DeleteQ = 0x14,
+    IncrementQ = 0x15,
+    DecrementQ = 0x16,
+    FlushQ = 0x18,
+    AppendQ = 0x19,
+    PrependQ = 0x1a,
     Touch = 0x1c,
+    GetAndTouch = 0x1d,
+    GetAndTouchQ = 0x1e,
+    GetAndTouchK = 0x23,
+    GetAndTouchKQ = 0x24,
     StartAuth = 0x21,
 }
 
+/// The opcode of a store command, quiet or not, and the length of its extras.
+pub(crate) fn store_opcode(command: StoreCommand, quiet: bool) -> (Opcode, u8) {
+    match (command, quiet) {
+        (StoreCommand::Set, false) | (StoreCommand::Cas, false) => (Opcode::Set, 8),
+        (StoreCommand::Set, true) | (StoreCommand::Cas, true) => (Opcode::SetQ, 8),
+        (StoreCommand::Add, false) => (Opcode::Add, 8),
+        (StoreCommand::Add, true) => (Opcode::AddQ, 8),
+        (StoreCommand::Replace, false) => (Opcode::Replace, 8),
+        (StoreCommand::Replace, true) => (Opcode::ReplaceQ, 8),
+        (StoreCommand::Append, false) => (Opcode::Append, 0),
+        (StoreCommand::Append, true) => (Opcode::AppendQ, 0),
+        (StoreCommand::Prepend, false) => (Opcode::Prepend, 0),
+        (StoreCommand::Prepend, true) => (Opcode::PrependQ, 0),
+    }
+}
+
 pub enum Magic {
     Request = 0x80,
     Response = 0x81,
@@ -51,6 +82,9 @@ pub struct StoreExtras {
     pub expiration: u32,
 }
 
+/// Expiration of a counter request failing on a missing key, instead of creating it.
+pub(crate) const COUNTER_NO_INIT: u32 = 0xffffffff;
+
 #[derive(Debug)]
 pub struct CounterExtras {
     pub amount: u64,
@@ -100,6 +134,14 @@ pub struct Response {
 }
 
 impl Response {
+    pub(crate) fn opaque(&self) -> u32 {
+        self.header.opaque
+    }
+
+    pub(crate) fn opcode(&self) -> u8 {
+        self.header.opcode
+    }
+
     pub(crate) fn err(self) -> Result<Self, MemcacheError> {
         let status = self.header.vbucket_id_or_status;
         if status == OK_STATUS {
@@ -166,7 +208,7 @@ pub fn parse_get_response<R: io::Read, V: FromMemcacheValueExt>(reader: &mut R)
 pub fn parse_gets_response<R: io::Read, V: FromMemcacheValueExt>(
     reader: &mut R,
     max_responses: usize,
-) -> Result<HashMap<String, V>, MemcacheError> {
+) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
     let mut result = HashMap::new();
     for _ in 0..=max_responses {
         let Response {
@@ -179,7 +221,6 @@ pub fn parse_gets_response<R: io::Read, V: FromMemcacheValueExt>(
             return Ok(result);
         }
         let flags = Cursor::new(extras).read_u32::<BigEndian>()?;
-        let key = String::from_utf8(key)?;
         result.insert(
             key,
             FromMemcacheValueExt::from_memcache_value(value, flags, Some(header.cas))?,
@@ -226,3 +267,39 @@ pub fn parse_stats_response<R: io::Read>(reader: &mut R) -> Result<HashMap<Strin
 pub fn parse_start_auth_response<R: io::Read>(reader: &mut R) -> Result<bool, MemcacheError> {
     parse_response(reader)?.err().map(|_| true)
 }
+
+/// Convert the response to a pipelined command into its reply, leaving the errors of the command itself in
+/// the inner result.
+pub(crate) fn parse_reply(command: &Command, response: Response) -> Result<Reply, MemcacheError> {
+    match (command, response.err()) {
+        (
+            Command::Get(_),
+            Ok(Response {
+                header, extras, value, ..
+            }),
+        ) => {
+            let flags = Cursor::new(extras).read_u32::<BigEndian>()?;
+            Ok(Reply::Value(Some(FromMemcacheValueExt::from_memcache_value(
+                value,
+                flags,
+                Some(header.cas),
+            )?)))
+        }
+        (Command::Get(_), Err(MemcacheError::CommandError(CommandError::KeyNotFound))) => Ok(Reply::Value(None)),
+        (Command::Store { .. }, Ok(_)) => Ok(Reply::Stored(true)),
+        // add of an existing key, replace of a missing key and "item not stored" on append or prepend
+        (Command::Store { .. }, Err(MemcacheError::CommandError(CommandError::KeyExists)))
+        | (Command::Store { .. }, Err(MemcacheError::CommandError(CommandError::KeyNotFound)))
+        | (Command::Store { .. }, Err(MemcacheError::CommandError(CommandError::Unknown(0x5)))) => {
+            Ok(Reply::Stored(false))
+        }
+        (Command::Delete(_), Ok(_)) => Ok(Reply::Deleted(true)),
+        (Command::Delete(_), Err(MemcacheError::CommandError(CommandError::KeyNotFound))) => Ok(Reply::Deleted(false)),
+        (Command::Touch(..), Ok(_)) => Ok(Reply::Touched(true)),
+        (Command::Touch(..), Err(MemcacheError::CommandError(CommandError::KeyNotFound))) => Ok(Reply::Touched(false)),
+        (Command::Increment(..), Ok(Response { value, .. })) | (Command::Decrement(..), Ok(Response { value, .. })) => {
+            Ok(Reply::Counter(Cursor::new(value).read_u64::<BigEndian>()?))
+        }
+        (_, Err(e)) => Err(e),
+    }
+}
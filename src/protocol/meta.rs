@@ -0,0 +1,838 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use super::ascii::{self, CappedLineReader, StoreCommand};
+use super::{Item, ProtocolTrait, RawItem, StoreOptions};
+use crate::client::Stats;
+use crate::compression;
+use crate::error::{ClientError, CommandError, MemcacheError, ServerError};
+use crate::pipeline::{Command, Reply};
+use crate::stream::Stream;
+use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
+
+/// Item metadata to fetch along with the value with a meta get command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetaGetOptions {
+    /// Return the cas token of the item.
+    pub cas: bool,
+    /// Return the remaining time to live of the item.
+    pub ttl: bool,
+    /// Return the seconds since the item was last accessed.
+    pub last_access: bool,
+    /// Return whether the item has been fetched before.
+    pub hit: bool,
+    /// Opaque token the server echoes back in the response.
+    pub opaque: Option<u32>,
+    /// Update the expiration of the item to this many seconds, like `touch`.
+    pub touch: Option<u32>,
+}
+
+impl MetaGetOptions {
+    pub(crate) fn flags(&self) -> String {
+        let mut flags = String::from("v f");
+        if self.cas {
+            flags.push_str(" c");
+        }
+        if self.ttl {
+            flags.push_str(" t");
+        }
+        if self.last_access {
+            flags.push_str(" l");
+        }
+        if self.hit {
+            flags.push_str(" h");
+        }
+        if let Some(opaque) = self.opaque {
+            flags.push_str(&format!(" O{}", opaque));
+        }
+        if let Some(expiration) = self.touch {
+            flags.push_str(&format!(" T{}", expiration));
+        }
+        flags
+    }
+}
+
+/// A value fetched with a meta get command, with the item metadata asked for in `MetaGetOptions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaValue<V> {
+    pub value: V,
+    /// Client flags stored with the value, without the bits marking a compressed value.
+    pub flags: u32,
+    pub cas: Option<u64>,
+    /// Remaining time to live in seconds, `-1` when the item never expires.
+    pub ttl: Option<i64>,
+    /// Seconds since the item was last accessed, before this fetch.
+    pub last_access: Option<u64>,
+    /// Whether the item had been fetched before this fetch.
+    pub hit: Option<bool>,
+    pub opaque: Option<u32>,
+}
+
+/// An item fetched with the meta get flags guarding a key against stampedes: a missing key is created as an
+/// empty placeholder, and a single client at a time wins the right to recompute a missing, stale or expiring
+/// value.
+pub(crate) struct Lease {
+    /// The value with its flags, or `None` for the placeholder of a missing key.
+    pub(crate) value: Option<(Vec<u8>, u32)>,
+    pub(crate) cas: Option<u64>,
+    /// `W`, this client won the right to recompute the value.
+    pub(crate) win: bool,
+}
+
+/// Status code of a meta command response.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Status {
+    /// `VA <size>`, the value data follows the line.
+    Value(usize),
+    /// `HD`, success without a value.
+    Header,
+    /// `EN`, the item was not found by a get.
+    Miss,
+    /// `NF`, the item was not found.
+    NotFound,
+    /// `NS`, the item was not stored.
+    NotStored,
+    /// `EX`, the cas token did not match.
+    Exists,
+    /// `MN`, end of a pipeline of quiet commands.
+    Noop,
+}
+
+/// Response line of a meta command, holding the status and the returned flags.
+pub(crate) struct Response {
+    pub(crate) status: Status,
+    line: String,
+}
+
+impl Response {
+    fn flags(&self) -> impl Iterator<Item = &str> {
+        let skip = if let Status::Value(_) = self.status { 2 } else { 1 };
+        self.line.split(' ').skip(skip).filter(|token| !token.is_empty())
+    }
+
+    pub(crate) fn flag(&self, flag: char) -> Option<&str> {
+        self.flags()
+            .find(|token| token.starts_with(flag))
+            .map(|token| &token[1..])
+    }
+
+    pub(crate) fn parse_flag<T>(&self, flag: char) -> Result<Option<T>, MemcacheError>
+    where
+        T: FromStr,
+        MemcacheError: From<T::Err>,
+    {
+        match self.flag(flag) {
+            Some(value) => Ok(Some(value.parse()?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn unexpected(self) -> MemcacheError {
+        ServerError::BadResponse(Cow::Owned(self.line)).into()
+    }
+}
+
+/// Keys that can't be written into a command line as is are sent base64 encoded, along with the `b` flag.
+pub(crate) fn encode_key(key: &[u8]) -> (Cow<'_, str>, bool) {
+    match std::str::from_utf8(key) {
+        Ok(s) if !s.is_empty() && s.bytes().all(|b| b.is_ascii_graphic()) => (Cow::Borrowed(s), false),
+        _ => (Cow::Owned(BASE64.encode(key)), true),
+    }
+}
+
+/// Format a meta command line for the key with the given flags.
+pub(crate) fn command_line(command: &str, key: &[u8], flags: &str) -> String {
+    let (key, base64) = encode_key(key);
+    let mut line = format!("{} {}", command, key);
+    if !flags.is_empty() {
+        line.push(' ');
+        line.push_str(flags);
+    }
+    if base64 {
+        line.push_str(" b");
+    }
+    line.push_str("\r\n");
+    line
+}
+
+/// Format the `ms` command line storing `length` bytes of data, mapping the ascii store commands to modes.
+pub(crate) fn store_command_line(
+    command: StoreCommand,
+    key: &[u8],
+    length: usize,
+    flags: u32,
+    options: &StoreOptions,
+) -> Result<String, MemcacheError> {
+    let mut meta_flags = length.to_string();
+    match command {
+        StoreCommand::Append => meta_flags.push_str(" MA"),
+        StoreCommand::Prepend => meta_flags.push_str(" MP"),
+        _ => {
+            meta_flags.push_str(&format!(" T{} F{}", options.expiration, options.flags.unwrap_or(flags)));
+            match command {
+                StoreCommand::Add => meta_flags.push_str(" ME"),
+                StoreCommand::Replace => meta_flags.push_str(" MR"),
+                _ => (),
+            }
+        }
+    }
+    match options.cas {
+        Some(cas) => meta_flags.push_str(&format!(" C{}", cas)),
+        None if command == StoreCommand::Cas => Err(ClientError::Error(Cow::Borrowed(
+            "cas_id should be present when using cas command",
+        )))?,
+        None => (),
+    }
+    if options.noreply {
+        meta_flags.push_str(" q");
+    }
+    Ok(command_line("ms", key, &meta_flags))
+}
+
+pub(crate) fn parse_response(line: &str) -> Result<Response, MemcacheError> {
+    let line = MemcacheError::try_from(line)?.trim_end_matches("\r\n");
+    let mut tokens = line.split(' ');
+    let status = match tokens.next() {
+        Some("VA") => match tokens.next().map(str::parse) {
+            Some(Ok(length)) => Status::Value(length),
+            _ => Err(ServerError::BadResponse(Cow::Owned(line.into())))?,
+        },
+        Some("HD") => Status::Header,
+        Some("EN") => Status::Miss,
+        Some("NF") => Status::NotFound,
+        Some("NS") => Status::NotStored,
+        Some("EX") => Status::Exists,
+        Some("MN") => Status::Noop,
+        _ => Err(ServerError::BadResponse(Cow::Owned(line.into())))?,
+    };
+    Ok(Response {
+        status,
+        line: line.into(),
+    })
+}
+
+pub(crate) fn parse_store_response(response: Response) -> Result<bool, MemcacheError> {
+    match response.status {
+        Status::Header => Ok(true),
+        Status::NotStored => Ok(false),
+        Status::Exists => Err(CommandError::KeyExists)?,
+        Status::NotFound => Err(CommandError::KeyNotFound)?,
+        _ => Err(response.unexpected()),
+    }
+}
+
+/// Decode the value data read after a `VA` response, `data` holds the data and its trailing `\r\n`.
+pub(crate) fn parse_meta_value<V: FromMemcacheValueExt>(
+    response: &Response,
+    data: Vec<u8>,
+) -> Result<MetaValue<V>, MemcacheError> {
+    let flags = response.parse_flag('f')?.unwrap_or(0);
+    let cas = response.parse_flag('c')?;
+    Ok(MetaValue {
+        value: ascii::parse_value_data(data, flags, cas)?,
+        flags,
+        cas,
+        ttl: response.parse_flag('t')?,
+        last_access: response.parse_flag('l')?,
+        hit: response.parse_flag::<u8>('h')?.map(|hit| hit == 1),
+        opaque: response.parse_flag('O')?,
+    })
+}
+
+/// Decode the value read after the `VA` response of a meta get. It's decompressed first, so that its flags are
+/// reported without the bits marking a compressed value, like [`Item::decode`] does.
+pub(crate) fn parse_meta_get_value<V: FromMemcacheValueExt>(
+    response: &Response,
+    data: Vec<u8>,
+) -> Result<MetaValue<V>, MemcacheError> {
+    let raw: MetaValue<(Vec<u8>, u32, Option<u64>)> = parse_meta_value(response, data)?;
+    let (value, flags) = compression::decompress(raw.value.0, raw.flags)?;
+    Ok(MetaValue {
+        value: V::from_memcache_value(value, flags, raw.cas)?,
+        flags,
+        cas: raw.cas,
+        ttl: raw.ttl,
+        last_access: raw.last_access,
+        hit: raw.hit,
+        opaque: raw.opaque,
+    })
+}
+
+/// Parse the value of a lease get. An empty value is taken as the placeholder of a missing key when a client won
+/// the lease or is already recomputing it, as flagged by `Z`, unless it is a stale value, flagged by `X`.
+pub(crate) fn parse_lease(response: &Response, mut data: Vec<u8>) -> Result<Lease, MemcacheError> {
+    if !data.ends_with(b"\r\n") {
+        return Err(ServerError::BadResponse(Cow::Owned(String::from_utf8(data)?)).into());
+    }
+    // remove the trailing \r\n
+    data.truncate(data.len() - 2);
+    let flags = response.parse_flag('f')?.unwrap_or(0);
+    let win = response.flag('W').is_some();
+    let pending = win || response.flag('Z').is_some();
+    let placeholder = data.is_empty() && flags == 0 && pending && response.flag('X').is_none();
+    Ok(Lease {
+        value: if placeholder { None } else { Some((data, flags)) },
+        cas: response.parse_flag('c')?,
+        win,
+    })
+}
+
+/// Keep the values of a multi-key meta get.
+pub(crate) fn values<V>(values: HashMap<Vec<u8>, MetaValue<V>>) -> HashMap<Vec<u8>, V> {
+    values.into_iter().map(|(key, value)| (key, value.value)).collect()
+}
+
+/// Turn a multi-key meta get into items, along with the metadata the meta protocol returns.
+pub(crate) fn items(values: HashMap<Vec<u8>, MetaValue<RawItem>>) -> HashMap<Vec<u8>, Item<Vec<u8>>> {
+    values
+        .into_iter()
+        .map(|(key, value)| {
+            let item = Item {
+                ttl_remaining: value.ttl,
+                last_access: value.last_access,
+                ..value.value.0
+            };
+            (key, item)
+        })
+        .collect()
+}
+
+/// Parse the `ME <key> <name>=<value>*` response of a meta debug command, or `None` on `EN`.
+pub(crate) fn parse_debug_response(line: &str) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+    let line = MemcacheError::try_from(line)?.trim_end_matches("\r\n");
+    if line == "EN" {
+        return Ok(None);
+    }
+    if !line.starts_with("ME ") {
+        Err(ServerError::BadResponse(Cow::Owned(line.into())))?
+    }
+    Ok(Some(
+        line.split(' ')
+            .skip(2)
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect(),
+    ))
+}
+
+pub(crate) fn parse_u64_data(mut data: Vec<u8>) -> Result<u64, MemcacheError> {
+    data.truncate(data.len().saturating_sub(2));
+    Ok(String::from_utf8(data)?.parse()?)
+}
+
+pub struct MetaProtocol<C: Read + Write + Sized> {
+    reader: CappedLineReader<C>,
+    /// Whether quiet commands were sent since the last reply read, whose failures may still be pending.
+    quiet: bool,
+}
+
+impl ProtocolTrait for MetaProtocol<Stream> {
+    fn auth(&mut self, username: &str, password: &str) -> Result<(), MemcacheError> {
+        // meta commands have no authentication of their own, the ascii `set` of credentials is used instead
+        let credentials = format!("{} {}", username, password);
+        self.send(format!("set auth 0 0 {}\r\n{}\r\n", credentials.len(), credentials).as_bytes())?;
+        self.reader.read_line(ascii::parse_store_response).map(|_| ())
+    }
+
+    fn version(&mut self) -> Result<String, MemcacheError> {
+        self.send(b"version\r\n")?;
+        self.reader.read_line(ascii::parse_version_response)
+    }
+
+    fn flush(&mut self) -> Result<(), MemcacheError> {
+        self.send(b"flush_all\r\n")?;
+        self.reader.read_line(ascii::parse_ok_response)
+    }
+
+    fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError> {
+        self.send(format!("flush_all {}\r\n", delay).as_bytes())?;
+        self.reader.read_line(ascii::parse_ok_response)
+    }
+
+    fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError> {
+        // values are read as stored, for the raw tuple to keep its compression bits
+        Ok(self
+            .read_meta_value(key, &Default::default(), parse_meta_value)?
+            .map(|value| value.value))
+    }
+
+    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        Ok(values(self.multi_get(keys, "v f c")?))
+    }
+
+    fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError> {
+        Ok(items(self.multi_get(keys, "v f c t l")?))
+    }
+
+    fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError> {
+        let options = MetaGetOptions {
+            cas: true,
+            touch: Some(expiration),
+            ..Default::default()
+        };
+        Ok(self.meta_get(key, &options)?.map(|value| value.value))
+    }
+
+    fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError> {
+        Ok(values(self.multi_get(keys, &format!("v f c T{}", expiration))?))
+    }
+
+    fn cas<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            cas: Some(cas),
+            ..Default::default()
+        };
+        match self.store(StoreCommand::Cas, key, value, &options) {
+            Ok(t) => Ok(t),
+            Err(MemcacheError::CommandError(e)) if e == CommandError::KeyExists || e == CommandError::KeyNotFound => {
+                Ok(false)
+            }
+            e => e,
+        }
+    }
+
+    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Set, key, value, &options).map(|_| ())
+    }
+
+    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Add, key, value, &options).map(|_| ())
+    }
+
+    fn replace<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        key: &[u8],
+        value: V,
+        expiration: u32,
+    ) -> Result<(), MemcacheError> {
+        let options = StoreOptions {
+            expiration,
+            ..Default::default()
+        };
+        self.store(StoreCommand::Replace, key, value, &options).map(|_| ())
+    }
+
+    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
+        self.store(StoreCommand::Append, key, value, &Default::default())
+            .map(|_| ())
+    }
+
+    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError> {
+        self.store(StoreCommand::Prepend, key, value, &Default::default())
+            .map(|_| ())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError> {
+        self.send(command_line("md", key, "").as_bytes())?;
+        let response = self.reader.read_line(parse_response)?;
+        match response.status {
+            Status::Header => Ok(true),
+            Status::NotFound => Ok(false),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.arithmetic(key, &format!("v D{}", amount))
+    }
+
+    fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError> {
+        self.arithmetic(key, &format!("v MD D{}", amount))
+    }
+
+    fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError> {
+        // the N flag creates a missing counter with the J initial value, and its ttl
+        self.arithmetic(key, &format!("v N{} J{} D{}", expiration, initial, amount))
+    }
+
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError> {
+        self.send(command_line("mg", key, &format!("T{}", expiration)).as_bytes())?;
+        let response = self.reader.read_line(parse_response)?;
+        match response.status {
+            Status::Header => Ok(true),
+            Status::Miss => Ok(false),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    fn stats(&mut self) -> Result<Stats, MemcacheError> {
+        self.send(b"stats\r\n")?;
+        let mut stats: Stats = HashMap::new();
+        while let Some((key, value)) = self.reader.read_line(ascii::parse_stat_line)? {
+            stats.insert(key, value);
+        }
+        Ok(stats)
+    }
+}
+
+impl MetaProtocol<Stream> {
+    pub(crate) fn new(stream: Stream) -> Self {
+        Self {
+            reader: CappedLineReader::new(stream),
+            quiet: false,
+        }
+    }
+
+    pub(crate) fn stream(&mut self) -> &mut Stream {
+        self.reader.get_mut()
+    }
+
+    /// Skip the failures answered to the quiet commands sent since the last reply, up to a noop.
+    pub(crate) fn settle(&mut self) -> Result<(), MemcacheError> {
+        if !self.quiet {
+            return Ok(());
+        }
+        self.send(b"mn\r\n")?;
+        loop {
+            let response = self.reader.read_line(parse_response)?;
+            match response.status {
+                Status::Noop => break,
+                Status::Value(length) => {
+                    self.read_data(length)?;
+                }
+                _ => (),
+            }
+        }
+        self.quiet = false;
+        Ok(())
+    }
+
+    /// Get a value along with the item metadata selected by `options`. Keys which are not printable ascii are
+    /// sent base64 encoded.
+    pub fn meta_get<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        options: &MetaGetOptions,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        self.read_meta_value(key, options, parse_meta_get_value)
+    }
+
+    /// Send a meta get and decode its value with `parse`.
+    fn read_meta_value<V>(
+        &mut self,
+        key: &[u8],
+        options: &MetaGetOptions,
+        parse: fn(&Response, Vec<u8>) -> Result<MetaValue<V>, MemcacheError>,
+    ) -> Result<Option<MetaValue<V>>, MemcacheError> {
+        self.send(command_line("mg", key, &options.flags()).as_bytes())?;
+        let response = self.reader.read_line(parse_response)?;
+        match response.status {
+            Status::Value(length) => {
+                let data = self.read_data(length)?;
+                Ok(Some(parse(&response, data)?))
+            }
+            Status::Miss => Ok(None),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    /// Get a key with a lease, creating a placeholder living `vivify` seconds when it is missing, and handing
+    /// out a win when it has less than `recache` seconds left to live.
+    pub(crate) fn lease_get(&mut self, key: &[u8], vivify: u32, recache: Option<u32>) -> Result<Lease, MemcacheError> {
+        let mut flags = format!("v f c N{}", vivify);
+        if let Some(recache) = recache {
+            flags.push_str(&format!(" R{}", recache));
+        }
+        self.send(command_line("mg", key, &flags).as_bytes())?;
+        let response = self.reader.read_line(parse_response)?;
+        match response.status {
+            Status::Value(length) => {
+                let data = self.read_data(length)?;
+                parse_lease(&response, data)
+            }
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    /// Give up a lease won by `lease_get`: the placeholder of a missing key is deleted, and a stale or
+    /// expiring value is invalidated, so that the next get wins it. Nothing is done if the item changed since.
+    pub(crate) fn release_lease(&mut self, key: &[u8], lease: &Lease) -> Result<(), MemcacheError> {
+        let mut flags = String::new();
+        if let Some(cas) = lease.cas {
+            flags.push_str(&format!("C{}", cas));
+        }
+        if lease.value.is_some() {
+            flags.push_str(" I");
+        }
+        self.send(command_line("md", key, flags.trim_start()).as_bytes())?;
+        let response = self.reader.read_line(parse_response)?;
+        match response.status {
+            Status::Header | Status::NotFound | Status::Exists => Ok(()),
+            _ => Err(response.unexpected()),
+        }
+    }
+
+    /// Get the internal metadata memcached holds for the item, like `exp`, `la`, `cas`, `fetch`, `cls` and
+    /// `size`, without affecting it.
+    pub fn meta_debug(&mut self, key: &[u8]) -> Result<Option<HashMap<String, String>>, MemcacheError> {
+        self.send(command_line("me", key, "").as_bytes())?;
+        self.reader.read_line(parse_debug_response)
+    }
+
+    pub(crate) fn execute(&mut self, commands: &[Command]) -> Result<Vec<Result<Reply, MemcacheError>>, MemcacheError> {
+        let mut request = Vec::new();
+        for command in commands {
+            match command {
+                Command::Get(key) => request.extend_from_slice(command_line("mg", key, "v f c").as_bytes()),
+                Command::Store {
+                    command,
+                    key,
+                    value,
+                    flags,
+                    expiration,
+                } => {
+                    let options = StoreOptions {
+                        expiration: *expiration,
+                        ..Default::default()
+                    };
+                    let line = store_command_line(*command, key, value.len(), *flags, &options)?;
+                    request.extend_from_slice(line.as_bytes());
+                    request.extend_from_slice(value);
+                    request.extend_from_slice(b"\r\n");
+                }
+                Command::Delete(key) => request.extend_from_slice(command_line("md", key, "").as_bytes()),
+                Command::Increment(key, amount) => {
+                    request.extend_from_slice(command_line("ma", key, &format!("v D{}", amount)).as_bytes())
+                }
+                Command::Decrement(key, amount) => {
+                    request.extend_from_slice(command_line("ma", key, &format!("v MD D{}", amount)).as_bytes())
+                }
+                Command::Touch(key, expiration) => {
+                    request.extend_from_slice(command_line("mg", key, &format!("T{}", expiration)).as_bytes())
+                }
+            }
+        }
+        self.send(&request)?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for command in commands {
+            let response = match self
+                .reader
+                .read_line(|line| ascii::command_reply(line, parse_response))?
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    replies.push(Err(e));
+                    continue;
+                }
+            };
+            let data = match response.status {
+                Status::Value(length) => Some(self.read_data(length)?),
+                _ => None,
+            };
+            let reply = match (command, &response.status, data) {
+                (Command::Get(_), Status::Value(_), Some(data)) => {
+                    Ok(Reply::Value(Some(parse_meta_value(&response, data)?.value)))
+                }
+                (Command::Get(_), Status::Miss, _) => Ok(Reply::Value(None)),
+                (Command::Store { .. }, _, _) => parse_store_response(response).map(Reply::Stored),
+                (Command::Delete(_), Status::Header, _) => Ok(Reply::Deleted(true)),
+                (Command::Delete(_), Status::NotFound, _) => Ok(Reply::Deleted(false)),
+                (Command::Increment(..), Status::Value(_), Some(data))
+                | (Command::Decrement(..), Status::Value(_), Some(data)) => Ok(Reply::Counter(parse_u64_data(data)?)),
+                (Command::Increment(..), Status::NotFound, _) | (Command::Decrement(..), Status::NotFound, _) => {
+                    Err(CommandError::KeyNotFound.into())
+                }
+                (Command::Touch(..), Status::Header, _) => Ok(Reply::Touched(true)),
+                (Command::Touch(..), Status::Miss, _) => Ok(Reply::Touched(false)),
+                _ => return Err(response.unexpected()),
+            };
+            replies.push(reply);
+        }
+        Ok(replies)
+    }
+
+    /// Get several keys with the given meta flags, in a single round trip.
+    fn multi_get<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        flags: &str,
+    ) -> Result<HashMap<Vec<u8>, MetaValue<V>>, MemcacheError> {
+        // quiet gets only answer hits, tagged with the key index as opaque, and the noop marks the end
+        let mut request = String::new();
+        for (index, key) in keys.iter().enumerate() {
+            request.push_str(&command_line("mg", key, &format!("{} q O{}", flags, index)));
+        }
+        request.push_str("mn\r\n");
+        self.send(request.as_bytes())?;
+
+        let mut result: HashMap<Vec<u8>, MetaValue<V>> = HashMap::with_capacity(keys.len());
+        loop {
+            let response = self.reader.read_line(parse_response)?;
+            match response.status {
+                Status::Value(length) => {
+                    let data = self.read_data(length)?;
+                    let key = match response.parse_flag::<usize>('O')?.and_then(|index| keys.get(index)) {
+                        Some(key) => key,
+                        None => return Err(response.unexpected()),
+                    };
+                    result.insert(key.to_vec(), parse_meta_value(&response, data)?);
+                }
+                Status::Noop => return Ok(result),
+                _ => return Err(response.unexpected()),
+            }
+        }
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<(), MemcacheError> {
+        self.reader.get_mut().write_all(request)?;
+        self.reader.get_mut().flush()?;
+        Ok(())
+    }
+
+    fn read_data(&mut self, length: usize) -> Result<Vec<u8>, MemcacheError> {
+        let mut data = vec![0u8; length + 2];
+        self.reader.read_exact(data.as_mut_slice())?;
+        Ok(data)
+    }
+
+    pub(crate) fn store<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let line = store_command_line(command, key, value.get_length(), value.get_flags(), options)?;
+        self.reader.get_mut().write_all(line.as_bytes())?;
+        value.write_to(self.reader.get_mut())?;
+        self.send(b"\r\n")?;
+
+        if options.noreply {
+            // quiet stores are still answered on failure
+            self.quiet = true;
+            return Ok(true);
+        }
+
+        parse_store_response(self.reader.read_line(parse_response)?)
+    }
+
+    fn arithmetic(&mut self, key: &[u8], flags: &str) -> Result<u64, MemcacheError> {
+        self.send(command_line("ma", key, flags).as_bytes())?;
+        let response = self.reader.read_line(parse_response)?;
+        match response.status {
+            Status::Value(length) => parse_u64_data(self.read_data(length)?),
+            Status::NotFound => Err(CommandError::KeyNotFound)?,
+            _ => Err(response.unexpected()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_keys() {
+        assert_eq!(encode_key(b"foo:bar"), (Cow::Borrowed("foo:bar"), false));
+        assert_eq!(encode_key(b"foo bar"), (Cow::Owned("Zm9vIGJhcg==".into()), true));
+        assert_eq!(encode_key(b"\x00\xff"), (Cow::Owned("AP8=".into()), true));
+        assert_eq!(command_line("mg", b"foo", "v"), "mg foo v\r\n");
+        assert_eq!(command_line("md", b"foo\n", ""), "md Zm9vCg== b\r\n");
+    }
+
+    #[test]
+    fn store_command_lines() {
+        let options = StoreOptions {
+            expiration: 10,
+            ..Default::default()
+        };
+        let line = store_command_line(StoreCommand::Set, b"foo", 3, 0, &options).unwrap();
+        assert_eq!(line, "ms foo 3 T10 F0\r\n");
+        let line = store_command_line(StoreCommand::Add, b"foo", 3, 1, &options).unwrap();
+        assert_eq!(line, "ms foo 3 T10 F1 ME\r\n");
+        let line = store_command_line(StoreCommand::Append, b"foo", 3, 0, &options).unwrap();
+        assert_eq!(line, "ms foo 3 MA\r\n");
+        assert!(store_command_line(StoreCommand::Cas, b"foo", 3, 0, &options).is_err());
+        let options = StoreOptions {
+            cas: Some(42),
+            noreply: true,
+            ..Default::default()
+        };
+        let line = store_command_line(StoreCommand::Cas, b"foo", 3, 0, &options).unwrap();
+        assert_eq!(line, "ms foo 3 T0 F0 C42 q\r\n");
+    }
+
+    #[test]
+    fn parse_leases() {
+        let won = parse_lease(&parse_response("VA 0 f0 c3 W\r\n").unwrap(), b"\r\n".to_vec()).unwrap();
+        assert!(won.win);
+        assert_eq!((won.value, won.cas), (None, Some(3)));
+        let pending = parse_lease(&parse_response("VA 0 f0 c3 Z\r\n").unwrap(), b"\r\n".to_vec()).unwrap();
+        assert!(!pending.win && pending.value.is_none());
+        let stale = parse_lease(&parse_response("VA 3 f5 c4 X Z\r\n").unwrap(), b"old\r\n".to_vec()).unwrap();
+        assert_eq!(stale.value, Some((b"old".to_vec(), 5)));
+        let hit = parse_lease(&parse_response("VA 0 f0 c5\r\n").unwrap(), b"\r\n".to_vec()).unwrap();
+        assert_eq!(hit.value, Some((Vec::new(), 0)));
+        assert!(parse_lease(&parse_response("VA 3 f0\r\n").unwrap(), b"old".to_vec()).is_err());
+    }
+
+    #[test]
+    fn parse_responses() {
+        let response = parse_response("VA 3 f5 c12 t-1 l3 h1 O7\r\n").unwrap();
+        assert_eq!(response.status, Status::Value(3));
+        let value: MetaValue<String> = parse_meta_value(&response, b"bar\r\n".to_vec()).unwrap();
+        assert_eq!(
+            value,
+            MetaValue {
+                value: "bar".into(),
+                flags: 5,
+                cas: Some(12),
+                ttl: Some(-1),
+                last_access: Some(3),
+                hit: Some(true),
+                opaque: Some(7),
+            }
+        );
+        assert_eq!(parse_response("HD\r\n").unwrap().status, Status::Header);
+        assert_eq!(parse_response("EN\r\n").unwrap().status, Status::Miss);
+        assert_eq!(parse_response("MN\r\n").unwrap().status, Status::Noop);
+        assert!(parse_response("VA x\r\n").is_err());
+        assert!(parse_response("CLIENT_ERROR bad command line format\r\n").is_err());
+        assert!(!parse_store_response(parse_response("NS\r\n").unwrap()).unwrap());
+    }
+
+    #[test]
+    fn parse_debug_responses() {
+        let debug = parse_debug_response("ME foo exp=-1 la=4 cas=2 fetch=no cls=1 size=63\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(debug["exp"], "-1");
+        assert_eq!(debug["size"], "63");
+        assert!(parse_debug_response("EN\r\n").unwrap().is_none());
+    }
+}
@@ -1,20 +1,172 @@
-mod ascii;
+pub(crate) mod ascii;
 mod binary;
-mod binary_packet;
+pub(crate) mod binary_packet;
+pub(crate) mod meta;
 
 use crate::client::Stats;
-use crate::error::MemcacheError;
+use crate::compression;
+use crate::error::{ClientError, CommandError, MemcacheError};
+use crate::pipeline::{Command, Reply};
 pub(crate) use crate::protocol::ascii::AsciiProtocol;
+use crate::protocol::ascii::StoreCommand;
 pub(crate) use crate::protocol::binary::BinaryProtocol;
+use crate::protocol::binary_packet::COUNTER_NO_INIT;
+pub(crate) use crate::protocol::meta::MetaProtocol;
+pub use crate::protocol::meta::{MetaGetOptions, MetaValue};
 use crate::stream::Stream;
 use crate::value::{FromMemcacheValueExt, ToMemcacheValue};
 use enum_dispatch::enum_dispatch;
 use std::collections::HashMap;
 
+/// Options of the store commands taking them, like [`Client::set_with`](crate::Client::set_with).
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::StoreOptions;
+///
+/// let options = StoreOptions {
+///     expiration: 60,
+///     flags: Some(0x100),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoreOptions {
+    /// Expiration of the item in seconds, `0` for no expiration.
+    pub expiration: u32,
+    /// Don't wait for the server to answer. The command then always reports the value as stored, as a failure
+    /// is only read and discarded by the next command on the connection.
+    pub noreply: bool,
+    /// Client flags to store with the value, overriding `ToMemcacheValue::get_flags`. The bit marking a
+    /// compressed value is still added on top of them, see [`Flags`](crate::Flags) for the reserved bits.
+    pub flags: Option<u32>,
+    /// Store the value only if the item still has this cas token, as returned by `gets`.
+    pub cas: Option<u64>,
+}
+
+/// Reject the expiration which the binary protocol takes as not to create a missing counter, so that
+/// `increment_or_init` creates counters with every protocol.
+pub(crate) fn check_counter_expiration(expiration: u32) -> Result<(), MemcacheError> {
+    if expiration == COUNTER_NO_INIT {
+        Err(ClientError::Error(
+            "increment_or_init can't take an expiration of u32::MAX, which the binary protocol reserves".into(),
+        ))?
+    }
+    Ok(())
+}
+
+/// A value along with the metadata of its item, as returned by [`Client::get_item`](crate::Client::get_item).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item<V> {
+    pub value: V,
+    /// Client flags stored with the value, without the bits marking a compressed value.
+    pub flags: u32,
+    pub cas: Option<u64>,
+    /// Remaining time to live in seconds, `-1` when the item never expires. Only returned by the meta protocol.
+    pub ttl_remaining: Option<i64>,
+    /// Seconds since the item was last accessed, before this fetch. Only returned by the meta protocol.
+    pub last_access: Option<u64>,
+    /// Size of the value as stored, in bytes, which is the compressed size of a compressed value.
+    pub size: usize,
+}
+
+impl Item<Vec<u8>> {
+    /// Decompress and decode the value of an item read from the server.
+    pub(crate) fn decode<V: FromMemcacheValueExt>(self) -> Result<Item<V>, MemcacheError> {
+        let (value, flags) = compression::decompress(self.value, self.flags)?;
+        Ok(Item {
+            value: V::from_memcache_value(value, flags, self.cas)?,
+            flags,
+            cas: self.cas,
+            ttl_remaining: self.ttl_remaining,
+            last_access: self.last_access,
+            size: self.size,
+        })
+    }
+}
+
+/// An item read as is by the protocols, to be decoded by [`Item::decode`].
+pub(crate) struct RawItem(pub Item<Vec<u8>>);
+
+impl FromMemcacheValueExt for RawItem {
+    fn from_memcache_value(value: Vec<u8>, flags: u32, cas: Option<u64>) -> Result<Self, MemcacheError> {
+        Ok(RawItem(Item {
+            size: value.len(),
+            value,
+            flags,
+            cas,
+            ttl_remaining: None,
+            last_access: None,
+        }))
+    }
+}
+
+/// Unwrap the items read by a multi-key get.
+pub(crate) fn raw_items(items: HashMap<Vec<u8>, RawItem>) -> HashMap<Vec<u8>, Item<Vec<u8>>> {
+    items.into_iter().map(|(key, RawItem(item))| (key, item)).collect()
+}
+
 #[enum_dispatch]
 pub enum Protocol {
     Ascii(AsciiProtocol<Stream>),
     Binary(BinaryProtocol),
+    Meta(MetaProtocol<Stream>),
+}
+
+impl Protocol {
+    pub(crate) fn execute(&mut self, commands: &[Command]) -> Result<Vec<Result<Reply, MemcacheError>>, MemcacheError> {
+        match self {
+            Protocol::Ascii(protocol) => protocol.execute(commands),
+            Protocol::Binary(protocol) => protocol.execute(commands),
+            Protocol::Meta(protocol) => protocol.execute(commands),
+        }
+    }
+
+    /// Run a store command with options, returning whether the value was stored.
+    pub(crate) fn store<V: ToMemcacheValue<Stream>>(
+        &mut self,
+        command: StoreCommand,
+        key: &[u8],
+        value: V,
+        options: &StoreOptions,
+    ) -> Result<bool, MemcacheError> {
+        let result = match self {
+            Protocol::Ascii(protocol) => protocol.store(command, key, value, options),
+            Protocol::Binary(protocol) => protocol.store_with(command, key, value, options),
+            Protocol::Meta(protocol) => protocol.store(command, key, value, options),
+        };
+        not_stored(result)
+    }
+
+    /// Read and discard the replies left on the connection by the last noreply commands, so that the next
+    /// command reads its own reply.
+    pub(crate) fn settle(&mut self) -> Result<(), MemcacheError> {
+        match self {
+            Protocol::Ascii(_) => Ok(()),
+            Protocol::Binary(protocol) => protocol.settle(),
+            Protocol::Meta(protocol) => protocol.settle(),
+        }
+    }
+
+    pub(crate) fn stream(&mut self) -> &mut Stream {
+        match self {
+            Protocol::Ascii(protocol) => protocol.stream(),
+            Protocol::Binary(protocol) => &mut protocol.stream,
+            Protocol::Meta(protocol) => protocol.stream(),
+        }
+    }
+}
+
+/// Report an add of an existing key, a replace or append of a missing key and a cas mismatch as a value not
+/// stored, rather than as an error.
+pub(crate) fn not_stored(result: Result<bool, MemcacheError>) -> Result<bool, MemcacheError> {
+    match result {
+        Err(MemcacheError::CommandError(CommandError::KeyExists))
+        | Err(MemcacheError::CommandError(CommandError::KeyNotFound))
+        | Err(MemcacheError::CommandError(CommandError::Unknown(0x5))) => Ok(false),
+        result => result,
+    }
 }
 
 #[enum_dispatch(Protocol)]
@@ -23,28 +175,46 @@ pub trait ProtocolTrait {
     fn version(&mut self) -> Result<String, MemcacheError>;
     fn flush(&mut self) -> Result<(), MemcacheError>;
     fn flush_with_delay(&mut self, delay: u32) -> Result<(), MemcacheError>;
-    fn get<V: FromMemcacheValueExt>(&mut self, key: &str) -> Result<Option<V>, MemcacheError>;
-    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&str]) -> Result<HashMap<String, V>, MemcacheError>;
-    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError>;
+    fn get<V: FromMemcacheValueExt>(&mut self, key: &[u8]) -> Result<Option<V>, MemcacheError>;
+    fn gets<V: FromMemcacheValueExt>(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, V>, MemcacheError>;
+    fn get_items(&mut self, keys: &[&[u8]]) -> Result<HashMap<Vec<u8>, Item<Vec<u8>>>, MemcacheError>;
+    fn get_and_touch<V: FromMemcacheValueExt>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<Option<V>, MemcacheError>;
+    fn get_and_touch_multi<V: FromMemcacheValueExt>(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Vec<u8>, V>, MemcacheError>;
+    fn set<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError>;
     fn cas<V: ToMemcacheValue<Stream>>(
         &mut self,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
         cas: u64,
     ) -> Result<bool, MemcacheError>;
-    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V, expiration: u32) -> Result<(), MemcacheError>;
+    fn add<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V, expiration: u32) -> Result<(), MemcacheError>;
     fn replace<V: ToMemcacheValue<Stream>>(
         &mut self,
-        key: &str,
+        key: &[u8],
         value: V,
         expiration: u32,
     ) -> Result<(), MemcacheError>;
-    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V) -> Result<(), MemcacheError>;
-    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &str, value: V) -> Result<(), MemcacheError>;
-    fn delete(&mut self, key: &str) -> Result<bool, MemcacheError>;
-    fn increment(&mut self, key: &str, amount: u64) -> Result<u64, MemcacheError>;
-    fn decrement(&mut self, key: &str, amount: u64) -> Result<u64, MemcacheError>;
-    fn touch(&mut self, key: &str, expiration: u32) -> Result<bool, MemcacheError>;
+    fn append<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError>;
+    fn prepend<V: ToMemcacheValue<Stream>>(&mut self, key: &[u8], value: V) -> Result<(), MemcacheError>;
+    fn delete(&mut self, key: &[u8]) -> Result<bool, MemcacheError>;
+    fn increment(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError>;
+    fn decrement(&mut self, key: &[u8], amount: u64) -> Result<u64, MemcacheError>;
+    fn increment_or_init(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64, MemcacheError>;
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<bool, MemcacheError>;
     fn stats(&mut self) -> Result<Stats, MemcacheError>;
 }
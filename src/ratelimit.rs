@@ -0,0 +1,184 @@
+use crate::client::Client;
+use crate::error::MemcacheError;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The outcome of a request checked against a rate limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The request is within the limit and has been counted.
+    Allowed {
+        /// The number of requests still allowed before being limited.
+        remaining: u64,
+        /// When the requests counted so far no longer weigh on the limit.
+        reset_at: SystemTime,
+    },
+    /// The request is over the limit.
+    Limited {
+        /// How long to wait before a request is allowed again.
+        retry_after: Duration,
+    },
+}
+
+impl Decision {
+    /// Returns `true` if the request is allowed.
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Decision::Allowed { .. })
+    }
+}
+
+/// The current time, with the index of its time bucket of `window` seconds and the milliseconds elapsed in it.
+fn bucket(window: u32) -> (u64, u64) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let window = u64::from(window.max(1)) * 1000;
+    (now / window, now % window)
+}
+
+fn bucket_start(bucket: u64, window: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(bucket * u64::from(window.max(1)))
+}
+
+/// A fixed window rate limiter, allowing `limit` requests per identifier in each time bucket of `window` seconds.
+///
+/// Each bucket has its own counter, created with the window as its ttl on the first request and incremented on
+/// the next ones. The limit is cheap to check, but a burst of up to twice the limit can get through around the
+/// end of a bucket, see [`SlidingWindow`] for a smoother limit.
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::ratelimit::FixedWindow;
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let client = memcache::Client::with_pool(pool);
+/// let limiter = FixedWindow::new(&client, "fixed_window", 2, 60);
+/// assert!(limiter.check("user:1").unwrap().is_allowed());
+/// assert!(limiter.check("user:1").unwrap().is_allowed());
+/// assert!(!limiter.check("user:1").unwrap().is_allowed());
+/// assert!(limiter.check("user:2").unwrap().is_allowed());
+/// # client.flush().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FixedWindow {
+    client: Client,
+    prefix: String,
+    limit: u64,
+    window: u32,
+}
+
+impl FixedWindow {
+    /// Create a limiter of `limit` requests per `window` seconds, its counters being stored under `prefix`.
+    pub fn new(client: &Client, prefix: &str, limit: u64, window: u32) -> Self {
+        FixedWindow {
+            client: client.clone(),
+            prefix: prefix.to_string(),
+            limit,
+            window: window.max(1),
+        }
+    }
+
+    /// Count a request of `id` and decide whether it is allowed.
+    pub fn check(&self, id: &str) -> Result<Decision, MemcacheError> {
+        let (bucket, elapsed) = bucket(self.window);
+        let key = format!("{}:{}:{}", self.prefix, id, bucket);
+        let count = self.client.increment_or_init(&key, 1, 1, self.window)?;
+        if count > self.limit {
+            let retry_after = Duration::from_millis(u64::from(self.window) * 1000 - elapsed);
+            return Ok(Decision::Limited { retry_after });
+        }
+        Ok(Decision::Allowed {
+            remaining: self.limit - count,
+            reset_at: bucket_start(bucket + 1, self.window),
+        })
+    }
+}
+
+/// An approximate sliding window rate limiter, allowing `limit` requests per identifier in any `window` seconds.
+///
+/// The requests are counted in fixed time buckets, and the count of the previous bucket is weighted by the part
+/// of the sliding window still overlapping it. Both counters are read in a single multi-get, the current one
+/// being incremented only if the request is allowed.
+///
+/// Example:
+///
+/// ```rust
+/// use memcache::ratelimit::{Decision, SlidingWindow};
+///
+/// let pool = memcache::Pool::builder()
+/// .connection_timeout(std::time::Duration::from_secs(1))
+/// .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+/// .unwrap();
+/// let client = memcache::Client::with_pool(pool);
+/// let limiter = SlidingWindow::new(&client, "sliding_window", 1, 60);
+/// assert!(limiter.check("user:1").unwrap().is_allowed());
+/// match limiter.check("user:1").unwrap() {
+///     Decision::Limited { retry_after } => assert!(retry_after.as_secs() <= 120),
+///     Decision::Allowed { .. } => panic!("the second request should be limited"),
+/// }
+/// # client.flush().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SlidingWindow {
+    client: Client,
+    prefix: String,
+    limit: u64,
+    window: u32,
+}
+
+impl SlidingWindow {
+    /// Create a limiter of `limit` requests per `window` seconds, its counters being stored under `prefix`.
+    pub fn new(client: &Client, prefix: &str, limit: u64, window: u32) -> Self {
+        SlidingWindow {
+            client: client.clone(),
+            prefix: prefix.to_string(),
+            limit,
+            window: window.max(1),
+        }
+    }
+
+    /// Decide whether a request of `id` is allowed, counting it if so.
+    pub fn check(&self, id: &str) -> Result<Decision, MemcacheError> {
+        let (bucket, elapsed) = bucket(self.window);
+        let window = f64::from(self.window) * 1000.0;
+        // the part of the previous bucket still in the sliding window
+        let weight = 1.0 - elapsed as f64 / window;
+        let current = format!("{}:{}:{}", self.prefix, id, bucket);
+        let previous = format!("{}:{}:{}", self.prefix, id, bucket.wrapping_sub(1));
+        let counts: HashMap<&str, String> = self.client.gets(&[current.as_str(), previous.as_str()])?;
+        let count = |key: &str| counts.get(key).and_then(|count| count.trim().parse::<u64>().ok());
+        let (previous, mut current_count) = (count(&previous).unwrap_or(0), count(&current).unwrap_or(0));
+
+        let limit = self.limit as f64;
+        if previous as f64 * weight + current_count as f64 + 1.0 <= limit {
+            // the previous bucket must outlive the current one to be weighted in the next
+            current_count = self
+                .client
+                .increment_or_init(&current, 1, 1, self.window.saturating_mul(2))?;
+            let estimate = previous as f64 * weight + current_count as f64;
+            if estimate <= limit {
+                return Ok(Decision::Allowed {
+                    remaining: (limit - estimate) as u64,
+                    reset_at: bucket_start(bucket + 2, self.window),
+                });
+            }
+        }
+
+        // wait until the weighted counts leave room for one more request
+        let wait = if current_count + 1 > self.limit {
+            let fraction = 1.0 - (limit - 1.0) / current_count.max(1) as f64;
+            window - elapsed as f64 + fraction.clamp(0.0, 1.0) * window
+        } else {
+            let fraction = 1.0 - (limit - current_count as f64 - 1.0) / previous.max(1) as f64;
+            fraction.clamp(0.0, 1.0) * window - elapsed as f64
+        };
+        Ok(Decision::Limited {
+            retry_after: Duration::from_millis(wait.max(0.0).ceil() as u64),
+        })
+    }
+}
@@ -1,6 +1,7 @@
 mod udp_stream;
 
-use std::io::{self, Read, Write};
+use std::io::{self, IoSlice, Read, Write};
+use std::mem;
 use std::net::TcpStream;
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
@@ -10,7 +11,10 @@ use openssl::ssl::SslStream;
 
 pub(crate) use self::udp_stream::UdpStream;
 
-pub enum Stream {
+/// Write buffer size. Commands smaller than this go out in a single write when flushed.
+const BUFFER_SIZE: usize = 8 * 1024;
+
+pub(crate) enum Socket {
     Tcp(TcpStream),
     Udp(UdpStream),
     #[cfg(unix)]
@@ -19,39 +23,177 @@ pub enum Stream {
     Tls(SslStream<TcpStream>),
 }
 
+/// A connection socket with a write buffer. The protocols flush it once per command or batch of commands,
+/// so that the fields of a request don't each cost a syscall.
+pub struct Stream {
+    socket: Socket,
+    buffer: Vec<u8>,
+    capturing: bool,
+}
+
+impl Stream {
+    pub(crate) fn new(socket: Socket) -> Self {
+        Self {
+            socket,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            capturing: false,
+        }
+    }
+
+    /// Run the writes in memory instead of on the socket, returning the written bytes. Values only writable
+    /// to a `Stream` are encoded with it before being compressed.
+    pub(crate) fn capture<F>(&mut self, write: F) -> io::Result<Vec<u8>>
+    where
+        F: FnOnce(&mut Stream) -> io::Result<()>,
+    {
+        let pending = mem::take(&mut self.buffer);
+        self.capturing = true;
+        let result = write(self);
+        self.capturing = false;
+        let captured = mem::replace(&mut self.buffer, pending);
+        result.map(|_| captured)
+    }
+
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.socket.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
 impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.socket.read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.capturing || self.buffer.len() + buf.len() <= BUFFER_SIZE {
+            self.buffer.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        if buf.len() < BUFFER_SIZE {
+            self.flush_buffer()?;
+            self.buffer.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        // a large value is not copied, it goes out along with the buffered request header in one vectored write
+        while !self.buffer.is_empty() {
+            let written = self
+                .socket
+                .write_vectored(&[IoSlice::new(&self.buffer), IoSlice::new(buf)])?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            if written <= self.buffer.len() {
+                self.buffer.drain(..written);
+            } else {
+                let value_written = written - self.buffer.len();
+                self.buffer.clear();
+                return Ok(value_written);
+            }
+        }
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        self.socket.flush()
+    }
+}
+
+impl Read for Socket {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
-            Stream::Tcp(ref mut stream) => stream.read(buf),
-            Stream::Udp(ref mut stream) => stream.read(buf),
+            Socket::Tcp(ref mut stream) => stream.read(buf),
+            Socket::Udp(ref mut stream) => stream.read(buf),
             #[cfg(unix)]
-            Stream::Unix(ref mut stream) => stream.read(buf),
+            Socket::Unix(ref mut stream) => stream.read(buf),
             #[cfg(feature = "tls")]
-            Stream::Tls(ref mut stream) => stream.read(buf),
+            Socket::Tls(ref mut stream) => stream.read(buf),
         }
     }
 }
 
-impl Write for Stream {
+impl Write for Socket {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self {
-            Stream::Tcp(ref mut stream) => stream.write(buf),
-            Stream::Udp(ref mut stream) => stream.write(buf),
+            Socket::Tcp(ref mut stream) => stream.write(buf),
+            Socket::Udp(ref mut stream) => stream.write(buf),
+            #[cfg(unix)]
+            Socket::Unix(ref mut stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Socket::Tls(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(ref mut stream) => stream.write_vectored(bufs),
+            Socket::Udp(ref mut stream) => stream.write_vectored(bufs),
             #[cfg(unix)]
-            Stream::Unix(ref mut stream) => stream.write(buf),
+            Socket::Unix(ref mut stream) => stream.write_vectored(bufs),
             #[cfg(feature = "tls")]
-            Stream::Tls(ref mut stream) => stream.write(buf),
+            Socket::Tls(ref mut stream) => stream.write_vectored(bufs),
         }
     }
 
     fn flush(&mut self) -> io::Result<()> {
         match self {
-            Stream::Tcp(ref mut stream) => stream.flush(),
-            Stream::Udp(ref mut stream) => stream.flush(),
+            Socket::Tcp(ref mut stream) => stream.flush(),
+            Socket::Udp(ref mut stream) => stream.flush(),
             #[cfg(unix)]
-            Stream::Unix(ref mut stream) => stream.flush(),
+            Socket::Unix(ref mut stream) => stream.flush(),
             #[cfg(feature = "tls")]
-            Stream::Tls(ref mut stream) => stream.flush(),
+            Socket::Tls(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn read_available(socket: &mut UnixStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            match socket.read(&mut buf) {
+                Ok(0) => return data,
+                Ok(n) => data.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return data,
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffered_writes() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        remote.set_nonblocking(true).unwrap();
+        let mut stream = Stream::new(Socket::Unix(local));
+
+        stream.write_all(b"set foo 0 0 3\r\n").unwrap();
+        stream.write_all(b"bar\r\n").unwrap();
+        assert!(read_available(&mut remote).is_empty());
+        stream.flush().unwrap();
+        assert_eq!(read_available(&mut remote), b"set foo 0 0 3\r\nbar\r\n");
+
+        let value = vec![b'x'; BUFFER_SIZE * 4];
+        stream.write_all(b"header").unwrap();
+        stream.write_all(&value).unwrap();
+        stream.write_all(b"\r\n").unwrap();
+        stream.flush().unwrap();
+        let mut expected = b"header".to_vec();
+        expected.extend_from_slice(&value);
+        expected.extend_from_slice(b"\r\n");
+        let mut received = Vec::new();
+        while received.len() < expected.len() {
+            received.extend(read_available(&mut remote));
         }
+        assert_eq!(received, expected);
     }
 }
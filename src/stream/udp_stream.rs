@@ -1,3 +1,4 @@
+use crate::connection::Timeouts;
 use crate::error::MemcacheError;
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
 use rand;
@@ -26,6 +27,11 @@ impl UdpStream {
             request_id: rand::random::<u16>(),
         });
     }
+
+    pub(crate) fn set_timeouts(&self, timeouts: &Timeouts) -> io::Result<()> {
+        self.socket.set_read_timeout(timeouts.read)?;
+        self.socket.set_write_timeout(timeouts.write)
+    }
 }
 
 impl Read for UdpStream {
@@ -1,11 +1,25 @@
+use crate::compression;
 use crate::error::MemcacheError;
 use std::io;
 use std::io::Write;
 use std::str;
 use std::str::FromStr;
 
+/// Bits of the flags word set by this crate, reserved whichever features are enabled. Values stored with custom
+/// flags through `StoreOptions::flags` should avoid them: a value carrying the bit of a compression algorithm
+/// whose feature is enabled is decompressed when it's read back, and one carrying the bit of a serde format is
+/// decoded with it by the wrappers of that format.
 pub enum Flags {
     Bytes = 0,
+    /// Values serialized with serde, one bit per format.
+    Json = 0x1,
+    MsgPack = 0x2,
+    Bincode = 0x4,
+    Cbor = 0x8,
+    /// Compressed values, one bit per algorithm.
+    Zstd = 0x10,
+    Lz4 = 0x20,
+    Gzip = 0x40,
 }
 
 /// determine how the value is serialize to memcache
@@ -80,6 +94,23 @@ impl<'a, W: Write> ToMemcacheValue<W> for &'a str {
     }
 }
 
+/// Writes a borrowed value, so that it can be stored and still handed back to the caller.
+pub(crate) struct Borrowed<'a, V>(pub &'a V);
+
+impl<'a, W: Write, V: ToMemcacheValue<W>> ToMemcacheValue<W> for Borrowed<'a, V> {
+    fn get_flags(&self) -> u32 {
+        self.0.get_flags()
+    }
+
+    fn get_length(&self) -> usize {
+        self.0.get_length()
+    }
+
+    fn write_to(&self, stream: &mut W) -> io::Result<()> {
+        self.0.write_to(stream)
+    }
+}
+
 macro_rules! impl_to_memcache_value_for_number {
     ($ty:ident) => {
         impl<W: Write> ToMemcacheValue<W> for $ty {
@@ -126,10 +157,12 @@ pub trait FromMemcacheValueExt: Sized {
 
 impl<V: FromMemcacheValue> FromMemcacheValueExt for V {
     fn from_memcache_value(value: Vec<u8>, flags: u32, _cas: Option<u64>) -> MemcacheValue<Self> {
+        let (value, flags) = compression::decompress(value, flags)?;
         FromMemcacheValue::from_memcache_value(value, flags)
     }
 }
 
+/// The value as stored, still compressed if it was, along with its flags and cas token.
 impl FromMemcacheValueExt for (Vec<u8>, u32, Option<u64>) {
     fn from_memcache_value(value: Vec<u8>, flags: u32, cas: Option<u64>) -> MemcacheValue<Self> {
         return Ok((value, flags, cas));
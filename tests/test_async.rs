@@ -0,0 +1,299 @@
+#![cfg(feature = "tokio")]
+
+extern crate memcache;
+
+use memcache::aio::{Client, ConnectionManager, Pool};
+use memcache::{MetaGetOptions, MetaValue, StoreOptions};
+use std::collections::HashMap;
+use std::time::Duration;
+
+async fn connect(target: &str) -> Client {
+    let pool = Pool::builder()
+        .max_size(5)
+        .connection_timeout(Duration::from_millis(500))
+        .build(ConnectionManager::new(target).unwrap())
+        .await
+        .unwrap();
+    Client::with_pool(pool)
+}
+
+#[tokio::test]
+async fn test_async() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+        "memcache:///tmp/memcached.sock",
+    ] {
+        let client = connect(url).await;
+        client.flush().await.unwrap();
+        assert!(client.version().await.unwrap()[0].1 != "");
+
+        client.set("async_foo", "bar", 0).await.unwrap();
+        let value: Option<String> = client.get("async_foo").await.unwrap();
+        assert_eq!(value, Some("bar".into()));
+        let value: Option<String> = client.get("not_exists_key").await.unwrap();
+        assert_eq!(value, None);
+
+        client.set("async_baz", "qux", 0).await.unwrap();
+        let values: HashMap<&str, (Vec<u8>, u32, Option<u64>)> = client
+            .gets(&["async_foo", "async_baz", "not_exists_key"])
+            .await
+            .unwrap();
+        assert_eq!(values.len(), 2);
+        let bytes: HashMap<&[u8], String> = client.gets(&[&b"async_baz"[..]]).await.unwrap();
+        assert_eq!(bytes[&b"async_baz"[..]], "qux");
+        let cas = values["async_foo"].2.unwrap();
+        assert_eq!(client.cas("async_foo", "bar2", 0, cas).await.unwrap(), true);
+        assert_eq!(client.cas("async_foo", "bar3", 0, cas).await.unwrap(), false);
+
+        client.add("async_added", "bar", 0).await.unwrap();
+        client.replace("async_foo", "baz", 0).await.unwrap();
+        client.append("async_foo", "z").await.unwrap();
+        client.prepend("async_foo", "a").await.unwrap();
+        let value: Option<String> = client.get("async_foo").await.unwrap();
+        assert_eq!(value, Some("abazz".into()));
+
+        assert_eq!(client.touch("async_foo", 1000).await.unwrap(), true);
+        assert_eq!(client.touch("not_exists_key", 1000).await.unwrap(), false);
+        assert_eq!(client.delete("async_foo").await.unwrap(), true);
+        assert_eq!(client.delete("async_foo").await.unwrap(), false);
+
+        client.set("async_counter", 3, 0).await.unwrap();
+        assert_eq!(client.increment("async_counter", 100).await.unwrap(), 103);
+        assert_eq!(client.decrement("async_counter", 3).await.unwrap(), 100);
+
+        assert!(!client.stats().await.unwrap()[0].1.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_async_meta_get() {
+    let client = connect("memcache://localhost:12345?protocol=meta").await;
+    client.set("async_meta_foo", "bar", 100).await.unwrap();
+    let options = MetaGetOptions {
+        ttl: true,
+        opaque: Some(7),
+        ..Default::default()
+    };
+    let value: MetaValue<String> = client.meta_get("async_meta_foo", &options).await.unwrap().unwrap();
+    assert_eq!(value.value, "bar");
+    assert!(value.ttl.unwrap() > 0);
+    assert_eq!(value.opaque, Some(7));
+    assert!(client.meta_debug("async_meta_foo").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_async_get_and_touch() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = connect(url).await;
+        client.set("async_gat_foo", "foo", 100).await.unwrap();
+        client.set("async_gat_bar", "bar", 100).await.unwrap();
+        client.delete("async_gat_missing").await.unwrap();
+
+        let (value, _, cas): (Vec<u8>, u32, Option<u64>) =
+            client.get_and_touch("async_gat_foo", 1000).await.unwrap().unwrap();
+        assert_eq!(value, b"foo");
+        assert!(cas.is_some());
+        let value: Option<String> = client.get_and_touch("async_gat_missing", 1000).await.unwrap();
+        assert_eq!(value, None);
+
+        let values: HashMap<&str, String> = client
+            .get_and_touch_multi(&["async_gat_foo", "async_gat_bar", "async_gat_missing"], 1000)
+            .await
+            .unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["async_gat_bar"], "bar");
+    }
+}
+
+#[tokio::test]
+async fn test_async_increment_or_init() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = connect(url).await;
+        client.delete("async_counter_init").await.unwrap();
+        assert!(client.increment("async_counter_init", 1).await.is_err());
+        assert!(client
+            .increment_or_init("async_counter_init", 1, 0, u32::MAX)
+            .await
+            .is_err());
+        assert_eq!(
+            client
+                .increment_or_init("async_counter_init", 5, 10, 100)
+                .await
+                .unwrap(),
+            10
+        );
+        assert_eq!(
+            client
+                .increment_or_init("async_counter_init", 5, 10, 100)
+                .await
+                .unwrap(),
+            15
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_async_update() {
+    let client = connect("memcache://localhost:12345").await;
+    client.delete("async_update").await.unwrap();
+    for expected in 1..=2 {
+        let update = client
+            .update("async_update", 100, 0, |value: Option<u64>| {
+                Some(value.unwrap_or(0) + 1)
+            })
+            .await
+            .unwrap();
+        assert_eq!(update.value, Some(expected));
+        assert_eq!(update.attempts, 1);
+    }
+}
+
+#[tokio::test]
+async fn test_async_get_item() {
+    for protocol in &["binary", "ascii", "meta"] {
+        let client = connect(&format!("memcache://localhost:12345?protocol={}", protocol)).await;
+        client.set("async_item", "value", 100).await.unwrap();
+        let item: memcache::Item<String> = client.get_item("async_item").await.unwrap().unwrap();
+        assert_eq!(item.value, "value");
+        assert_eq!(item.size, 5);
+        assert!(item.cas.is_some());
+        assert_eq!(item.ttl_remaining.is_some(), *protocol == "meta");
+
+        let items: HashMap<&str, memcache::Item<String>> = client.get_items(&["async_item"]).await.unwrap();
+        assert_eq!(items["async_item"].value, "value");
+    }
+}
+
+#[tokio::test]
+async fn test_async_store_options() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        // a single connection, so that the replies left by noreply writes are read by the next command
+        let pool = Pool::builder()
+            .max_size(1)
+            .connection_timeout(Duration::from_millis(500))
+            .build(ConnectionManager::new(url).unwrap())
+            .await
+            .unwrap();
+        let client = Client::with_pool(pool);
+        client.delete("async_store_options").await.unwrap();
+
+        let options = StoreOptions {
+            flags: Some(0x100),
+            ..Default::default()
+        };
+        assert!(client.add_with("async_store_options", "bar", &options).await.unwrap());
+        assert!(!client.add_with("async_store_options", "baz", &options).await.unwrap());
+        let values: HashMap<&str, (Vec<u8>, u32, Option<u64>)> = client.gets(&["async_store_options"]).await.unwrap();
+        let (_, flags, cas) = values["async_store_options"].clone();
+        assert_eq!(flags, 0x100);
+
+        let stale = StoreOptions {
+            cas: Some(cas.unwrap() + 1000),
+            ..Default::default()
+        };
+        assert!(!client.set_with("async_store_options", "baz", &stale).await.unwrap());
+
+        let noreply = StoreOptions {
+            noreply: true,
+            ..Default::default()
+        };
+        assert!(client.add_with("async_store_options", "qux", &noreply).await.unwrap());
+        assert!(client.append_with("async_store_options", "!", &noreply).await.unwrap());
+        let value: Option<String> = client.get("async_store_options").await.unwrap();
+        assert_eq!(value, Some("bar!".into()));
+    }
+}
+
+#[tokio::test]
+async fn test_async_namespace() {
+    let client = connect("memcache://localhost:12345").await;
+    let service = client.with_namespace("async_svc:");
+    service.set("foo", "bar", 0).await.unwrap();
+    let value: Option<String> = client.get("async_svc:foo").await.unwrap();
+    assert_eq!(value, Some("bar".into()));
+    let values: HashMap<&str, String> = service.gets(&["foo", "missing"]).await.unwrap();
+    assert_eq!(values.len(), 1);
+    assert_eq!(values["foo"], "bar");
+    assert!(service.delete("foo").await.unwrap());
+    assert!(service.set(&"a".repeat(245), "bar", 0).await.is_err());
+}
+
+#[tokio::test]
+async fn test_async_binary_keys() {
+    let binary = connect("memcache://localhost:12345").await;
+    let meta = connect("memcache://localhost:12345?protocol=meta").await;
+    let ascii = connect("memcache://localhost:12345?protocol=ascii").await;
+    let key = b"async binary key\r\n\xff";
+    binary.set(key, "bar", 0).await.unwrap();
+    let value: Option<String> = meta.get(key).await.unwrap();
+    assert_eq!(value, Some("bar".into()));
+    assert!(ascii.get::<String>(key).await.is_err());
+    assert!(meta.delete(key).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_async_key_policy() {
+    let client = connect("memcache://localhost:12345?protocol=ascii").await;
+    let long = "async_policy".repeat(30);
+    assert!(client.set(&long, "bar", 0).await.is_err());
+    let client = client.with_key_policy(memcache::KeyPolicy::Hash {
+        prefix: "h:".to_string(),
+        log: None,
+    });
+    client.set(&long, "bar", 0).await.unwrap();
+    let values: HashMap<&String, String> = client.gets(&[&long]).await.unwrap();
+    assert_eq!(values[&long], "bar");
+    assert!(client.delete(&long).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_async_concurrent() {
+    let client = connect("memcache://localhost:12345").await;
+    let mut handles = Vec::new();
+    for i in 0..10 {
+        let client = client.clone();
+        handles.push(tokio::spawn(async move {
+            let key = format!("async_key{}", i);
+            for j in 0..20 {
+                let value = format!("value{}", j);
+                client.set(&key, &value, 0).await.unwrap();
+                let result: Option<String> = client.get(&key).await.unwrap();
+                assert_eq!(result, Some(value));
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+#[cfg(feature = "lz4")]
+#[tokio::test]
+async fn test_async_compression() {
+    let large = "a rendered fragment ".repeat(1000);
+    let client = connect("memcache://localhost:12345?protocol=meta&compression=lz4").await;
+    client.set("async_compressed", &large, 0).await.unwrap();
+    let value: MetaValue<String> = client
+        .meta_get("async_compressed", &MetaGetOptions::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((value.value, value.flags), (large, 0));
+    // the raw tuple is read as stored, with the compression bit
+    let (_, flags, _): (Vec<u8>, u32, Option<u64>) = client.get("async_compressed").await.unwrap().unwrap();
+    assert_ne!(flags, 0);
+}
@@ -0,0 +1,80 @@
+#![cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+
+extern crate memcache;
+
+use memcache::MetaGetOptions;
+
+mod helpers;
+
+/// Size of the stored item, including the overhead of memcached, and its flags.
+fn stored(key: &str) -> (usize, u32) {
+    let meta = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+    let size = meta.meta_debug(key).unwrap().unwrap()["size"].parse().unwrap();
+    let (_, flags, _): (Vec<u8>, u32, Option<u64>) = meta.get(key).unwrap().unwrap();
+    (size, flags)
+}
+
+#[test]
+fn test_compression() {
+    let large = "a rendered fragment ".repeat(1000);
+    for algorithm in &["zstd", "lz4", "gzip"] {
+        if !cfg!(feature = "zstd") && *algorithm == "zstd"
+            || !cfg!(feature = "lz4") && *algorithm == "lz4"
+            || !cfg!(feature = "gzip") && *algorithm == "gzip"
+        {
+            continue;
+        }
+        for protocol in &["binary", "ascii", "meta"] {
+            let url = format!(
+                "memcache://localhost:12345?protocol={}&compression={}&compression_threshold=100",
+                protocol, algorithm
+            );
+            let client = helpers::connect(&url).unwrap();
+            let plain = helpers::connect("memcache://localhost:12345").unwrap();
+
+            client.set("compressed", &large, 0).unwrap();
+            let (size, flags) = stored("compressed");
+            assert!(size < large.len());
+            assert_ne!(flags, 0);
+            let value: Option<String> = client.get("compressed").unwrap();
+            assert_eq!(value.as_ref(), Some(&large));
+            // decompressed whatever the settings of the reading client
+            let value: Option<String> = plain.get("compressed").unwrap();
+            assert_eq!(value.as_ref(), Some(&large));
+            let item: memcache::Item<String> = plain.get_item("compressed").unwrap().unwrap();
+            assert_eq!(item.value, large);
+            assert!(item.size < large.len());
+            assert_eq!(item.flags, 0);
+            let meta = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+            let value = meta
+                .meta_get::<String>("compressed", &MetaGetOptions::default())
+                .unwrap()
+                .unwrap();
+            assert_eq!((value.value, value.flags), (large.clone(), 0));
+
+            client.set("small", "under the threshold", 0).unwrap();
+            assert_eq!(stored("small").1, 0);
+
+            // incompressible values are stored raw
+            let noise: Vec<u8> = (0..1000).map(|_| rand::random::<u8>()).collect();
+            client.set("noise", noise.as_slice(), 0).unwrap();
+            let (size, flags) = stored("noise");
+            assert!(size > noise.len());
+            assert_eq!(flags, 0);
+            let value: Option<Vec<u8>> = client.get("noise").unwrap();
+            assert_eq!(value, Some(noise));
+
+            // appended values are concatenated to the stored one and never compressed
+            client.set("appended", "start", 0).unwrap();
+            client.append("appended", large.as_str()).unwrap();
+            let value: Option<String> = client.get("appended").unwrap();
+            assert_eq!(value, Some(format!("start{}", large)));
+
+            let mut connection = client.get_connection("pipelined").unwrap();
+            connection.pipeline().set("pipelined", &large, 0).execute().unwrap();
+            assert!(stored("pipelined").0 < large.len());
+            let value: Option<String> = client.get("pipelined").unwrap();
+            assert_eq!(value.as_ref(), Some(&large));
+        }
+    }
+}
@@ -0,0 +1,106 @@
+extern crate memcache;
+
+use memcache::{MetaGetOptions, MetaValue};
+use std::collections::HashMap;
+use std::{thread, time};
+
+mod helpers;
+
+#[test]
+fn test_meta() {
+    let client = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+
+    client.flush_with_delay(1).unwrap();
+    thread::sleep(time::Duration::from_secs(1));
+    client.flush().unwrap();
+
+    client.set("meta_foo", "bar", 0).unwrap();
+    let value: Option<String> = client.get("meta_foo").unwrap();
+    assert_eq!(value, Some("bar".into()));
+
+    client.set("meta_baz", "qux", 0).unwrap();
+    let values: HashMap<&str, (Vec<u8>, u32, Option<u64>)> =
+        client.gets(&["meta_foo", "meta_baz", "not_exists_key"]).unwrap();
+    assert_eq!(values.len(), 2);
+    assert_eq!(values["meta_foo"].0, b"bar");
+    assert_eq!(values["meta_baz"].0, b"qux");
+    let cas = values["meta_foo"].2.unwrap();
+    assert_eq!(client.cas("meta_foo", "bar2", 0, cas).unwrap(), true);
+    assert_eq!(client.cas("meta_foo", "bar3", 0, cas).unwrap(), false);
+
+    assert_eq!(client.touch("meta_foo", 1000).unwrap(), true);
+    assert_eq!(client.touch("not_exists_key", 1000).unwrap(), false);
+
+    let value: Option<String> = client.get("not_exists_key").unwrap();
+    assert_eq!(value, None);
+
+    client.add("meta_added", "x", 0).unwrap();
+    client.replace("meta_added", "y", 0).unwrap();
+    client.append("meta_added", "z").unwrap();
+    client.prepend("meta_added", "x").unwrap();
+    let value: Option<String> = client.get("meta_added").unwrap();
+    assert_eq!(value, Some("xyz".into()));
+
+    assert_eq!(client.delete("meta_added").unwrap(), true);
+    assert_eq!(client.delete("meta_added").unwrap(), false);
+
+    assert!(client.increment("meta_counter", 1).is_err());
+    client.set("meta_counter", 3, 0).unwrap();
+    assert_eq!(client.increment("meta_counter", 100).unwrap(), 103);
+    assert_eq!(client.decrement("meta_counter", 3).unwrap(), 100);
+
+    assert!(!client.stats().unwrap()[0].1.is_empty());
+}
+
+#[test]
+fn test_meta_get() {
+    let client = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+
+    client.set("meta_get_foo", "bar", 100).unwrap();
+    let options = MetaGetOptions {
+        cas: true,
+        ttl: true,
+        last_access: true,
+        hit: true,
+        opaque: Some(42),
+        ..Default::default()
+    };
+    let value: MetaValue<String> = client.meta_get("meta_get_foo", &options).unwrap().unwrap();
+    assert_eq!(value.value, "bar");
+    assert!(value.cas.is_some());
+    assert!(value.ttl.unwrap() > 0 && value.ttl.unwrap() <= 100);
+    assert!(value.last_access.is_some());
+    assert_eq!(value.hit, Some(false));
+    assert_eq!(value.opaque, Some(42));
+
+    let value: MetaValue<String> = client.meta_get("meta_get_foo", &options).unwrap().unwrap();
+    assert_eq!(value.hit, Some(true));
+
+    client.set("meta_get_forever", "bar", 0).unwrap();
+    let value: MetaValue<String> = client.meta_get("meta_get_forever", &options).unwrap().unwrap();
+    assert_eq!(value.ttl, Some(-1));
+
+    let value: Option<MetaValue<String>> = client.meta_get("not_exists_key", &options).unwrap();
+    assert_eq!(value, None);
+
+    let debug = client.meta_debug("meta_get_foo").unwrap().unwrap();
+    assert!(debug.contains_key("size"));
+    assert_eq!(client.meta_debug("not_exists_key").unwrap(), None);
+
+    let client = helpers::connect("memcache://localhost:12345").unwrap();
+    assert!(client.meta_get::<String>("meta_get_foo", &options).is_err());
+}
+
+#[test]
+fn test_meta_base64_keys() {
+    let client = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+
+    for key in &["meta key with spaces", "meta_ключ"] {
+        client.set(key, "bar", 0).unwrap();
+        let value: Option<String> = client.get(key).unwrap();
+        assert_eq!(value, Some("bar".into()));
+        let values: HashMap<&str, String> = client.gets(&[*key]).unwrap();
+        assert_eq!(values[*key], "bar");
+        assert_eq!(client.delete(key).unwrap(), true);
+    }
+}
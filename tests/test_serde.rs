@@ -0,0 +1,54 @@
+#![cfg(feature = "serde")]
+
+extern crate memcache;
+
+use memcache::{Bincode, Cbor, Json, MsgPack};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+mod helpers;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Session {
+    user_id: u64,
+    name: String,
+    roles: Vec<String>,
+}
+
+#[test]
+fn test_serde() {
+    let session = Session {
+        user_id: 7,
+        name: "ana".into(),
+        roles: vec!["admin".into()],
+    };
+
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = helpers::connect(url).unwrap();
+
+        client.set("serde_json", Json(&session), 0).unwrap();
+        client.set("serde_msgpack", MsgPack(&session), 0).unwrap();
+        client.set("serde_bincode", Bincode(&session), 0).unwrap();
+        client.set("serde_cbor", Cbor(&session), 0).unwrap();
+
+        let value: Option<Json<Session>> = client.get("serde_json").unwrap();
+        assert_eq!(value.unwrap().0, session);
+        let value: Option<MsgPack<Session>> = client.get("serde_msgpack").unwrap();
+        assert_eq!(value.unwrap().0, session);
+        let value: Option<Bincode<Session>> = client.get("serde_bincode").unwrap();
+        assert_eq!(value.unwrap().0, session);
+        let value: Option<Cbor<Session>> = client.get("serde_cbor").unwrap();
+        assert_eq!(value.unwrap().0, session);
+
+        let values: HashMap<&str, Json<Session>> = client.gets(&["serde_json", "serde_missing"]).unwrap();
+        assert_eq!(values["serde_json"].0, session);
+
+        assert!(client.get::<Json<Session>>("serde_cbor").is_err());
+        client.set("serde_plain", "{}", 0).unwrap();
+        assert!(client.get::<Json<Session>>("serde_plain").is_err());
+    }
+}
@@ -19,6 +19,61 @@ fn gen_random_key() -> String {
     return String::from_utf8(bs).unwrap();
 }
 
+#[test]
+fn test_multi_servers() {
+    let urls = vec![
+        "memcache://localhost:12345",
+        "memcache://localhost:12346",
+        "memcache://localhost:12347",
+        "memcache://localhost:12348",
+        "memcache://localhost:12349",
+    ];
+    let pools = urls
+        .iter()
+        .map(|url| {
+            r2d2::Pool::builder()
+                .max_size(5)
+                .connection_timeout(time::Duration::from_millis(500))
+                .build(memcache::ConnectionManager::new(url).unwrap())
+                .unwrap()
+        })
+        .collect();
+    let client = memcache::Client::with_pools(pools).unwrap();
+    client.flush().unwrap();
+    assert_eq!(client.version().unwrap().len(), 5);
+
+    let mut keys: Vec<String> = Vec::new();
+    for _ in 0..100 {
+        let key = gen_random_key();
+        client.set(key.as_str(), key.as_str(), 0).unwrap();
+        keys.push(key);
+    }
+
+    for key in &keys {
+        let value: String = client.get(key.as_str()).unwrap().unwrap();
+        assert_eq!(&value, key);
+    }
+
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let values: HashMap<&str, String> = client.gets(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in values {
+        assert_eq!(key, value);
+    }
+
+    let used_servers = client
+        .stats()
+        .unwrap()
+        .into_iter()
+        .filter(|(_, stats)| stats["curr_items"].trim() != "0")
+        .count();
+    assert!(used_servers > 1);
+
+    for key in &keys {
+        assert!(client.delete(key).unwrap());
+    }
+}
+
 #[test]
 fn udp_test() {
     let client = helpers::connect("memcache+udp://localhost:22345").unwrap();
@@ -75,7 +130,7 @@ fn udp_test() {
     assert_eq!(client.touch("fooo", 12345).unwrap(), true);
 
     // gets is not supported for udp
-    let value: Result<std::collections::HashMap<String, String>, _> = client.gets(&["foo", "fooo"]);
+    let value: Result<std::collections::HashMap<&str, String>, _> = client.gets(&["foo", "fooo"]);
     assert_eq!(value.is_ok(), false);
 
     let mut keys: Vec<String> = Vec::new();
@@ -150,7 +205,7 @@ fn test_cas() {
 
         client.set("ascii_baz", "qux", 0).unwrap();
 
-        let values: HashMap<String, (Vec<u8>, u32, Option<u64>)> =
+        let values: HashMap<&str, (Vec<u8>, u32, Option<u64>)> =
             client.gets(&["ascii_foo", "ascii_baz", "not_exists_key"]).unwrap();
         assert_eq!(values.len(), 2);
         let ascii_foo_value = values.get("ascii_foo").unwrap();
@@ -176,3 +231,984 @@ fn test_cas() {
         client.flush().unwrap();
     }
 }
+
+#[test]
+fn test_pipeline() {
+    use memcache::Reply;
+
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = helpers::connect(url).unwrap();
+        let key = gen_random_key();
+        let counter = gen_random_key();
+        let missing = gen_random_key();
+        let mut connection = client.get_connection(&key).unwrap();
+
+        let replies = connection
+            .pipeline()
+            .set(&key, "bar", 0)
+            .add(&key, "baz", 0)
+            .append(&key, "baz")
+            .get(&key)
+            .get(&missing)
+            .set(&counter, 1, 0)
+            .increment(&counter, 41)
+            .decrement(&counter, 2)
+            .touch(&key, 100)
+            .touch(&missing, 100)
+            .delete(&key)
+            .delete(&key)
+            .execute()
+            .unwrap();
+        let mut replies = replies.into_iter().map(Result::unwrap);
+
+        assert_eq!(replies.next(), Some(Reply::Stored(true)));
+        assert_eq!(replies.next(), Some(Reply::Stored(false)));
+        assert_eq!(replies.next(), Some(Reply::Stored(true)));
+        let value: Option<String> = replies.next().unwrap().value().unwrap();
+        assert_eq!(value, Some("barbaz".into()));
+        assert_eq!(replies.next(), Some(Reply::Value(None)));
+        assert_eq!(replies.next(), Some(Reply::Stored(true)));
+        assert_eq!(replies.next(), Some(Reply::Counter(42)));
+        assert_eq!(replies.next(), Some(Reply::Counter(40)));
+        assert_eq!(replies.next(), Some(Reply::Touched(true)));
+        assert_eq!(replies.next(), Some(Reply::Touched(false)));
+        assert_eq!(replies.next(), Some(Reply::Deleted(true)));
+        assert_eq!(replies.next(), Some(Reply::Deleted(false)));
+        assert_eq!(replies.next(), None);
+
+        // a failing command only fails its own reply
+        let replies = connection
+            .pipeline()
+            .set(&key, "not a number", 0)
+            .increment(&key, 1)
+            .get(&key)
+            .execute()
+            .unwrap();
+        assert!(replies[1].is_err());
+        let value: Option<String> = replies.into_iter().nth(2).unwrap().unwrap().value().unwrap();
+        assert_eq!(value, Some("not a number".into()));
+
+        assert!(connection.pipeline().execute().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn test_broken_connection() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .connection_timeout(time::Duration::from_millis(500))
+            .build(memcache::ConnectionManager::new(url).unwrap())
+            .unwrap();
+        let client = memcache::Client::with_pool(pool.clone());
+        let key = gen_random_key();
+        let missing = gen_random_key();
+
+        client.set(&key, "not a number", 0).unwrap();
+        // errors replied to a command leave the connection usable
+        assert!(client.increment(&key, 1).is_err());
+        assert!(!pool.get().unwrap().is_broken());
+
+        // a value failing to decode can leave the end of the response unread, the connection is dropped
+        // instead of handing the leftover to the next command
+        assert!(client.get::<u64>(&key).is_err());
+        let value: Option<String> = client.get(&key).unwrap();
+        assert_eq!(value, Some("not a number".into()));
+        let value: Option<String> = client.get(&missing).unwrap();
+        assert_eq!(value, None);
+    }
+}
+
+#[test]
+fn test_stats_on_pooled_connection() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        // without the check on checkout, anything stats leaves unread goes to the next command
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .test_on_check_out(false)
+            .build(memcache::ConnectionManager::new(*url).unwrap())
+            .unwrap();
+        let client = memcache::Client::with_pool(pool);
+        let stats = client.stats().unwrap();
+        assert!(!stats.is_empty());
+        assert!(!stats[0].1.is_empty());
+
+        let key = gen_random_key();
+        client.set(&key, "bar", 0).unwrap();
+        let value: Option<String> = client.get(&key).unwrap();
+        assert_eq!(value, Some("bar".into()));
+    }
+}
+
+#[test]
+fn test_timeout() {
+    // accepts connections but never replies
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("memcache://{}?timeout=0.1", listener.local_addr().unwrap());
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .test_on_check_out(false)
+        .build(memcache::ConnectionManager::new(url).unwrap())
+        .unwrap();
+    let client = memcache::Client::with_pool(pool);
+
+    let started = time::Instant::now();
+    match client.get::<String>("foo") {
+        Err(memcache::MemcacheError::Timeout) => (),
+        result => panic!("expected a timeout, got {:?}", result),
+    }
+    assert!(started.elapsed() < time::Duration::from_secs(5));
+}
+
+#[test]
+fn test_large_value() {
+    let value = "x".repeat(100 * 1024);
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+        "memcache:///tmp/memcached.sock",
+    ] {
+        let client = helpers::connect(url).unwrap();
+        let key = gen_random_key();
+        client.set(&key, &value, 0).unwrap();
+        let stored: Option<String> = client.get(&key).unwrap();
+        assert_eq!(stored.as_ref(), Some(&value));
+        let values: HashMap<&String, String> = client.gets(&[&key]).unwrap();
+        assert_eq!(values[&key], value);
+    }
+}
+
+#[test]
+fn test_store_options() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        // a single connection, so that the replies left by noreply writes are read by the next command
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .connection_timeout(time::Duration::from_millis(500))
+            .build(memcache::ConnectionManager::new(url).unwrap())
+            .unwrap();
+        let client = memcache::Client::with_pool(pool.clone());
+        let key = gen_random_key();
+        let missing = gen_random_key();
+
+        let options = memcache::StoreOptions {
+            flags: Some(0x100),
+            ..Default::default()
+        };
+        assert!(client.set_with(&key, "bar", &options).unwrap());
+        let values: HashMap<&String, (Vec<u8>, u32, Option<u64>)> = client.gets(&[&key]).unwrap();
+        let (value, flags, cas) = values[&key].clone();
+        assert_eq!((value.as_slice(), flags), (&b"bar"[..], 0x100));
+        let cas = cas.unwrap();
+
+        assert!(!client.add_with(&key, "baz", &Default::default()).unwrap());
+        assert!(!client.replace_with(&missing, "baz", &Default::default()).unwrap());
+        assert!(!client.append_with(&missing, "baz", &Default::default()).unwrap());
+
+        let stale = memcache::StoreOptions {
+            cas: Some(cas + 1000),
+            ..Default::default()
+        };
+        assert!(!client.set_with(&key, "baz", &stale).unwrap());
+        assert!(!client.replace_with(&missing, "baz", &stale).unwrap());
+        let current = memcache::StoreOptions {
+            cas: Some(cas),
+            ..Default::default()
+        };
+        assert!(client.replace_with(&key, "baz", &current).unwrap());
+        let value: Option<String> = client.get(&key).unwrap();
+        assert_eq!(value, Some("baz".into()));
+
+        // failed noreply writes are skipped before the next command reads its reply
+        let noreply = memcache::StoreOptions {
+            noreply: true,
+            ..Default::default()
+        };
+        assert!(client.add_with(&key, "qux", &noreply).unwrap());
+        assert!(client.replace_with(&missing, "qux", &noreply).unwrap());
+        assert!(client.append_with(&key, "!", &noreply).unwrap());
+        let value: Option<String> = client.get(&key).unwrap();
+        assert_eq!(value, Some("baz!".into()));
+        assert!(client.set_with(&key, "quux", &noreply).unwrap());
+        let value: Option<String> = client.get(&key).unwrap();
+        assert_eq!(value, Some("quux".into()));
+        assert!(!pool.get().unwrap().is_broken());
+    }
+
+    let client = helpers::connect("memcache://localhost:12345?protocol=ascii").unwrap();
+    let options = memcache::StoreOptions {
+        cas: Some(1),
+        ..Default::default()
+    };
+    assert!(client.append_with("foo", "bar", &options).is_err());
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn test_foreign_flags() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = helpers::connect(url).unwrap();
+        let key = gen_random_key();
+
+        // 0x10 is the compressed bit of PHP's memcached, only taken for zstd when its feature is enabled
+        let options = memcache::StoreOptions {
+            flags: Some(memcache::Flags::Zstd as u32),
+            ..Default::default()
+        };
+        assert!(client.set_with(&key, "bar", &options).unwrap());
+        let value: Option<String> = client.get(&key).unwrap();
+        assert_eq!(value, Some("bar".into()));
+        let (value, flags, _): (Vec<u8>, u32, Option<u64>) = client.get(&key).unwrap().unwrap();
+        assert_eq!((value.as_slice(), flags), (&b"bar"[..], 0x10));
+        let item: memcache::Item<String> = client.get_item(&key).unwrap().unwrap();
+        assert_eq!((item.value.as_str(), item.flags), ("bar", 0x10));
+    }
+}
+
+#[test]
+fn test_get_and_touch() {
+    let meta = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+    let ttl_options = memcache::MetaGetOptions {
+        ttl: true,
+        ..Default::default()
+    };
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = helpers::connect(url).unwrap();
+        let key = gen_random_key();
+        let other = gen_random_key();
+        let missing = gen_random_key();
+        client.set(&key, "foo", 100).unwrap();
+        client.set(&other, "bar", 100).unwrap();
+
+        let (value, _, cas): (Vec<u8>, u32, Option<u64>) = client.get_and_touch(&key, 1000).unwrap().unwrap();
+        assert_eq!(value, b"foo");
+        assert!(cas.is_some());
+        let ttl = meta
+            .meta_get::<String>(&key, &ttl_options)
+            .unwrap()
+            .unwrap()
+            .ttl
+            .unwrap();
+        assert!(ttl > 100 && ttl <= 1000);
+        assert_eq!(client.get_and_touch::<String>(&missing, 1000).unwrap(), None);
+
+        let values: HashMap<&String, (Vec<u8>, u32, Option<u64>)> =
+            client.get_and_touch_multi(&[&key, &other, &missing], 2000).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[&other].0, b"bar");
+        assert!(values[&key].2.is_some());
+        for key in &[&key, &other] {
+            let ttl = meta
+                .meta_get::<String>(key, &ttl_options)
+                .unwrap()
+                .unwrap()
+                .ttl
+                .unwrap();
+            assert!(ttl > 1000 && ttl <= 2000);
+        }
+    }
+}
+
+#[test]
+fn test_increment_or_init() {
+    let meta = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+    let ttl_options = memcache::MetaGetOptions {
+        ttl: true,
+        ..Default::default()
+    };
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = helpers::connect(url).unwrap();
+        let key = gen_random_key();
+
+        // only increment_or_init creates missing counters
+        assert!(client.increment(&key, 1).is_err());
+        assert!(client.decrement(&key, 1).is_err());
+        let replies = client
+            .get_connection(&key)
+            .unwrap()
+            .pipeline()
+            .increment(&key, 1)
+            .execute()
+            .unwrap();
+        assert!(replies[0].is_err());
+        assert_eq!(client.get::<u64>(&key).unwrap(), None);
+
+        assert_eq!(client.increment_or_init(&key, 5, 10, 100).unwrap(), 10);
+        let ttl = meta
+            .meta_get::<String>(&key, &ttl_options)
+            .unwrap()
+            .unwrap()
+            .ttl
+            .unwrap();
+        assert!(ttl > 0 && ttl <= 100);
+        assert_eq!(client.increment_or_init(&key, 5, 10, 100).unwrap(), 15);
+        assert_eq!(client.increment(&key, 1).unwrap(), 16);
+
+        // the binary protocol would take u32::MAX as not to create the counter
+        let missing = gen_random_key();
+        assert!(client.increment_or_init(&missing, 1, 0, u32::MAX).is_err());
+        assert_eq!(client.get::<u64>(&missing).unwrap(), None);
+
+        client.set(&key, "not a number", 0).unwrap();
+        assert!(client.increment_or_init(&key, 1, 0, 100).is_err());
+    }
+}
+
+#[test]
+fn test_update() {
+    for url in &[
+        "memcache://localhost:12345",
+        "memcache://localhost:12345?protocol=ascii",
+        "memcache://localhost:12345?protocol=meta",
+    ] {
+        let client = helpers::connect(url).unwrap();
+        let key = gen_random_key();
+
+        let update = client
+            .update(&key, 100, 0, |value: Option<u64>| Some(value.unwrap_or(0) + 1))
+            .unwrap();
+        assert_eq!(
+            update,
+            memcache::Update {
+                value: Some(1),
+                attempts: 1
+            }
+        );
+
+        // another client writing between the read and the write forces a retry
+        let mut interfered = false;
+        let update = client
+            .update(&key, 100, 3, |value: Option<u64>| {
+                if !interfered {
+                    interfered = true;
+                    client.set(&key, 10, 100).unwrap();
+                }
+                Some(value.unwrap() + 1)
+            })
+            .unwrap();
+        assert_eq!(
+            update,
+            memcache::Update {
+                value: Some(11),
+                attempts: 2
+            }
+        );
+        assert_eq!(client.get::<u64>(&key).unwrap(), Some(11));
+
+        let result = client.update(&key, 100, 1, |value: Option<u64>| {
+            client.set(&key, 20, 100).unwrap();
+            value
+        });
+        match result {
+            Err(memcache::MemcacheError::CommandError(memcache::CommandError::KeyExists)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let update = client.update(&key, 100, 0, |_: Option<String>| None).unwrap();
+        assert_eq!(
+            update,
+            memcache::Update {
+                value: None,
+                attempts: 1
+            }
+        );
+        assert_eq!(client.get::<u64>(&key).unwrap(), Some(20));
+    }
+}
+
+#[test]
+fn test_multi_writes() {
+    for protocol in &["binary", "ascii", "meta"] {
+        let pools = (12346..12350)
+            .map(|port| {
+                let url = format!("memcache://localhost:{}?protocol={}", port, protocol);
+                r2d2::Pool::builder()
+                    .max_size(1)
+                    .connection_timeout(time::Duration::from_millis(500))
+                    .build(memcache::ConnectionManager::new(url).unwrap())
+                    .unwrap()
+            })
+            .collect();
+        let client = memcache::Client::with_pools(pools).unwrap();
+        let keys: Vec<String> = (0..50).map(|_| gen_random_key()).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let items: Vec<(&str, &str)> = keys.iter().map(|key| (*key, *key)).collect();
+
+        let outcomes = client.add_multi(&items[..25], 100).unwrap();
+        assert_eq!(outcomes.len(), 25);
+        assert!(outcomes.values().all(|outcome| *outcome.as_ref().unwrap()));
+        let outcomes = client.add_multi(&items, 100).unwrap();
+        assert_eq!(
+            outcomes.values().filter(|outcome| *outcome.as_ref().unwrap()).count(),
+            25
+        );
+        assert_eq!(outcomes[keys[0]].as_ref().unwrap(), &false);
+        assert_eq!(outcomes[keys[49]].as_ref().unwrap(), &true);
+
+        let outcomes = client.set_multi(&items, 100).unwrap();
+        assert!(outcomes.values().all(|outcome| *outcome.as_ref().unwrap()));
+        let values: HashMap<&str, String> = client.gets(&keys).unwrap();
+        assert_eq!(values.len(), 50);
+        assert_eq!(values[keys[7]], keys[7]);
+
+        let missing = gen_random_key();
+        let outcomes = client.touch_multi(&[keys[0], keys[1], &missing], 1000).unwrap();
+        assert_eq!(outcomes[keys[0]].as_ref().unwrap(), &true);
+        assert_eq!(outcomes[missing.as_str()].as_ref().unwrap(), &false);
+
+        let outcomes = client.delete_multi(&keys[..40]).unwrap();
+        assert!(outcomes.values().all(|outcome| *outcome.as_ref().unwrap()));
+        let outcomes = client.delete_multi(&keys[30..]).unwrap();
+        assert_eq!(
+            outcomes.values().filter(|outcome| *outcome.as_ref().unwrap()).count(),
+            10
+        );
+        let values: HashMap<&str, String> = client.gets(&keys).unwrap();
+        assert!(values.is_empty());
+
+        assert!(client.set_multi::<&str, &str>(&[], 100).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn test_get_item() {
+    for protocol in &["binary", "ascii", "meta"] {
+        let client = helpers::connect(&format!("memcache://localhost:12345?protocol={}", protocol)).unwrap();
+        let key = gen_random_key();
+        let missing = gen_random_key();
+        let options = memcache::StoreOptions {
+            expiration: 100,
+            flags: Some(0x100),
+            ..Default::default()
+        };
+        assert!(client.set_with(&key, "value", &options).unwrap());
+
+        let item: memcache::Item<String> = client.get_item(&key).unwrap().unwrap();
+        assert_eq!(item.value, "value");
+        assert_eq!(item.flags, 0x100);
+        assert_eq!(item.size, 5);
+        assert!(client.cas(&key, "other", 100, item.cas.unwrap()).unwrap());
+        if *protocol == "meta" {
+            assert!(item.ttl_remaining.unwrap() > 0 && item.ttl_remaining.unwrap() <= 100);
+            assert!(item.last_access.is_some());
+        } else {
+            assert_eq!(item.ttl_remaining, None);
+            assert_eq!(item.last_access, None);
+        }
+        assert_eq!(client.get_item::<String>(&missing).unwrap(), None);
+
+        let items: HashMap<&String, memcache::Item<String>> = client.get_items(&[&key, &missing]).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[&key].value, "other");
+        assert_eq!(items[&key].size, 5);
+    }
+}
+
+type ComputeError = Box<dyn std::error::Error + Send + Sync>;
+
+#[test]
+fn test_get_or_compute() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    for protocol in &["meta", "binary", "ascii"] {
+        let client = helpers::connect(&format!("memcache://localhost:12345?protocol={}", protocol)).unwrap();
+        let key = gen_random_key();
+        let computed = Arc::new(AtomicUsize::new(0));
+
+        // concurrent callers of a missing key wait for the single one computing it
+        let handles: Vec<JoinHandle<String>> = (0..8)
+            .map(|_| {
+                let (client, key, computed) = (client.clone(), key.clone(), computed.clone());
+                thread::spawn(move || {
+                    client
+                        .get_or_compute(&key, 100, time::Duration::from_secs(5), || {
+                            computed.fetch_add(1, Ordering::SeqCst);
+                            thread::sleep(time::Duration::from_millis(200));
+                            Ok::<_, ComputeError>("computed".to_string())
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "computed");
+        }
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+        assert_eq!(client.get::<String>(&key).unwrap(), Some("computed".into()));
+
+        // a failed computation releases the key for the next caller
+        let missing = gen_random_key();
+        let result: Result<String, ComputeError> =
+            client.get_or_compute(&missing, 100, time::Duration::from_secs(5), || Err("failed".into()));
+        assert_eq!(result.unwrap_err().to_string(), "failed");
+        let start = time::Instant::now();
+        let value: String = client
+            .get_or_compute(&missing, 100, time::Duration::from_secs(5), || {
+                Ok::<_, ComputeError>("retried".to_string())
+            })
+            .unwrap();
+        assert_eq!(value, "retried");
+        assert!(start.elapsed() < time::Duration::from_secs(1));
+
+        // a caller waiting on a slow computation gives up after its wait
+        let slow = gen_random_key();
+        let computing = {
+            let (client, slow) = (client.clone(), slow.clone());
+            thread::spawn(move || {
+                client
+                    .get_or_compute(&slow, 100, time::Duration::from_secs(5), || {
+                        thread::sleep(time::Duration::from_millis(1000));
+                        Ok::<_, ComputeError>("slow".to_string())
+                    })
+                    .unwrap()
+            })
+        };
+        thread::sleep(time::Duration::from_millis(100));
+        let start = time::Instant::now();
+        let result: Result<String, ComputeError> =
+            client.get_or_compute(&slow, 100, time::Duration::from_millis(200), || {
+                Ok("not the winner".to_string())
+            });
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<memcache::MemcacheError>(),
+            Some(memcache::MemcacheError::Timeout)
+        ));
+        assert!(start.elapsed() < time::Duration::from_millis(800));
+        assert_eq!(computing.join().unwrap(), "slow");
+    }
+
+    // a value close to its expiration is recomputed ahead of time by one caller
+    let client = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+    let key = gen_random_key();
+    client.set(&key, "old", 5).unwrap();
+    let value: String = client
+        .get_or_compute(&key, 100, time::Duration::from_secs(5), || {
+            Ok::<_, ComputeError>("new".to_string())
+        })
+        .unwrap();
+    assert_eq!(value, "new");
+    let value: String = client
+        .get_or_compute(&key, 100, time::Duration::from_secs(5), || {
+            Ok::<_, ComputeError>("newer".to_string())
+        })
+        .unwrap();
+    assert_eq!(value, "new");
+}
+
+#[test]
+fn test_lock() {
+    use memcache::lock::Lock;
+
+    for protocol in &["meta", "binary", "ascii"] {
+        let client = helpers::connect(&format!("memcache://localhost:12345?protocol={}", protocol)).unwrap();
+        let key = gen_random_key();
+        let mut lock = Lock::new(&client, &key, 10);
+        let mut other = Lock::new(&client, &key, 10);
+        assert_ne!(lock.token(), other.token());
+
+        assert!(lock.try_acquire().unwrap());
+        let fence = lock.fencing_token().unwrap();
+        assert!(!other.try_acquire().unwrap());
+        assert_eq!(other.fencing_token(), None);
+        let start = time::Instant::now();
+        assert!(!other.acquire(time::Duration::from_millis(100)).unwrap());
+        assert!(start.elapsed() >= time::Duration::from_millis(100));
+
+        // only the owner can extend or release the lock
+        assert!(!other.extend(20).unwrap());
+        assert!(!other.release().unwrap());
+        assert_eq!(client.get::<String>(&key).unwrap().as_deref(), Some(lock.token()));
+        assert!(lock.extend(20).unwrap());
+        assert!(lock.release().unwrap());
+        assert_eq!(client.get::<String>(&key).unwrap(), None);
+        assert!(!lock.release().unwrap());
+
+        assert!(other.acquire(time::Duration::from_millis(100)).unwrap());
+        assert!(other.fencing_token().unwrap() > fence);
+        assert!(other.release().unwrap());
+    }
+
+    // an expired lock can be taken by a waiting owner, and the old owner can no longer release it
+    let client = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+    let key = gen_random_key();
+    let mut lock = Lock::new(&client, &key, 1);
+    let mut other = Lock::new(&client, &key, 10);
+    assert!(lock.try_acquire().unwrap());
+    assert!(other.acquire(time::Duration::from_secs(3)).unwrap());
+    assert!(!lock.release().unwrap());
+    assert!(other.release().unwrap());
+}
+
+#[test]
+fn test_rate_limit() {
+    use memcache::ratelimit::{Decision, FixedWindow, SlidingWindow};
+
+    for protocol in &["meta", "binary", "ascii"] {
+        let client = helpers::connect(&format!("memcache://localhost:12345?protocol={}", protocol)).unwrap();
+        let prefix = gen_random_key();
+
+        let limiter = FixedWindow::new(&client, &prefix, 3, 3600);
+        for expected in (0..3).rev() {
+            match limiter.check("user").unwrap() {
+                Decision::Allowed { remaining, reset_at } => {
+                    assert_eq!(remaining, expected);
+                    assert!(reset_at > time::SystemTime::now());
+                }
+                decision => panic!("unexpected {:?}", decision),
+            }
+        }
+        match limiter.check("user").unwrap() {
+            Decision::Limited { retry_after } => assert!(retry_after <= time::Duration::from_secs(3600)),
+            decision => panic!("unexpected {:?}", decision),
+        }
+        assert!(limiter.check("other").unwrap().is_allowed());
+
+        let limiter = SlidingWindow::new(&client, &prefix, 2, 1);
+        assert!(limiter.check("user").unwrap().is_allowed());
+        assert!(limiter.check("user").unwrap().is_allowed());
+        let retry_after = match limiter.check("user").unwrap() {
+            Decision::Limited { retry_after } => retry_after,
+            decision => panic!("unexpected {:?}", decision),
+        };
+        assert!(retry_after <= time::Duration::from_secs(2));
+        thread::sleep(retry_after + time::Duration::from_millis(10));
+        assert!(limiter.check("user").unwrap().is_allowed());
+    }
+}
+
+#[test]
+fn test_near_cache() {
+    use memcache::near_cache::{NearCache, NearCacheOptions};
+
+    let client = helpers::connect("memcache://localhost:12345").unwrap();
+    let prefix = gen_random_key();
+    let options = NearCacheOptions {
+        ttl: time::Duration::from_millis(300),
+        prefixes: vec![prefix.clone()],
+        ..Default::default()
+    };
+    let cache = NearCache::new(&client, options);
+    let key = format!("{}:foo", prefix);
+
+    cache.set(&key, "bar", 0).unwrap();
+    assert_eq!(cache.get::<String>(&key).unwrap().as_deref(), Some("bar"));
+    // served from memory until it expires, even if changed by another client
+    client.set(&key, "baz", 0).unwrap();
+    assert_eq!(cache.get::<String>(&key).unwrap().as_deref(), Some("bar"));
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+    assert_eq!(stats.bytes, key.len() + 3);
+    thread::sleep(time::Duration::from_millis(400));
+    assert_eq!(cache.get::<String>(&key).unwrap().as_deref(), Some("baz"));
+
+    // writes through the near cache invalidate it right away
+    cache.set(&key, "qux", 0).unwrap();
+    assert_eq!(cache.get::<String>(&key).unwrap().as_deref(), Some("qux"));
+    assert!(cache.delete(&key).unwrap());
+    assert_eq!(cache.get::<String>(&key).unwrap(), None);
+    client.set(&key, "quux", 0).unwrap();
+    assert_eq!(cache.get::<String>(&key).unwrap().as_deref(), Some("quux"));
+    client.set(&key, "corge", 0).unwrap();
+    cache.invalidate(&key);
+    assert_eq!(cache.get::<String>(&key).unwrap().as_deref(), Some("corge"));
+
+    // other keys are always read from the server
+    let other = gen_random_key();
+    client.set(&other, 1, 0).unwrap();
+    assert_eq!(cache.get::<u32>(&other).unwrap(), Some(1));
+    client.set(&other, 2, 0).unwrap();
+    assert_eq!(cache.get::<u32>(&other).unwrap(), Some(2));
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 6));
+
+    cache.clear();
+    assert_eq!(cache.stats().entries, 0);
+}
+
+#[test]
+fn test_namespace() {
+    let pools = (12346..12350)
+        .map(|port| {
+            r2d2::Pool::builder()
+                .max_size(2)
+                .connection_timeout(time::Duration::from_millis(500))
+                .build(memcache::ConnectionManager::new(format!("memcache://localhost:{}", port)).unwrap())
+                .unwrap()
+        })
+        .collect();
+    let client = memcache::Client::with_pools(pools).unwrap();
+    for protocol in &["meta", "binary", "ascii"] {
+        let client = memcache::Client::connect(&[format!("memcache://localhost:12345?protocol={}", protocol)])
+            .unwrap()
+            .with_namespace("svc:")
+            .with_namespace("v2:");
+        assert_eq!(client.namespace(), "svc:v2:");
+        let key = gen_random_key();
+        client.set(&key, "bar", 0).unwrap();
+        assert_eq!(client.get::<String>(&key).unwrap().as_deref(), Some("bar"));
+        let values: HashMap<&String, String> = client.gets(&[&key]).unwrap();
+        assert_eq!(values.get(&key).map(String::as_str), Some("bar"));
+        let items = client.get_items::<String, _>(&[&key]).unwrap();
+        assert_eq!(items[&key].value, "bar");
+        let outcomes = client.delete_multi(&[&key]).unwrap();
+        assert!(outcomes[&key].as_ref().unwrap());
+    }
+
+    let service = client.with_namespace("svc:v2:");
+    let keys: Vec<String> = (0..20).map(|_| gen_random_key()).collect();
+    let items: Vec<(&str, &str)> = keys.iter().map(|key| (key.as_str(), key.as_str())).collect();
+    for outcome in service.set_multi(&items, 0).unwrap().values() {
+        assert!(outcome.as_ref().unwrap());
+    }
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let values: HashMap<&str, String> = service.gets(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in &values {
+        assert_eq!(key, value);
+        // the prefixed key routes to the same server as through the namespace
+        let prefixed = format!("svc:v2:{}", key);
+        assert_eq!(client.get::<String>(&prefixed).unwrap().as_ref(), Some(value));
+        assert_eq!(client.get::<String>(key).unwrap(), None);
+    }
+
+    // the prefix counts in the length limit
+    let long = "a".repeat(245);
+    client.set(&long, "bar", 0).unwrap();
+    match service.set(&long, "bar", 0) {
+        Err(memcache::MemcacheError::ClientError(memcache::ClientError::KeyTooLong)) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+    assert!(client.delete(&long).unwrap());
+}
+
+#[test]
+fn test_generational_namespace() {
+    use memcache::namespace::Namespace;
+
+    for protocol in &["meta", "binary", "ascii"] {
+        let client = helpers::connect(&format!("memcache://localhost:12345?protocol={}", protocol)).unwrap();
+        let name = gen_random_key();
+        let namespace = Namespace::new(&client, &name, time::Duration::from_secs(60));
+        let other = Namespace::new(&client, &name, time::Duration::from_millis(200));
+
+        let generation = namespace.generation().unwrap();
+        assert_eq!(other.generation().unwrap(), generation);
+        assert_eq!(namespace.key("foo").unwrap(), format!("{}:{}:foo", name, generation));
+        namespace.set("foo", "bar", 0).unwrap();
+        assert_eq!(other.get::<String>("foo").unwrap().as_deref(), Some("bar"));
+
+        // the old keys are orphaned, and other processes see it once their generation expires
+        assert_eq!(namespace.invalidate().unwrap(), generation + 1);
+        assert_eq!(namespace.get::<String>("foo").unwrap(), None);
+        assert_eq!(other.get::<String>("foo").unwrap().as_deref(), Some("bar"));
+        thread::sleep(time::Duration::from_millis(300));
+        assert_eq!(other.get::<String>("foo").unwrap(), None);
+        namespace.set("foo", "baz", 0).unwrap();
+        assert_eq!(other.get::<String>("foo").unwrap().as_deref(), Some("baz"));
+        assert!(other.delete("foo").unwrap());
+
+        // an evicted counter starts over past the previous generations
+        thread::sleep(time::Duration::from_millis(300));
+        client.delete(format!("{}:generation", name)).unwrap();
+        assert!(other.generation().unwrap() > generation + 1);
+    }
+}
+
+#[test]
+fn test_binary_keys() {
+    let binary = helpers::connect("memcache://localhost:12345").unwrap();
+    let meta = helpers::connect("memcache://localhost:12345?protocol=meta").unwrap();
+    let ascii = helpers::connect("memcache://localhost:12345?protocol=ascii").unwrap();
+    let key = [gen_random_key().as_bytes(), b" with\r\nspaces\xff\x00"].concat();
+
+    // binary sends the key as is, and meta base64 encodes it, which names the same item
+    binary.set(&key, "foo", 0).unwrap();
+    assert_eq!(meta.get::<String>(&key).unwrap().as_deref(), Some("foo"));
+    meta.set(&key, "bar", 0).unwrap();
+    assert_eq!(binary.get::<String>(&key).unwrap().as_deref(), Some("bar"));
+    assert_eq!(meta.increment_or_init(&key[..4], 1, 10, 0).unwrap(), 10);
+    assert_eq!(binary.increment(&key[..4], 1).unwrap(), 11);
+    assert!(binary.delete(&key[..4]).unwrap());
+
+    // ascii can't escape the key, and the rejected command leaves the connection usable
+    for key in [&key[..], b"foo bar", b"foo\n", b"\xff"] {
+        match ascii.get::<String>(key) {
+            Err(memcache::MemcacheError::ClientError(memcache::ClientError::InvalidKey)) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+    match ascii.set(&key, "bar", 0) {
+        Err(memcache::MemcacheError::ClientError(memcache::ClientError::InvalidKey)) => {}
+        result => panic!("unexpected {:?}", result),
+    }
+    assert!(ascii.gets::<String, _>(&["foo bar"]).is_err());
+    assert!(ascii.set_multi(&[("foo bar", "baz")], 0).is_err());
+    assert_eq!(ascii.get::<String>(&key[..10]).unwrap(), None);
+
+    // append and prepend fail on a missing key
+    assert!(binary.append(&key[..4], "baz").is_err());
+    assert!(binary.prepend(&key[..4], "baz").is_err());
+    binary.prepend(&key, "foo").unwrap();
+    assert_eq!(meta.get::<String>(&key).unwrap().as_deref(), Some("foobar"));
+
+    // the multi-key commands and pipelines take byte keys too, and key their results by them
+    let other = [&key[..], b"\x01"].concat();
+    for client in [&binary, &meta] {
+        let outcomes = client.set_multi(&[(&key, "foo"), (&other, "bar")], 0).unwrap();
+        assert!(outcomes[&other].as_ref().unwrap());
+        let values: HashMap<&Vec<u8>, String> = client.gets(&[&key, &other]).unwrap();
+        assert_eq!(values[&key], "foo");
+        assert_eq!(values[&other], "bar");
+        let items = client.get_items::<String, _>(&[&key[..], &key[..4]]).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[&key[..]].value, "foo");
+        let values: HashMap<Vec<u8>, String> = client.get_and_touch_multi(std::slice::from_ref(&other), 100).unwrap();
+        assert_eq!(values[&other], "bar");
+        let outcomes = client.delete_multi(&[&other]).unwrap();
+        assert!(outcomes[&other].as_ref().unwrap());
+    }
+    assert!(ascii.gets::<String, _>(&[&key]).is_err());
+
+    let mut connection = binary.get_connection(&key).unwrap();
+    let replies = connection.pipeline().get(&key).delete(&key[..4]).execute().unwrap();
+    assert_eq!(replies[1].as_ref().unwrap(), &memcache::Reply::Deleted(false));
+    assert_eq!(
+        replies
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap()
+            .value::<String>()
+            .unwrap()
+            .as_deref(),
+        Some("foo")
+    );
+    let failures = connection.quiet_batch().add(&key, "bar", 0).execute().unwrap();
+    assert_eq!(failures[0].0, key);
+
+    assert!(meta.delete(&key).unwrap());
+    assert_eq!(binary.get::<String>(&key).unwrap(), None);
+}
+
+#[test]
+fn test_key_policy() {
+    use memcache::KeyPolicy;
+    use std::sync::Mutex;
+
+    static HASHED: Mutex<Vec<(Vec<u8>, String)>> = Mutex::new(Vec::new());
+    let policy = KeyPolicy::Hash {
+        prefix: "h:".to_string(),
+        log: Some(|key, hashed| HASHED.lock().unwrap().push((key.to_vec(), hashed.to_string()))),
+    };
+    let long = format!("https://example.com/{}?q={}", gen_random_key(), "a".repeat(300));
+    let spaced = format!("{} with spaces", gen_random_key());
+
+    for protocol in &["meta", "binary", "ascii"] {
+        let strict = helpers::connect(&format!("memcache://localhost:12345?protocol={}", protocol)).unwrap();
+        match strict.set(&long, "bar", 0) {
+            Err(memcache::MemcacheError::ClientError(memcache::ClientError::KeyTooLong)) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+
+        let client = strict.with_namespace("policy:").with_key_policy(policy.clone());
+        client.set(&long, "long", 0).unwrap();
+        client.set(&spaced, "spaced", 0).unwrap();
+        assert_eq!(client.get::<String>(&long).unwrap().as_deref(), Some("long"));
+        // the results of multi-key commands are keyed by the original keys
+        let values: HashMap<&str, String> = client.gets(&[&long, &spaced, "missing"]).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[long.as_str()], "long");
+        assert_eq!(values[spaced.as_str()], "spaced");
+        let outcomes = client.delete_multi(&[&long, &spaced]).unwrap();
+        assert!(outcomes[&long].as_ref().unwrap());
+        assert!(outcomes[&spaced].as_ref().unwrap());
+    }
+
+    let hashed = HASHED.lock().unwrap();
+    assert!(hashed.iter().any(|(key, _)| key == long.as_bytes()));
+    let (_, digest) = hashed.iter().find(|(key, _)| key == spaced.as_bytes()).unwrap();
+    assert!(digest.starts_with("policy:h:"));
+    assert_eq!(digest.len(), "policy:h:".len() + 64);
+}
+
+#[test]
+fn test_quiet_batch() {
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .connection_timeout(time::Duration::from_millis(500))
+        .build(memcache::ConnectionManager::new("memcache://localhost:12345").unwrap())
+        .unwrap();
+    let mut connection = pool.get().unwrap();
+    let key = gen_random_key();
+    let counter = gen_random_key();
+    let missing = gen_random_key();
+
+    let failures = connection
+        .quiet_batch()
+        .set(&key, "bar", 0)
+        .add(&key, "baz", 0)
+        .append(&key, "!")
+        .replace(&missing, "baz", 0)
+        .set(&counter, 10u64, 0)
+        .increment(&counter, 5)
+        .decrement(&key, 1)
+        .delete(&missing)
+        .execute()
+        .unwrap();
+    let keys: Vec<&[u8]> = failures.iter().map(|(key, _)| key.as_slice()).collect();
+    assert_eq!(
+        keys,
+        vec![key.as_bytes(), missing.as_bytes(), key.as_bytes(), missing.as_bytes()]
+    );
+    assert!(matches!(
+        failures[0].1,
+        memcache::MemcacheError::CommandError(memcache::CommandError::KeyExists)
+    ));
+    assert!(matches!(
+        failures[3].1,
+        memcache::MemcacheError::CommandError(memcache::CommandError::KeyNotFound)
+    ));
+
+    assert!(connection.quiet_batch().execute().unwrap().is_empty());
+    drop(connection);
+
+    let client = memcache::Client::with_pool(pool);
+    let value: Option<String> = client.get(&key).unwrap();
+    assert_eq!(value, Some("bar!".into()));
+    let value: Option<u64> = client.get(&counter).unwrap();
+    assert_eq!(value, Some(15));
+
+    let client = helpers::connect("memcache://localhost:12345?protocol=ascii").unwrap();
+    let mut connection = client.get_connection(&key).unwrap();
+    assert!(connection.quiet_batch().delete(&key).execute().is_err());
+}